@@ -1,24 +1,48 @@
-use super::video::Resume;
-use super::{file_management, image_resizer, Video};
+use super::captions::{CaptionMode, FrameCaption};
+use super::contact_sheet;
+use super::custom_fields::{self, CustomField};
+use super::image_resizer::{self, ImageProfile};
+use super::language::Language;
+use super::prompts::{PromptSet, PromptStage};
+use super::sampling::SamplingStrategy;
+use super::video::{Resume, Translation};
+use super::{file_management, frames, Video};
 use base64::prelude::*;
 use chrono::{DateTime, Utc};
+use ollama_rs::error::OllamaError;
 use ollama_rs::generation::completion::request::GenerationRequest;
 use ollama_rs::generation::images::Image;
+use ollama_rs::generation::parameters::{FormatType, JsonStructure};
 use ollama_rs::models::ModelOptions;
 use ollama_rs::Ollama;
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
 use regex::Regex;
 use std::collections::HashSet;
+use std::fmt;
 use std::io::ErrorKind;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
-use std::{fmt, fs};
+
+/// The maximum number of words of the generated titles.
+const MAX_TITLE_WORDS: usize = 8;
+
+/// The maximum number of generated keywords.
+const MAX_KEYWORDS: usize = 5;
+
+/// The number of times a model is asked to repair a resume that doesn't follow the rules.
+const RESUME_REPAIRS: usize = 2;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum VideoDataError {
     FFMpegNotFoundError(String),
     FrameExtractionError(String),
     FFProbeNotFoundError(String),
     MetadataExtractionError(String),
+    AudioExtractionError(String),
+    ModelServerError(String),
 }
 
 impl std::error::Error for VideoDataError {}
@@ -35,53 +59,455 @@ impl fmt::Display for VideoDataError {
             VideoDataError::MetadataExtractionError(ref cause) => {
                 write!(f, "Error while extracting metadata for: {}", cause)
             }
+            VideoDataError::AudioExtractionError(ref cause) => {
+                write!(f, "Error while extracting the audio for: {}", cause)
+            }
+            VideoDataError::ModelServerError(ref cause) => {
+                write!(f, "The model server can't be reached: {}", cause)
+            }
         }
     }
 }
 
-/// Extract frames for a video.
+/// Extract frames for a video as PNG files in the temp folder, according to a sampling strategy. When the interval or scene detection strategies give fewer frames than `min_frames`, a fixed count of `min_frames` frames is extracted instead.
 ///
 /// ### Parameters
 /// - `temp_folder`: The path of the temp folder to save the thumbnails in.    
 /// - `video`: The video that will have thumbnails extracted.
+/// - `sampling`: The sampling strategy.
+/// - `min_frames`: The minimum number of frames for the interval and scene detection strategies.
+/// - `max_frames`: The maximum number of frames for the interval and scene detection strategies.
 ///   
 /// ### Returns
-/// A Result containing an array of paths to the thumbnails.
+/// A Result containing an array of paths to the thumbnails, the time of each thumbnail in the video in seconds (empty if FFmpeg didn't give them), and the sampling strategy that was actually used.
 ///
 /// ### Errors
 /// Returns an error if FFmpeg can't be run (e.g. not in the path).
 pub(crate) fn extract_frames_for_video(
     temp_folder: &str,
     video: &Video,
-) -> anyhow::Result<Vec<String>> {
+    sampling: &SamplingStrategy,
+    min_frames: u32,
+    max_frames: u32,
+) -> anyhow::Result<(Vec<String>, Vec<f32>, SamplingStrategy)> {
+    file_management::remove_files(&file_management::list_matching_files(
+        temp_folder,
+        video.id.as_str(),
+    ));
+    let extract = |filter: &str, max_frames: Option<u32>| {
+        let timestamps = run_ffmpeg_extraction(temp_folder, video, filter, max_frames)?;
+        let thumbnails = file_management::list_matching_files(temp_folder, video.id.as_str());
+        let timestamps = match_timestamps(timestamps, thumbnails.len(), video);
+        Ok((thumbnails, timestamps))
+    };
+    let discard = |thumbnails: Vec<String>| file_management::remove_files(&thumbnails);
+    sample_frames(video, sampling, min_frames, max_frames, &extract, &discard)
+}
+
+/// Extract frames for a video in memory, as JPEG images already shrunk for the models, according to a sampling strategy. When the interval or scene detection strategies give fewer frames than `min_frames`, a fixed count of `min_frames` frames is extracted instead.
+///
+/// ### Parameters
+/// - `video`: The video that will have frames extracted.
+/// - `sampling`: The sampling strategy.
+/// - `min_frames`: The minimum number of frames for the interval and scene detection strategies.
+/// - `max_frames`: The maximum number of frames for the interval and scene detection strategies.
+/// - `max_edge`: The maximum width and height of the frames, the smaller frames keeping their size.
+///   
+/// ### Returns
+/// A Result containing the JPEG images, the time of each image in the video in seconds (empty if FFmpeg didn't give them), and the sampling strategy that was actually used.
+///
+/// ### Errors
+/// Returns an error if FFmpeg can't be run (e.g. not in the path).
+pub(crate) fn extract_frames_in_memory(
+    video: &Video,
+    sampling: &SamplingStrategy,
+    min_frames: u32,
+    max_frames: u32,
+    max_edge: u32,
+) -> anyhow::Result<(Vec<Vec<u8>>, Vec<f32>, SamplingStrategy)> {
+    let extract = |filter: &str, max_frames: Option<u32>| {
+        let (frames, timestamps) = run_ffmpeg_pipe_extraction(video, filter, max_frames, max_edge)?;
+        let timestamps = match_timestamps(timestamps, frames.len(), video);
+        Ok((frames, timestamps))
+    };
+    sample_frames(video, sampling, min_frames, max_frames, &extract, &drop)
+}
+
+/// Samples the frames of a video according to a sampling strategy, whatever the frames are stored in. When the interval or scene detection strategies give fewer frames than `min_frames`, a fixed count of `min_frames` frames is extracted instead.
+///
+/// ### Parameters
+/// - `video`: The video that will have frames extracted.
+/// - `sampling`: The sampling strategy.
+/// - `min_frames`: The minimum number of frames for the interval and scene detection strategies.
+/// - `max_frames`: The maximum number of frames for the interval and scene detection strategies.
+/// - `extract`: Extracts the frames selected by an FFmpeg video filter, up to a maximum number, with their time.
+/// - `discard`: Gets rid of the frames that are not kept.
+///   
+/// ### Returns
+/// A Result containing the frames, the time of each frame in the video in seconds (empty if FFmpeg didn't give them), and the sampling strategy that was actually used.
+///
+/// ### Errors
+/// Returns an error if the frames can't be extracted.
+fn sample_frames<T, E, D>(
+    video: &Video,
+    sampling: &SamplingStrategy,
+    min_frames: u32,
+    max_frames: u32,
+    extract: &E,
+    discard: &D,
+) -> anyhow::Result<(Vec<T>, Vec<f32>, SamplingStrategy)>
+where
+    E: Fn(&str, Option<u32>) -> anyhow::Result<(Vec<T>, Vec<f32>)>,
+    D: Fn(Vec<T>),
+{
+    let min_frames = min_frames.max(1);
+    let max_frames = max_frames.max(min_frames);
+    let (frames, timestamps) = match sampling {
+        SamplingStrategy::FixedCount { count } => {
+            let count = (*count).max(1);
+            let interval = get_capture_interval(video.duration, count);
+            let (frames, timestamps) = extract(&format!("fps=1/{}", interval), Some(count))?;
+            return Ok((frames, timestamps, sampling.clone()));
+        }
+        SamplingStrategy::FixedInterval { interval } => {
+            extract(&format!("fps=1/{}", interval), Some(max_frames))?
+        }
+        SamplingStrategy::SceneDetection { threshold } => {
+            let (frames, timestamps) = extract(&format!("select='gt(scene,{})'", threshold), None)?;
+            if timestamps.is_empty() {
+                let (kept, dropped) = select_evenly(frames, max_frames as usize);
+                discard(dropped);
+                (kept, timestamps)
+            } else {
+                let frames: Vec<(T, f32)> = frames.into_iter().zip(timestamps).collect();
+                let (kept, dropped) = select_evenly(frames, max_frames as usize);
+                discard(dropped.into_iter().map(|(frame, _)| frame).collect());
+                kept.into_iter().unzip()
+            }
+        }
+    };
+    if frames.len() >= min_frames as usize {
+        Ok((frames, timestamps, sampling.clone()))
+    } else {
+        log::debug!(
+            "Only {} frames extracted with {} for {}, falling back to {} frames",
+            frames.len(),
+            sampling,
+            video.path,
+            min_frames
+        );
+        discard(frames);
+        sample_frames(
+            video,
+            &SamplingStrategy::FixedCount { count: min_frames },
+            min_frames,
+            max_frames,
+            extract,
+            discard,
+        )
+    }
+}
+
+/// Creates the FFmpeg command extracting frames of a video. The frames go through the `showinfo` filter, which logs their time in the video.
+///
+/// ### Parameters
+/// - `video`: The video that will have thumbnails extracted.
+/// - `filter`: The FFmpeg video filter selecting the frames.
+/// - `max_frames`: The maximum number of frames to extract, None for no limit.
+///
+/// ### Returns
+/// The FFmpeg command, without its output.
+fn make_ffmpeg_extraction_command(video: &Video, filter: &str, max_frames: Option<u32>) -> Command {
+    let mut ffmpeg_command = Command::new("ffmpeg");
+    ffmpeg_command
+        .arg("-i")
+        .arg(video.path.as_str())
+        .arg("-vf")
+        .arg(format!("{},showinfo", filter))
+        .arg("-fps_mode")
+        .arg("vfr");
+    if let Some(max_frames) = max_frames {
+        ffmpeg_command.arg("-frames:v").arg(max_frames.to_string());
+    }
+    ffmpeg_command
+}
+
+/// Runs an FFmpeg command extracting frames of a video.
+///
+/// ### Parameters
+/// - `ffmpeg_command`: The FFmpeg command.
+/// - `video`: The video that will have thumbnails extracted.
+///
+/// ### Returns
+/// A Result containing the standard output of FFmpeg, and the time in seconds of the frames logged by FFmpeg, in the order they were extracted.
+///
+/// ### Errors
+/// Returns an error if FFmpeg can't be run (e.g. not in the path) or fails.
+fn run_ffmpeg_extraction_command(
+    ffmpeg_command: &mut Command,
+    video: &Video,
+) -> anyhow::Result<(Vec<u8>, Vec<f32>)> {
+    match ffmpeg_command.stderr(Stdio::piped()).output() {
+        Ok(output) if output.status.success() => {
+            let timestamps = parse_frame_timestamps(&String::from_utf8_lossy(&output.stderr));
+            Ok((output.stdout, timestamps))
+        }
+        Ok(_) => {
+            let error_message = format!("FFmpeg failed for file {}", video.path);
+            Err(VideoDataError::FrameExtractionError(error_message).into())
+        }
+        Err(error) => {
+            if error.kind() == ErrorKind::NotFound {
+                let error_message = "FFMpeg can't be found, we're stopping here. Please install FFMpeg and FFProbe and make sure they're in the path.".to_string();
+                Err(VideoDataError::FFMpegNotFoundError(error_message).into())
+            } else {
+                let error_message = format!("Couldn't run FFmpeg for file {}", video.path);
+                Err(VideoDataError::FrameExtractionError(error_message).into())
+            }
+        }
+    }
+}
+
+/// Runs FFmpeg to extract frames of a video as PNG files in the temp folder.
+///
+/// ### Parameters
+/// - `temp_folder`: The path of the temp folder to save the thumbnails in.    
+/// - `video`: The video that will have thumbnails extracted.
+/// - `filter`: The FFmpeg video filter selecting the frames.
+/// - `max_frames`: The maximum number of frames to extract, None for no limit.
+///
+/// ### Returns
+/// A Result containing the time in seconds of the frames logged by FFmpeg, in the order they were extracted.
+///
+/// ### Errors
+/// Returns an error if FFmpeg can't be run (e.g. not in the path) or fails.
+fn run_ffmpeg_extraction(
+    temp_folder: &str,
+    video: &Video,
+    filter: &str,
+    max_frames: Option<u32>,
+) -> anyhow::Result<Vec<f32>> {
     let mut path: PathBuf = PathBuf::from(temp_folder);
     let mut filename_template = video.id.clone();
     filename_template.push_str("_%04d.png");
     path = path.join(filename_template);
-    let mut binding = Command::new("ffmpeg");
-    let mut fps = String::from("fps=1/");
-    fps.push_str(format!("{}", video.gap).as_str());
-    let ffmpeg_command = binding
-        .arg("-i")
-        .arg(video.path.as_str())
-        .arg("-vf")
-        .arg(fps)
+    let mut ffmpeg_command = make_ffmpeg_extraction_command(video, filter, max_frames);
+    ffmpeg_command
         .arg(path.to_str().unwrap())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null());
-    let status = ffmpeg_command.status();
-    if status.is_err() {
-        if status.err().unwrap().kind() == ErrorKind::NotFound {
-            let error_message = "FFMpeg can't be found, we're stopping here. Please install FFMpeg and FFProbe and make sure they're in the path.".to_string();
-            return Err(VideoDataError::FFMpegNotFoundError(error_message).into());
+        .stdout(Stdio::null());
+    let (_, timestamps) = run_ffmpeg_extraction_command(&mut ffmpeg_command, video)?;
+    Ok(timestamps)
+}
+
+/// Runs FFmpeg to extract frames of a video as JPEG images piped on its standard output, shrunk to fit the size expected by the models.
+///
+/// ### Parameters
+/// - `video`: The video that will have frames extracted.
+/// - `filter`: The FFmpeg video filter selecting the frames.
+/// - `max_frames`: The maximum number of frames to extract, None for no limit.
+/// - `max_edge`: The maximum width and height of the frames, the smaller frames keeping their size.
+///
+/// ### Returns
+/// A Result containing the JPEG images, and the time in seconds of the frames logged by FFmpeg, in the order they were extracted.
+///
+/// ### Errors
+/// Returns an error if FFmpeg can't be run (e.g. not in the path) or fails.
+fn run_ffmpeg_pipe_extraction(
+    video: &Video,
+    filter: &str,
+    max_frames: Option<u32>,
+    max_edge: u32,
+) -> anyhow::Result<(Vec<Vec<u8>>, Vec<f32>)> {
+    let filter = format!(
+        "{},scale='min(iw,{})':'min(ih,{})':force_original_aspect_ratio=decrease",
+        filter, max_edge, max_edge
+    );
+    let mut ffmpeg_command = make_ffmpeg_extraction_command(video, &filter, max_frames);
+    ffmpeg_command
+        .arg("-f")
+        .arg("image2pipe")
+        .arg("-c:v")
+        .arg("mjpeg")
+        .arg("-q:v")
+        .arg("2")
+        .arg("-")
+        .stdout(Stdio::piped());
+    let (stream, timestamps) = run_ffmpeg_extraction_command(&mut ffmpeg_command, video)?;
+    Ok((frames::split_jpeg_stream(&stream), timestamps))
+}
+
+/// Decodes the frames of a video: the frames held in memory, or else the thumbnails, which are left untouched.
+///
+/// ### Parameters
+/// - `video`: The video.
+///
+/// ### Returns
+/// A Result containing the decoded frames, in their order.
+///
+/// ### Errors
+/// Returns an error naming the first frame that can't be read or decoded.
+fn decode_frames(video: &Video) -> anyhow::Result<Vec<image::DynamicImage>> {
+    let decoded = |image: image::ImageResult<image::DynamicImage>, frame: &str| {
+        image.map_err(|error| {
+            anyhow::anyhow!(
+                "Couldn't read the frame {} of file: {}, {}",
+                frame,
+                video.path,
+                error
+            )
+        })
+    };
+    if !video.frames.is_empty() {
+        return video
+            .frames
+            .par_iter()
+            .enumerate()
+            .map(|(index, frame)| decoded(image::load_from_memory(frame), &(index + 1).to_string()))
+            .collect();
+    }
+    video
+        .thumbnails
+        .par_iter()
+        .map(|thumbnail| decoded(image::open(thumbnail), thumbnail))
+        .collect()
+}
+
+/// Encodes an image prepared for a model.
+///
+/// ### Parameters
+/// - `data`: The JPEG image.
+///
+/// ### Returns
+/// The image, encoded for the model.
+fn encode_image(data: &[u8]) -> Image {
+    Image::from_base64(BASE64_STANDARD.encode(data).as_str())
+}
+
+/// Reads the frames of a video to send them to a model, each frame being prepared according to the image profile of the model.
+///
+/// ### Parameters
+/// - `video`: The video.
+/// - `profile`: The image profile of the model.
+///
+/// ### Returns
+/// A Result containing the images, encoded for the model.
+///
+/// ### Errors
+/// Returns an error naming the first frame that can't be read, decoded or encoded.
+fn read_frames(video: &Video, profile: &ImageProfile) -> anyhow::Result<Vec<Image>> {
+    decode_frames(video)?
+        .into_par_iter()
+        .map(|image| image_resizer::prepare_image(image, profile).map(|data| encode_image(&data)))
+        .collect()
+}
+
+/// Tiles the frames of a video in a contact sheet, each tile being labelled with the time of its frame, and prepares it according to the image profile of the model.
+///
+/// ### Parameters
+/// - `video`: The video.
+/// - `profile`: The image profile of the model.
+///
+/// ### Returns
+/// A Result containing the contact sheet as a JPEG image.
+///
+/// ### Errors
+/// Returns an error naming the first frame that can't be read or decoded, or if the contact sheet can't be encoded.
+pub(crate) fn make_contact_sheet(video: &Video, profile: &ImageProfile) -> anyhow::Result<Vec<u8>> {
+    let frames = decode_frames(video)?;
+    let sheet = contact_sheet::compose(
+        &frames,
+        &video.timestamps,
+        profile.max_edge,
+        profile.upscale,
+    );
+    image_resizer::prepare_image(sheet, profile)
+}
+
+/// Reads the images of a video to send them to a model: its contact sheet, made now if it wasn't made with the frames, or else its frames.
+///
+/// ### Parameters
+/// - `video`: The video.
+/// - `profile`: The image profile of the model.
+/// - `sheet`: Flag to send the frames as a single contact sheet.
+///
+/// ### Returns
+/// A Result containing the images, encoded for the model.
+///
+/// ### Errors
+/// Returns an error naming the first frame that can't be read, decoded or encoded.
+fn read_images(video: &Video, profile: &ImageProfile, sheet: bool) -> anyhow::Result<Vec<Image>> {
+    if !sheet {
+        return read_frames(video, profile);
+    }
+    if video.contact_sheet.is_empty() {
+        Ok(vec![encode_image(&make_contact_sheet(video, profile)?)])
+    } else {
+        Ok(vec![encode_image(&video.contact_sheet)])
+    }
+}
+
+/// Parses the time of the frames from the log of the FFmpeg `showinfo` filter.
+///
+/// ### Parameters
+/// - `log`: The log written by FFmpeg on its error output.
+///
+/// ### Returns
+/// The time of the frames in seconds, in the order they were logged.
+fn parse_frame_timestamps(log: &str) -> Vec<f32> {
+    let re = Regex::new(r"Parsed_showinfo.*\bpts_time:\s*(-?[0-9.]+)").unwrap();
+    log.lines()
+        .filter_map(|line| re.captures(line))
+        .filter_map(|captures| captures[1].parse::<f32>().ok())
+        .collect()
+}
+
+/// Matches the time of the frames logged by FFmpeg with the extracted thumbnails. FFmpeg can log a frame more than it writes when the number of frames is limited.
+///
+/// ### Parameters
+/// - `timestamps`: The time of the frames logged by FFmpeg.
+/// - `count`: The number of extracted thumbnails.
+/// - `video`: The video, for the log messages.
+///
+/// ### Returns
+/// The time of each thumbnail, empty if there are fewer times than thumbnails.
+fn match_timestamps(mut timestamps: Vec<f32>, count: usize, video: &Video) -> Vec<f32> {
+    if timestamps.len() < count {
+        log::debug!(
+            "Only {} frame times logged by FFmpeg for {} frames of {}, the frames won't have a time",
+            timestamps.len(),
+            count,
+            video.path
+        );
+        return Vec::new();
+    }
+    timestamps.truncate(count);
+    timestamps
+}
+
+/// Selects evenly spread items from a list, so that it doesn't exceed a maximum length.
+///
+/// ### Parameters
+/// - `items`: The list of items.
+/// - `max`: The maximum number of items to keep.
+///
+/// ### Returns
+/// A tuple with the kept items and the dropped ones.
+fn select_evenly<T>(items: Vec<T>, max: usize) -> (Vec<T>, Vec<T>) {
+    if items.len() <= max {
+        return (items, Vec::new());
+    }
+    let len = items.len();
+    let kept_indexes: HashSet<usize> = (0..max).map(|i| i * len / max).collect();
+    let mut kept = Vec::new();
+    let mut dropped = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        if kept_indexes.contains(&index) {
+            kept.push(item);
         } else {
-            let error_message = format!("Couldn't run FFmpeg for file {}", video.path);
-            return Err(VideoDataError::FrameExtractionError(error_message).into());
+            dropped.push(item);
         }
     }
-
-    let thumbnails = file_management::list_matching_files(temp_folder, video.id.as_str());
-    Ok(thumbnails)
+    (kept, dropped)
 }
 
 /// Runs a text model to create a resume of the video file after it's been analysed by the computer vision model.
@@ -89,6 +515,9 @@ pub(crate) fn extract_frames_for_video(
 /// ### Parameters
 /// - `ollama`: The model prompter for the text model.    
 /// - `model`: The name of the model.   
+/// - `prompts`: The prompt templates.
+/// - `language`: The language of the answer.
+/// - `fields`: The custom fields of the resume.
 /// - `video`: The video to analyse.   
 ///
 /// ### Returns
@@ -99,27 +528,96 @@ pub(crate) fn extract_frames_for_video(
 pub(crate) async fn run_resume_model_for_video(
     ollama: &Ollama,
     model: &str,
+    prompts: &PromptSet,
+    language: &Language,
+    fields: &[CustomField],
     video: &Video,
 ) -> anyhow::Result<Resume> {
-    let prompt = "You are a helpful assistant and expert in concise storytelling. The following text tells the story of a video. Please resume that story in 20 words focusing on the person and their action and less on their environment, from that resume please generate a title of maximum 8 words, and make a list of up to 5 keywords that resumes the story, the keywords will include the person on the video if any (e.g. woman, child...). Please format the answer in a json format: {\"title\": <<title>>, \"description\": <<description>>, \"keywords\": <<array of keywords>>}, with no other text at all, only the json result. The story is:";
     if video.story.is_empty() {
         Err(anyhow::anyhow!("No story to resume for : {}", video.path))
     } else {
-        let mut resume_prompt = prompt.to_string();
-        resume_prompt += video.story.as_str();
-        let options = ModelOptions::default().temperature(0.5);
+        let prompt = prompts.render(
+            PromptStage::Resume,
+            video,
+            language,
+            fields,
+            CaptionMode::Off,
+        );
+        generate_resume(
+            ollama,
+            model,
+            prompt,
+            Vec::new(),
+            fields,
+            "resume",
+            &video.path,
+        )
+        .await
+    }
+}
+
+/// Runs a computer vision model to caption each thumbnail of the video file, one at a time.
+///
+/// ### Parameters
+/// - `ollama`: The model prompter for the computer vision model.    
+/// - `model`: The name of the model.   
+/// - `prompts`: The prompt templates.
+/// - `profile`: The image profile of the model.
+/// - `video`: The video to analyse.
+///   
+/// ### Returns
+/// A Result containing the caption of each thumbnail with its time in the video.
+///
+/// ### Errors
+/// Returns an error if the model can't be reached, doesn't exist, or doesn't return a result for a thumbnail.
+pub(crate) async fn run_caption_model_for_video(
+    ollama: &Ollama,
+    model: &str,
+    prompts: &PromptSet,
+    profile: &ImageProfile,
+    video: &Video,
+) -> anyhow::Result<Vec<FrameCaption>> {
+    // The captions are only read by the models, so they are in English whatever the language of the resume
+    let prompt = prompts.render(
+        PromptStage::Caption,
+        video,
+        &Language::default(),
+        &[],
+        CaptionMode::Off,
+    );
+
+    let images = read_frames(video, profile)?;
+    let mut timeline = Vec::new();
+    for (index, image) in images.into_iter().enumerate() {
+        let options = ModelOptions::default().temperature(0.2);
         let res = ollama
-            .generate(GenerationRequest::new(model.to_string(), resume_prompt).options(options))
+            .generate(
+                GenerationRequest::new(model.to_string(), prompt.to_string())
+                    .options(options)
+                    .images(vec![image]),
+            )
             .await;
-        if let Ok(res) = res {
-            Ok(serde_json::from_str(res.response.as_str())?)
-        } else {
-            Err(anyhow::anyhow!(
-                "Couldn't generate answer from resume model for file: {}",
-                video.path
-            ))
+        match res {
+            Ok(res) => {
+                log::debug!("Caption of frame {}: {}", index + 1, res.response);
+                timeline.push(FrameCaption {
+                    timestamp: video.timestamps.get(index).copied(),
+                    caption: res.response.trim().to_string(),
+                });
+            }
+            Err(OllamaError::ReqwestError(error)) => {
+                return Err(VideoDataError::ModelServerError(error.to_string()).into());
+            }
+            Err(err) => {
+                log::debug!("Error in run_caption_model_for_video: {}", err);
+                return Err(anyhow::anyhow!(
+                    "Couldn't caption the frames for file: {}",
+                    video.path
+                ));
+            }
         }
     }
+    Ok(timeline)
 }
 
 /// Runs a computer vision model to create a story of the video file based on thumbnails of this video.
@@ -127,6 +625,11 @@ pub(crate) async fn run_resume_model_for_video(
 /// ### Parameters
 /// - `ollama`: The model prompter for the computer vision model.    
 /// - `model`: The name of the model.   
+/// - `prompts`: The prompt templates.
+/// - `language`: The language of the answer.
+/// - `captions`: The caption mode, the captions of the frames being given with the thumbnails in the story mode.
+/// - `profile`: The image profile of the model.
+/// - `sheet`: Flag to send the frames as a single contact sheet.
 /// - `video`: The video to analyse.
 ///   
 /// ### Returns
@@ -134,32 +637,24 @@ pub(crate) async fn run_resume_model_for_video(
 ///
 /// ### Errors
 /// Returns an error if the model can't be reached, doesn't exist, or doesn't return a result.
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn run_computer_vision_model_for_video(
     ollama: &Ollama,
     model: &str,
+    prompts: &PromptSet,
+    language: &Language,
+    captions: CaptionMode,
+    profile: &ImageProfile,
+    sheet: bool,
     video: &Video,
 ) -> anyhow::Result<String> {
-    let prompt = "The following images are part of a video, they tell a story. Please describe that story focusing on the persons and their action and less on their environment.";
-
-    image_resizer::resize_images(&video.thumbnails);
-    let mut images = vec![];
-    for thumbnail in &video.thumbnails {
-        let image_data = match fs::read(thumbnail) {
-            Ok(img) => img,
-            Err(_) => {
-                return Err(anyhow::anyhow!(
-                    "Couldn't generate answer from computer vision model for file: {}",
-                    video.path,
-                ));
-            }
-        };
-
-        // Encode the image data as Base64
-        images.push(Image::from_base64(
-            BASE64_STANDARD.encode(&image_data).as_str(),
-        ))
+    let mut prompt = prompts.render(PromptStage::Story, video, language, &[], captions);
+    if sheet {
+        prompt += contact_sheet::get_prompt(video.get_frame_count()).as_str();
     }
-        let options = ModelOptions::default().temperature(0.5);
+
+    let images = read_images(video, profile, sheet)?;
+    let options = ModelOptions::default().temperature(0.5);
     let res = ollama
         .generate(
             GenerationRequest::new(model.to_string(), prompt.to_string())
@@ -170,14 +665,17 @@ pub(crate) async fn run_computer_vision_model_for_video(
     match res {
         Ok(res) => {
             log::debug!("Story: {}", res.response);
-            return Ok(res.response);
+            Ok(res.response)
+        }
+        Err(OllamaError::ReqwestError(error)) => {
+            Err(VideoDataError::ModelServerError(error.to_string()).into())
         }
         Err(err) => {
             log::debug!("Error in run_computer_vision_model_for_video: {}", err); //TODO push the error to the front
-            return Err(anyhow::anyhow!(
+            Err(anyhow::anyhow!(
                 "Couldn't generate answer from computer vision model for file: {}",
                 video.path
-            ));
+            ))
         }
     }
 }
@@ -187,6 +685,12 @@ pub(crate) async fn run_computer_vision_model_for_video(
 /// ### Parameters
 /// - `ollama`: The model prompter for the computer vision model.    
 /// - `model`: The name of the model.   
+/// - `prompts`: The prompt templates.
+/// - `language`: The language of the answer.
+/// - `fields`: The custom fields of the resume.
+/// - `captions`: The caption mode, the captions of the frames being given with the thumbnails in the story mode.
+/// - `profile`: The image profile of the model.
+/// - `sheet`: Flag to send the frames as a single contact sheet.
 /// - `video`: The video to analyse.   
 ///
 /// ### Returns
@@ -194,62 +698,52 @@ pub(crate) async fn run_computer_vision_model_for_video(
 ///
 /// ### Errors
 /// Returns an error if the model can't be reached, doesn't exist, or doesn't return a result.
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn run_only_computer_vision_model_for_video(
     ollama: &Ollama,
     model: &str,
+    prompts: &PromptSet,
+    language: &Language,
+    fields: &[CustomField],
+    captions: CaptionMode,
+    profile: &ImageProfile,
+    sheet: bool,
     video: &Video,
 ) -> anyhow::Result<Resume> {
-    let prompt = "The following images are part of a video, they tell a story. Please describe that story focusing on the persons and their action and less on their environment. Please resume that story in 20 words focusing on the person and their action and less on their environment, from that resume please generate a title of maximum 8 words, and make a list of up to 5 keywords that resumes the story, the keywords will include the person on the video if any (e.g. woman, child...). Please format the answer in a valid json format: {\"title\": <<title>>, \"description\": <<description>>, \"keywords\": <<array of keywords>>}, with no other text at all, only the json result.";
-
-    image_resizer::resize_images(&video.thumbnails);
-    let mut images = vec![];
-    for thumbnail in &video.thumbnails {
-        let image_data = match fs::read(thumbnail) {
-            Ok(img) => img,
-            Err(_) => {
-                return Err(anyhow::anyhow!(
-                    "Couldn't generate answer from computer vision model for file: {}",
-                    video.path,
-                ));
-            }
-        };
-
-        // Encode the image data as Base64
-        images.push(Image::from_base64(
-            BASE64_STANDARD.encode(&image_data).as_str(),
-        ))
-    }
-        let options = ModelOptions::default().temperature(0.5);
-    let res = ollama
-        .generate(
-            GenerationRequest::new(model.to_string(), prompt.to_string())
-                .options(options)
-                .images(images),
-        )
-        .await;
-    match res {
-        Ok(res) => {
-            match extract_json(&res.response) {
-                Some(story) => {
-                    log::debug!("Story: {}", story);
-                    Ok(serde_json::from_str(story.as_str())?)
-                }
-                None => Err(anyhow::anyhow!(
-                    "Couldn't generate answer from computer vision model for file: {}",
-                    video.path
-                )),
-            }
-            //log::debug!("Story: {}", res.response);
-            //return Ok(res.response);
-        }
-        Err(err) => {
-            log::debug!("Error in run_computer_vision_model_for_video: {}", err); //TODO push the error to the front
-            return Err(anyhow::anyhow!(
-                "Couldn't generate answer from computer vision model for file: {}",
-                video.path
-            ));
-        }
+    let mut prompt = prompts.render(PromptStage::SinglePass, video, language, fields, captions);
+    if sheet {
+        prompt += contact_sheet::get_prompt(video.get_frame_count()).as_str();
     }
+
+    let images = read_images(video, profile, sheet)?;
+    generate_resume(
+        ollama,
+        model,
+        prompt,
+        images,
+        fields,
+        "computer vision",
+        &video.path,
+    )
+    .await
+}
+
+/// Represents the metadata of a video read by FFprobe.
+/// ### Fields
+/// - `duration`: The duration of the video in seconds.
+/// - `creation_date`: The creation date of the video.
+/// - `width`: The width of the video in pixels.
+/// - `height`: The height of the video in pixels.
+/// - `camera_make`: The make of the camera that recorded the video.
+/// - `camera_model`: The model of the camera that recorded the video.
+#[derive(Default)]
+pub(crate) struct VideoMetadata {
+    pub duration: Option<f32>,
+    pub creation_date: Option<DateTime<Utc>>,
+    pub width: u32,
+    pub height: u32,
+    pub camera_make: String,
+    pub camera_model: String,
 }
 
 /// Gets the video's metadata ia FFprobe.
@@ -258,22 +752,18 @@ pub(crate) async fn run_only_computer_vision_model_for_video(
 /// - `video_path`: The path to the video to analyse.  
 ///  
 /// ### Returns
-/// A Result containing the duration of the video and its creation date.
+/// A Result containing the metadata of the video.
 ///
 /// ### Errors
 /// Returns an error if FFprobe can't be run (e.g. not in the path).
-pub(crate) fn get_video_metadata(
-    video_path: &str,
-) -> anyhow::Result<(Option<f32>, Option<DateTime<Utc>>)> {
+pub(crate) fn get_video_metadata(video_path: &str) -> anyhow::Result<VideoMetadata> {
     let output = Command::new("ffprobe")
         .arg("-v")
         .arg("error")
         .arg("-show_entries")
-        .arg("format=duration")
-        .arg("-show_entries")
-        .arg("stream_tags=creation_time")
+        .arg("format=duration:format_tags:stream=codec_type,width,height:stream_tags=creation_time")
         .arg("-of")
-        .arg("default=noprint_wrappers=1:nokey=1")
+        .arg("json")
         .arg(video_path)
         .output();
 
@@ -290,52 +780,127 @@ pub(crate) fn get_video_metadata(
         }
     };
 
-    let binding = String::from_utf8(output.stdout).unwrap();
-    let mut set = HashSet::new();
-    let metadata: Vec<String> = binding
-        .trim()
-        .lines()
-        .map(|s| s.to_string())
-        .filter(|item| set.insert(item.clone()))
-        .collect();
-
-    Ok(parse_metadata_to_tuple(metadata))
+    Ok(parse_metadata(&String::from_utf8_lossy(&output.stdout)))
 }
 
-/// Parses the result from FFprobe into something usable.
+/// Parses the JSON result from FFprobe into something usable.
 ///
 /// ### Parameters
-/// - `values`: The raw values from FFprobe.   
+/// - `output`: The raw JSON output from FFprobe.   
 ///
 /// ### Returns
-/// A tuple with the duration of the video and its creation date.
-fn parse_metadata_to_tuple(values: Vec<String>) -> (Option<f32>, Option<DateTime<Utc>>) {
-    let mut float_opt: Option<f32> = None;
-    let mut date_opt: Option<DateTime<Utc>> = None;
-
-    for value in values {
-        // Try parsing as a DateTime first
-        if let Ok(date) = DateTime::parse_from_rfc3339(&value) {
-            date_opt = Some(date.to_utc());
-        } else if let Ok(num) = value.parse::<f32>() {
-            // Not a date, try parsing as a float
-            float_opt = Some(num);
-        }
+/// The metadata of the video, with default values for what couldn't be found.
+fn parse_metadata(output: &str) -> VideoMetadata {
+    let mut metadata = VideoMetadata::default();
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(output) else {
+        return metadata;
+    };
+    let format = &value["format"];
+    metadata.duration = format["duration"]
+        .as_str()
+        .and_then(|duration| duration.parse::<f32>().ok());
+
+    // The tags are matched without case, as their case depends on the container
+    let format_tags: Vec<(String, &str)> = format["tags"]
+        .as_object()
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|(key, value)| value.as_str().map(|value| (key.to_lowercase(), value)))
+                .collect()
+        })
+        .unwrap_or_default();
+    let get_tag = |keys: &[&str]| {
+        keys.iter().find_map(|key| {
+            format_tags
+                .iter()
+                .find(|(tag, value)| tag == key && !value.trim().is_empty())
+                .map(|(_, value)| value.trim().to_string())
+        })
+    };
+    metadata.camera_make = get_tag(&[
+        "make",
+        "com.apple.quicktime.make",
+        "com.android.manufacturer",
+    ])
+    .unwrap_or_default();
+    metadata.camera_model =
+        get_tag(&["model", "com.apple.quicktime.model", "com.android.model"]).unwrap_or_default();
+
+    let streams = value["streams"].as_array().cloned().unwrap_or_default();
+    let stream_dates = streams.iter().filter_map(|stream| {
+        stream["tags"]["creation_time"]
+            .as_str()
+            .map(|date| date.to_string())
+    });
+    metadata.creation_date = get_tag(&["creation_time"])
+        .into_iter()
+        .chain(stream_dates)
+        .find_map(|date| DateTime::parse_from_rfc3339(&date).ok())
+        .map(|date| date.to_utc());
+    if let Some(stream) = streams
+        .iter()
+        .find(|stream| stream["codec_type"].as_str() == Some("video"))
+    {
+        metadata.width = stream["width"].as_u64().unwrap_or_default() as u32;
+        metadata.height = stream["height"].as_u64().unwrap_or_default() as u32;
     }
+    metadata
+}
 
-    (float_opt, date_opt)
+/// Checks via FFprobe if the content of a file is a video, for files without a known extension.
+/// Still images are also seen as video streams by FFprobe, so they are told apart by their format.
+///
+/// ### Parameters
+/// - `file_path`: The path of the file to check.
+///
+/// ### Returns
+/// True if the file contains a video stream, false if it doesn't or if FFprobe can't read it.
+pub(crate) fn is_video_content(file_path: &str) -> bool {
+    let output = Command::new("ffprobe")
+        .arg("-v")
+        .arg("error")
+        .arg("-show_entries")
+        .arg("format=format_name:stream=codec_type")
+        .arg("-of")
+        .arg("json")
+        .arg(file_path)
+        .output();
+    let Ok(output) = output else {
+        return false;
+    };
+    let Ok(value) = serde_json::from_slice::<serde_json::Value>(&output.stdout) else {
+        return false;
+    };
+    let format_name = value["format"]["format_name"].as_str().unwrap_or_default();
+    let is_image = format_name.is_empty()
+        || format_name == "image2"
+        || format_name.ends_with("_pipe")
+        || format_name == "gif";
+    let has_video_stream = value["streams"]
+        .as_array()
+        .map(|streams| {
+            streams
+                .iter()
+                .any(|stream| stream["codec_type"].as_str() == Some("video"))
+        })
+        .unwrap_or_default();
+    has_video_stream && !is_image
 }
 
-/// Gets the gap between two thumbnails extractions in seconds.
+/// Gets the interval between two thumbnails extractions in seconds, so that a given number of frames is spread over the whole video.
 ///
 /// ### Parameters
-/// - `duration`: The duration of the video.  
+/// - `duration`: The duration of the video, 0 if unknown.  
+/// - `count`: The number of frames to extract.
 ///  
 /// ### Returns
-/// The gap between two thumbnails extractions in seconds.
-pub(crate) fn get_capture_gap(duration: f32) -> i32 {
-    let f_gap = duration / 3.0;
-    f_gap.floor() as i32
+/// The interval between two thumbnails extractions in seconds, 1 second if the duration is unknown.
+pub(crate) fn get_capture_interval(duration: f32, count: u32) -> f32 {
+    if duration > 0.0 {
+        duration / count.max(1) as f32
+    } else {
+        1.0
+    }
 }
 
 /// Extracts the JSON value in the model's result in the case noise is introduced.
@@ -351,6 +916,273 @@ fn extract_json(input: &str) -> Option<String> {
     re.find(input).map(|mat| mat.as_str().to_string())
 }
 
+/// Translates the resume of a video into another language.
+///
+/// ### Parameters
+/// - `ollama`: The model prompter.
+/// - `model`: The name of the model.
+/// - `resume`: The resume of the video, in its own language.
+/// - `language`: The language to translate the resume into.
+/// - `path`: The path of the video, for the error messages.
+///
+/// ### Returns
+/// A Result containing the title, description and keywords in the language.
+///
+/// ### Errors
+/// Returns an error if the model can't be reached, doesn't exist, or doesn't return a valid JSON result.
+pub(crate) async fn translate_resume(
+    ollama: &Ollama,
+    model: &str,
+    resume: &Resume,
+    language: &Language,
+    path: &str,
+) -> anyhow::Result<Translation> {
+    log::debug!("Translating the resume of {} into {}", path, language.name);
+    let prompt = format!(
+        "Please translate the values of the following json into {}, keeping its keys, with a title of maximum {} words and up to {} keywords. Please answer with the translated json only, with no other text at all. The json is: {}",
+        language.name,
+        MAX_TITLE_WORDS,
+        MAX_KEYWORDS,
+        serde_json::to_string(&resume.get_translation(resume.get_language()).unwrap_or_default())?
+    );
+    let translation =
+        generate_resume(ollama, model, prompt, Vec::new(), &[], "translation", path).await?;
+    Ok(Translation {
+        title: translation.title,
+        description: translation.description,
+        keywords: translation.keywords,
+    })
+}
+
+/// Generates the resume of a video, asking the model to repair it when it doesn't follow the rules of the prompt. If it still doesn't after `RESUME_REPAIRS` attempts, its title and keywords are shortened.
+///
+/// ### Parameters
+/// - `ollama`: The model prompter.
+/// - `model`: The name of the model.
+/// - `prompt`: The prompt asking for the resume.
+/// - `images`: The images given with the prompt.
+/// - `fields`: The custom fields of the resume.
+/// - `role`: The role of the model, for the error messages.
+/// - `path`: The path of the video, for the error messages.
+///
+/// ### Returns
+/// A Result containing the resume of the video.
+///
+/// ### Errors
+/// Returns an error if the model can't be reached, doesn't exist, or doesn't return a valid JSON result.
+async fn generate_resume(
+    ollama: &Ollama,
+    model: &str,
+    prompt: String,
+    images: Vec<Image>,
+    fields: &[CustomField],
+    role: &str,
+    path: &str,
+) -> anyhow::Result<Resume> {
+    let format = JsonStructure::new_for_schema(custom_fields::make_schema(fields, MAX_KEYWORDS));
+    let response = generate_json(ollama, model, prompt, images, &format, role, path).await?;
+    let mut resume = parse_resume(&response, role, path)?;
+    for attempt in 1..=RESUME_REPAIRS {
+        let problems = check_resume(&resume, fields);
+        if problems.is_empty() {
+            return Ok(resume);
+        }
+        log::debug!(
+            "Invalid resume for {}: {}, asking the model to repair it ({}/{})",
+            path,
+            problems.join(", "),
+            attempt,
+            RESUME_REPAIRS
+        );
+        let repair_prompt = format!(
+            "The following json doesn't follow the rules: {}. Please fix it, keeping its meaning, so that the title has at most {} words and there are at most {} keywords.{} Please answer with the fixed json only, with no other text at all. The json is: {}",
+            problems.join(", "),
+            MAX_TITLE_WORDS,
+            MAX_KEYWORDS,
+            custom_fields::get_prompt(fields),
+            serde_json::to_string(&resume)?
+        );
+        let response = generate_json(
+            ollama,
+            model,
+            repair_prompt,
+            Vec::new(),
+            &format,
+            role,
+            path,
+        )
+        .await?;
+        match parse_resume(&response, role, path) {
+            Ok(repaired) => resume = repaired,
+            Err(error) => log::debug!("Invalid repaired resume for {}: {}", path, error),
+        }
+    }
+    let problems = check_resume(&resume, fields);
+    if !problems.is_empty() {
+        log::warn!(
+            "The resume of {} still doesn't follow the rules ({}), it is shortened and its invalid fields are left empty",
+            path,
+            problems.join(", ")
+        );
+        resume.title = resume
+            .title
+            .split_whitespace()
+            .take(MAX_TITLE_WORDS)
+            .collect::<Vec<_>>()
+            .join(" ");
+        resume.keywords.truncate(MAX_KEYWORDS);
+    }
+    custom_fields::clean_values(&mut resume.fields, fields);
+    Ok(resume)
+}
+
+/// Sends a prompt expecting a resume in JSON. The JSON schema of the resume is given to the model as structured output format, and when the server rejects the format, the prompt is sent again without it.
+///
+/// ### Parameters
+/// - `ollama`: The model prompter.
+/// - `model`: The name of the model.
+/// - `prompt`: The prompt.
+/// - `images`: The images given with the prompt.
+/// - `format`: The JSON schema of the resume.
+/// - `role`: The role of the model, for the error messages.
+/// - `path`: The path of the video, for the error messages.
+///
+/// ### Returns
+/// A Result containing the response of the model.
+///
+/// ### Errors
+/// Returns an error if the model can't be reached, doesn't exist, or doesn't return a result.
+async fn generate_json(
+    ollama: &Ollama,
+    model: &str,
+    prompt: String,
+    images: Vec<Image>,
+    format: &JsonStructure,
+    role: &str,
+    path: &str,
+) -> anyhow::Result<String> {
+    let options = ModelOptions::default().temperature(0.5);
+    let request = GenerationRequest::new(model.to_string(), prompt.clone())
+        .options(options.clone())
+        .images(images.clone());
+    let structured_request = request.format(FormatType::StructuredJson(format.clone()));
+    let res = match ollama.generate(structured_request).await {
+        Err(OllamaError::ReqwestError(error)) => {
+            return Err(VideoDataError::ModelServerError(error.to_string()).into())
+        }
+        Err(error) if is_structured_output_unsupported(&error) => {
+            log::debug!(
+                "Structured output not available with {}, asking for JSON in the prompt only: {}",
+                model,
+                error
+            );
+            ollama
+                .generate(
+                    GenerationRequest::new(model.to_string(), prompt)
+                        .options(options)
+                        .images(images),
+                )
+                .await
+        }
+        res => res,
+    };
+    match res {
+        Ok(res) => {
+            log::debug!("Resume: {}", res.response);
+            Ok(res.response)
+        }
+        Err(OllamaError::ReqwestError(error)) => {
+            Err(VideoDataError::ModelServerError(error.to_string()).into())
+        }
+        Err(error) => {
+            log::debug!("Error while generating a resume: {}", error);
+            Err(anyhow::anyhow!(
+                "Couldn't generate answer from {} model for file: {}",
+                role,
+                path
+            ))
+        }
+    }
+}
+
+/// Checks if an error returned by the server means that it doesn't support the structured output, e.g. a server older than Ollama 0.5, which only accepts `json` as format.
+/// The other errors, such as a missing model or an invalid image, would fail again without the format.
+///
+/// ### Parameters
+/// - `error`: The error returned by the server.
+///
+/// ### Returns
+/// True if the request can be sent again without the format.
+fn is_structured_output_unsupported(error: &OllamaError) -> bool {
+    let OllamaError::Other(message) = error else {
+        return false;
+    };
+    let message = message.to_lowercase();
+    !message.contains("image")
+        && ["format", "schema", "grammar"]
+            .iter()
+            .any(|word| message.contains(word))
+}
+
+/// Parses the resume returned by a model, which can be surrounded by other text when the structured output is not available.
+///
+/// ### Parameters
+/// - `response`: The response of the model.
+/// - `role`: The role of the model, for the error messages.
+/// - `path`: The path of the video, for the error messages.
+///
+/// ### Returns
+/// A Result containing the resume.
+///
+/// ### Errors
+/// Returns an error if the response doesn't contain a valid resume.
+fn parse_resume(response: &str, role: &str, path: &str) -> anyhow::Result<Resume> {
+    if let Ok(resume) = serde_json::from_str(response.trim()) {
+        return Ok(resume);
+    }
+    match extract_json(response) {
+        Some(json) => Ok(serde_json::from_str(json.as_str())?),
+        None => Err(anyhow::anyhow!(
+            "Couldn't generate answer from {} model for file: {}",
+            role,
+            path
+        )),
+    }
+}
+
+/// Checks that a resume follows the rules given in the prompts.
+///
+/// ### Parameters
+/// - `resume`: The resume to check.
+/// - `fields`: The custom fields of the resume.
+///
+/// ### Returns
+/// The problems of the resume, empty if it is valid.
+fn check_resume(resume: &Resume, fields: &[CustomField]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let title_words = resume.title.split_whitespace().count();
+    if title_words == 0 {
+        problems.push("the title is empty".to_string());
+    } else if title_words > MAX_TITLE_WORDS {
+        problems.push(format!(
+            "the title has {} words instead of at most {}",
+            title_words, MAX_TITLE_WORDS
+        ));
+    }
+    if resume.description.trim().is_empty() {
+        problems.push("the description is empty".to_string());
+    }
+    if resume.keywords.len() > MAX_KEYWORDS {
+        problems.push(format!(
+            "there are {} keywords instead of at most {}",
+            resume.keywords.len(),
+            MAX_KEYWORDS
+        ));
+    }
+    problems.extend(custom_fields::check_values(&resume.fields, fields));
+    problems
+}
+
 /// Gets the names of the models available on an Ollama server.
 ///
 /// ### Parameters
@@ -359,7 +1191,24 @@ fn extract_json(input: &str) -> Option<String> {
 /// ### Returns
 /// A Vec<String> of models names.
 pub async fn get_models_for_server(ollama: &Ollama) -> anyhow::Result<Vec<String>> {
-    let models =  ollama.list_local_models().await?;
+    let models = ollama.list_local_models().await?;
     let model_names: Vec<String> = models.into_iter().map(|m| m.name).collect();
     Ok(model_names)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_without_format_only_when_it_is_rejected() {
+        let unsupported = OllamaError::Other(
+            "{\"error\":\"json: cannot unmarshal object into Go struct field GenerateRequest.format of type string\"}".to_string(),
+        );
+        assert!(is_structured_output_unsupported(&unsupported));
+        let missing_model =
+            OllamaError::Other("{\"error\":\"model 'llava' not found\"}".to_string());
+        assert!(!is_structured_output_unsupported(&missing_model));
+        let invalid_image = OllamaError::Other("{\"error\":\"image: unknown format\"}".to_string());
+        assert!(!is_structured_output_unsupported(&invalid_image));
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::captions::{CaptionMode, FrameCaption};
+use super::custom_fields::CustomField;
+use super::faces::FaceBackend;
+use super::file_management;
+use super::image_resizer::ImageProfile;
+use super::language::Language;
+use super::prompts::PromptSet;
+use super::sampling::SamplingStrategy;
+use super::transcription::TranscriptionBackend;
+use super::video::{Resume, Video};
+
+/// Represents what the analysis of a video depends on besides its content, so that a cached result is only used with the same models, prompts and settings.
+/// ### Fields
+/// - `computer_vision_model`: The name of the computer vision model.
+/// - `text_model`: The name of the text model, None if the analysis is done in one step.
+/// - `prompts`: The prompt templates.
+/// - `languages`: The languages of the resumes.
+/// - `custom_fields`: The custom fields of the resumes.
+/// - `sampling`: The strategy used to extract the frames.
+/// - `min_frames`: The minimum number of frames to extract.
+/// - `max_frames`: The maximum number of frames to extract.
+/// - `image_profile`: How the frames are prepared for the computer vision model.
+/// - `captions`: Whether the frames are captioned one by one.
+/// - `contact_sheet`: Flag if the frames are sent as a single contact sheet.
+/// - `transcription`: The speech-to-text backend, None if the audio is not transcribed.
+/// - `faces`: The face detection backend, None if the faces are not detected.
+/// - `face_threshold`: The minimum similarity between two faces of the same person.
+#[derive(Serialize)]
+pub struct AnalysisSettings<'a> {
+    pub computer_vision_model: &'a str,
+    pub text_model: Option<&'a str>,
+    pub prompts: &'a PromptSet,
+    pub languages: &'a [Language],
+    pub custom_fields: &'a [CustomField],
+    pub sampling: &'a SamplingStrategy,
+    pub min_frames: u32,
+    pub max_frames: u32,
+    pub image_profile: ImageProfile,
+    pub captions: CaptionMode,
+    pub contact_sheet: bool,
+    pub transcription: Option<&'a TranscriptionBackend>,
+    pub faces: Option<&'a FaceBackend>,
+    pub face_threshold: f32,
+}
+
+impl AnalysisSettings<'_> {
+    /// Gets the fingerprint of the settings, stored with the cached results.
+    ///
+    /// ### Returns
+    /// The hash of the settings.
+    pub fn fingerprint(&self) -> String {
+        let settings = serde_json::to_string(self).unwrap_or_default();
+        format!("{:x}", md5::compute(settings))
+    }
+}
+
+/// Represents an analysis result stored in the cache.
+/// ### Fields
+/// - `hash`: The hash of the video's content.
+/// - `fingerprint`: The fingerprint of the settings of the analysis, empty for the results cached before it was recorded.
+/// - `story`: The story generated by the CV model, empty if the analysis was done in one step.
+/// - `transcript`: The transcript of the video's audio, empty if it was not transcribed.
+/// - `timeline`: The captions of the frames of the video, empty if they were not captioned.
+/// - `faces`: The IDs of the clusters of the faces found in the video, empty if the faces were not detected.
+/// - `resume`: The resume generated for the video.
+#[derive(Deserialize, Serialize)]
+pub struct CacheEntry {
+    pub hash: String,
+    #[serde(default)]
+    pub fingerprint: String,
+    #[serde(default)]
+    pub story: String,
+    #[serde(default)]
+    pub transcript: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timeline: Vec<FrameCaption>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub faces: Vec<u32>,
+    pub resume: Resume,
+}
+
+/// Represents the persistent analysis cache, stored as JSON lines in the work folder.
+/// ### Fields
+/// - `path`: The path of the cache file.
+/// - `fingerprint`: The fingerprint of the settings of the current analysis, the results made with other settings being ignored.
+/// - `entries`: The cached analysis results, indexed by the hash of the video's content and the fingerprint of their settings.
+#[derive(Default)]
+pub struct AnalysisCache {
+    path: PathBuf,
+    fingerprint: String,
+    entries: HashMap<(String, String), CacheEntry>,
+}
+
+impl AnalysisCache {
+    /// Loads the analysis cache from the work folder. Unreadable lines are ignored, and the latest entry wins when a video was analysed several times.
+    ///
+    /// ### Parameters
+    /// - `work_folder`: The path of the work folder.
+    ///
+    /// ### Returns
+    /// The analysis cache, empty if no cache file exists yet.
+    pub fn load(work_folder: &str) -> Self {
+        let mut path = PathBuf::from(work_folder);
+        path.push("cache.jsonl");
+        let mut entries = HashMap::new();
+        if let Ok(contents) = fs::read_to_string(&path) {
+            for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+                match serde_json::from_str::<CacheEntry>(line) {
+                    Ok(entry) => {
+                        entries.insert((entry.hash.clone(), entry.fingerprint.clone()), entry);
+                    }
+                    Err(error) => log::warn!("Ignoring invalid cache entry: {}", error),
+                }
+            }
+        }
+        log::debug!("Loaded {} cached analysis results", entries.len());
+        Self {
+            path,
+            fingerprint: String::default(),
+            entries,
+        }
+    }
+
+    /// Sets the settings of the current analysis, so that only the results made with the same settings are picked up and new results are stored with them.
+    ///
+    /// ### Parameters
+    /// - `settings`: The settings of the analysis.
+    pub fn set_settings(&mut self, settings: &AnalysisSettings) {
+        self.fingerprint = settings.fingerprint();
+    }
+
+    /// Gets the cached analysis result of a video, made with the settings of the current analysis.
+    ///
+    /// ### Parameters
+    /// - `hash`: The hash of the video's content.
+    ///
+    /// ### Returns
+    /// The cached analysis result if any.
+    pub fn get(&self, hash: &str) -> Option<&CacheEntry> {
+        self.entries.get(&(hash.to_string(), self.fingerprint.clone()))
+    }
+
+    /// Stores the analysis result of a video in the cache, and appends it to the cache file. The content key of the video is computed when the cache was not read for it, e.g. with `--force`.
+    ///
+    /// ### Parameters
+    /// - `video`: The analysed video.
+    ///
+    /// ### Returns
+    /// An empty Result in case of success.
+    ///
+    /// ### Errors
+    /// Returns an error if the video can't be read or if the cache file can't be written.
+    pub fn store(&mut self, video: &Video) -> anyhow::Result<()> {
+        let hash = if video.content_hash.is_empty() {
+            file_management::get_content_key(video.path.as_str())?
+        } else {
+            video.content_hash.clone()
+        };
+        let entry = CacheEntry {
+            hash,
+            fingerprint: self.fingerprint.clone(),
+            story: video.story.clone(),
+            transcript: video.transcript.clone(),
+            timeline: video.timeline.clone(),
+            faces: video.face_clusters.clone(),
+            resume: video.resume.clone(),
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        self.entries
+            .insert((entry.hash.clone(), entry.fingerprint.clone()), entry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates the settings of an analysis with the default values.
+    fn make_settings<'a>(prompts: &'a PromptSet, languages: &'a [Language], sampling: &'a SamplingStrategy) -> AnalysisSettings<'a> {
+        AnalysisSettings {
+            computer_vision_model: "vision",
+            text_model: None,
+            prompts,
+            languages,
+            custom_fields: &[],
+            sampling,
+            min_frames: 3,
+            max_frames: 10,
+            image_profile: ImageProfile::default(),
+            captions: CaptionMode::Off,
+            contact_sheet: false,
+            transcription: None,
+            faces: None,
+            face_threshold: 0.5,
+        }
+    }
+
+    #[test]
+    fn misses_the_results_made_with_other_analysis_settings() {
+        let folder = std::env::temp_dir().join(format!("aspargus-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let prompts = PromptSet::default();
+        let languages = vec![Language::default()];
+        let sampling = SamplingStrategy::default();
+        let mut cache = AnalysisCache::load(folder.to_str().unwrap());
+        cache.set_settings(&make_settings(&prompts, &languages, &sampling));
+        let video = Video {
+            content_hash: "content".to_string(),
+            ..Default::default()
+        };
+        cache.store(&video).unwrap();
+        assert!(cache.get("content").is_some());
+
+        let other_sampling = SamplingStrategy::SceneDetection { threshold: 0.3 };
+        cache.set_settings(&make_settings(&prompts, &languages, &other_sampling));
+        assert!(cache.get("content").is_none());
+        let mut settings = make_settings(&prompts, &languages, &sampling);
+        settings.captions = CaptionMode::Story;
+        cache.set_settings(&settings);
+        assert!(cache.get("content").is_none());
+        let mut settings = make_settings(&prompts, &languages, &sampling);
+        settings.contact_sheet = true;
+        cache.set_settings(&settings);
+        assert!(cache.get("content").is_none());
+
+        // The results are found again with the same settings, even once the cache is reloaded
+        let mut cache = AnalysisCache::load(folder.to_str().unwrap());
+        cache.set_settings(&make_settings(&prompts, &languages, &sampling));
+        assert!(cache.get("content").is_some());
+        let _ = fs::remove_dir_all(&folder);
+    }
+}
@@ -0,0 +1,90 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Represents whether the frames of the videos are captioned one by one before the analysis.
+/// ### Variants
+/// - `Off`: The frames are only sent together to tell the story of the video.
+/// - `Timeline`: Each frame is captioned with its timestamp, and the timeline is exported with the results.
+/// - `Story`: Same as `Timeline`, and the captions are also given to the model telling the story of the video.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptionMode {
+    #[default]
+    Off,
+    Timeline,
+    Story,
+}
+
+impl fmt::Display for CaptionMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptionMode::Off => write!(f, "off"),
+            CaptionMode::Timeline => write!(f, "timeline"),
+            CaptionMode::Story => write!(f, "story"),
+        }
+    }
+}
+
+impl FromStr for CaptionMode {
+    type Err = String;
+
+    /// Parses a caption mode written as `off`, `timeline` or `story`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "off" => Ok(CaptionMode::Off),
+            "timeline" => Ok(CaptionMode::Timeline),
+            "story" => Ok(CaptionMode::Story),
+            _ => Err(format!(
+                "Unknown caption mode: {}, expected off, timeline or story",
+                value
+            )),
+        }
+    }
+}
+
+/// Represents the caption of a frame of a video.
+/// ### Fields
+/// - `timestamp`: The time of the frame in the video in seconds, None if FFmpeg didn't give it.
+/// - `caption`: What the frame shows, generated by the CV model.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FrameCaption {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<f32>,
+    pub caption: String,
+}
+
+/// Formats a timestamp for the prompts, e.g. `1:05` or `1:02:05`.
+///
+/// ### Parameters
+/// - `timestamp`: The timestamp in seconds.
+///
+/// ### Returns
+/// The formatted timestamp.
+pub fn format_timestamp(timestamp: f32) -> String {
+    let seconds = timestamp.max(0.0).round() as u64;
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// Formats the timeline of a video for the prompts, a caption per line in the order of the frames.
+///
+/// ### Parameters
+/// - `timeline`: The captions of the frames.
+///
+/// ### Returns
+/// The formatted timeline, e.g. `- at 0:05: a child blows the candles of a cake`.
+pub fn format_timeline(timeline: &[FrameCaption]) -> String {
+    timeline
+        .iter()
+        .enumerate()
+        .map(|(index, frame)| match frame.timestamp {
+            Some(timestamp) => format!("- at {}: {}", format_timestamp(timestamp), frame.caption),
+            None => format!("- frame {}: {}", index + 1, frame.caption),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
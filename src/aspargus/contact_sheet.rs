@@ -0,0 +1,188 @@
+use std::{fmt, fs, str::FromStr};
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use super::captions;
+use super::image_resizer;
+use super::video::Video;
+
+/// The glyphs of the labels of the tiles, 3 pixels wide and 5 pixels high, each row being given by its 3 lowest bits.
+const GLYPHS: &[(char, [u8; 5])] = &[
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('#', [0b101, 0b111, 0b101, 0b111, 0b101]),
+];
+
+/// Represents whether the frames of the videos are sent to the computer vision model as a single contact sheet.
+/// ### Variants
+/// - `Off`: The frames are sent as separate images.
+/// - `On`: The frames are tiled in a grid, each tile labelled with its time in the video, and the grid is sent as a single image.
+/// - `Preview`: Same as `On`, and the contact sheet is also saved next to the video.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContactSheetMode {
+    #[default]
+    Off,
+    On,
+    Preview,
+}
+
+impl fmt::Display for ContactSheetMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContactSheetMode::Off => write!(f, "off"),
+            ContactSheetMode::On => write!(f, "on"),
+            ContactSheetMode::Preview => write!(f, "preview"),
+        }
+    }
+}
+
+impl FromStr for ContactSheetMode {
+    type Err = String;
+
+    /// Parses a contact sheet mode written as `off`, `on` or `preview`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "off" => Ok(ContactSheetMode::Off),
+            "on" => Ok(ContactSheetMode::On),
+            "preview" => Ok(ContactSheetMode::Preview),
+            _ => Err(format!(
+                "Unknown contact sheet mode: {}, expected off, on or preview",
+                value
+            )),
+        }
+    }
+}
+
+/// Tiles the frames of a video in a grid, as square as possible and read from left to right then top to bottom, each tile being labelled with the time of its frame, or its number when the times are unknown.
+///
+/// ### Parameters
+/// - `frames`: The frames of the video, in their order.
+/// - `timestamps`: The time of each frame in seconds, empty if FFmpeg didn't give them.
+/// - `max_edge`: The maximum width and height of the contact sheet.
+/// - `upscale`: Flag to enlarge the contact sheet if the frames are too small to reach the maximum size.
+///
+/// ### Returns
+/// The contact sheet.
+pub(crate) fn compose(frames: &[DynamicImage], timestamps: &[f32], max_edge: u32, upscale: bool) -> DynamicImage {
+    let Some(first) = frames.first() else {
+        return DynamicImage::new_rgb8(1, 1);
+    };
+    let columns = (frames.len() as f64).sqrt().ceil() as u32;
+    let rows = (frames.len() as u32).div_ceil(columns);
+    let (frame_width, frame_height) = first.dimensions();
+    let (width, height) = image_resizer::calculate_new_size(columns * frame_width, rows * frame_height, max_edge, upscale);
+    let (tile_width, tile_height) = ((width / columns).max(1), (height / rows).max(1));
+    let mut sheet = RgbaImage::from_pixel(tile_width * columns, tile_height * rows, Rgba([0, 0, 0, 255]));
+    for (index, frame) in frames.iter().enumerate() {
+        let (column, row) = (index as u32 % columns, index as u32 / columns);
+        let tile = frame.resize(tile_width, tile_height, FilterType::Triangle).to_rgba8();
+        let x = column * tile_width + (tile_width - tile.width()) / 2;
+        let y = row * tile_height + (tile_height - tile.height()) / 2;
+        imageops::replace(&mut sheet, &tile, x as i64, y as i64);
+        let label = match timestamps.get(index) {
+            Some(timestamp) => captions::format_timestamp(*timestamp),
+            None => format!("#{}", index + 1),
+        };
+        draw_label(&mut sheet, &label, column * tile_width, row * tile_height, tile_height);
+    }
+    DynamicImage::ImageRgba8(sheet)
+}
+
+/// Draws a label in white on a black box at the top left corner of a tile, its size following the height of the tile.
+///
+/// ### Parameters
+/// - `sheet`: The contact sheet.
+/// - `label`: The label, made of digits, `:` and `#`.
+/// - `x`: The left of the tile.
+/// - `y`: The top of the tile.
+/// - `tile_height`: The height of the tile.
+fn draw_label(sheet: &mut RgbaImage, label: &str, x: u32, y: u32, tile_height: u32) {
+    let scale = (tile_height / 60).max(1);
+    let width = (label.chars().count() as u32 * 4 + 1) * scale;
+    fill(sheet, x, y, width, 7 * scale, Rgba([0, 0, 0, 255]));
+    for (position, character) in label.chars().enumerate() {
+        let Some((_, glyph)) = GLYPHS.iter().find(|(glyph_character, _)| *glyph_character == character) else {
+            continue;
+        };
+        for (glyph_row, bits) in glyph.iter().enumerate() {
+            for glyph_column in 0..3 {
+                if bits & (0b100 >> glyph_column) == 0 {
+                    continue;
+                }
+                let left = x + (1 + position as u32 * 4 + glyph_column) * scale;
+                let top = y + (1 + glyph_row as u32) * scale;
+                fill(sheet, left, top, scale, scale, Rgba([255, 255, 255, 255]));
+            }
+        }
+    }
+}
+
+/// Fills a rectangle of the contact sheet with a colour, the part outside of the sheet being ignored.
+///
+/// ### Parameters
+/// - `sheet`: The contact sheet.
+/// - `x`: The left of the rectangle.
+/// - `y`: The top of the rectangle.
+/// - `width`: The width of the rectangle.
+/// - `height`: The height of the rectangle.
+/// - `colour`: The colour.
+fn fill(sheet: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, colour: Rgba<u8>) {
+    for top in y..(y + height).min(sheet.height()) {
+        for left in x..(x + width).min(sheet.width()) {
+            sheet.put_pixel(left, top, colour);
+        }
+    }
+}
+
+/// Gets the sentence telling the model how to read a contact sheet, added at the end of the prompts sending one.
+///
+/// ### Parameters
+/// - `frame_count`: The number of frames of the contact sheet.
+///
+/// ### Returns
+/// The sentence, starting with a space.
+pub(crate) fn get_prompt(frame_count: usize) -> String {
+    format!(
+        " The frames are given as a single contact sheet of {} frames, to be read from left to right and from top to bottom, each frame being labelled at its top left corner with its time in the video, or with its number when its time is unknown.",
+        frame_count
+    )
+}
+
+/// Gets the path of the preview of a video, next to the video, e.g. `birthday.mp4.contact.jpg`.
+///
+/// ### Parameters
+/// - `video_path`: The path of the video.
+///
+/// ### Returns
+/// The path of the preview.
+pub(crate) fn get_preview_path(video_path: &str) -> String {
+    format!("{}.contact.jpg", video_path)
+}
+
+/// Saves the contact sheet of a video next to it as a preview, replacing the previous one if any.
+///
+/// ### Parameters
+/// - `video`: The video, with its contact sheet.
+///
+/// ### Returns
+/// A Result containing the path of the preview.
+///
+/// ### Errors
+/// Returns an error if the preview can't be written.
+pub(crate) fn save_preview(video: &Video) -> anyhow::Result<String> {
+    let path = get_preview_path(video.path.as_str());
+    fs::write(&path, &video.contact_sheet)?;
+    Ok(path)
+}
@@ -0,0 +1,182 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use serde_json::Value;
+
+use super::aspargus_helper::VideoDataError;
+use super::video::Video;
+
+/// Writes the resume of a video into the metadata of its container, so that other tools such as photo managers can pick it up.
+/// FFmpeg copies the streams into a temporary file next to the video with the new metadata, which then atomically replaces the video.
+/// The modification time of the video is preserved.
+///
+/// ### Parameters
+/// - `video`: The analysed video.
+///
+/// ### Returns
+/// An empty Result in case of success.
+///
+/// ### Errors
+/// Returns an error if the video has no extension, if FFmpeg can't be run (e.g. not in the path) or fails, or if the video can't be replaced.
+pub(crate) fn write_metadata_to_video(video: &Video) -> anyhow::Result<()> {
+    let path = Path::new(video.path.as_str());
+    let extension = path
+        .extension()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default()
+        .to_lowercase();
+    // FFmpeg guesses the format of the temporary file from its extension
+    if extension.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} has no extension, FFmpeg can't tell the format to write its metadata in",
+            video.path
+        ));
+    }
+    let temp_path = path.with_file_name(format!(
+        ".{}.aspargus.{}",
+        path.file_stem().unwrap_or_default().to_str().unwrap_or_default(),
+        extension
+    ));
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .arg("-y")
+        .arg("-i")
+        .arg(video.path.as_str())
+        .arg("-map")
+        .arg("0")
+        .arg("-map_metadata")
+        .arg("0")
+        .arg("-c")
+        .arg("copy")
+        .arg("-ignore_unknown");
+    if matches!(extension.as_str(), "mp4" | "m4v" | "mov") {
+        // Writes the tags as QuickTime metadata keys, as the standard MP4 atoms have no keywords
+        command.arg("-movflags").arg("use_metadata_tags");
+    }
+    for (key, value) in get_tags(extension.as_str(), video) {
+        command.arg("-metadata").arg(format!("{}={}", key, value));
+    }
+    let status = command
+        .arg(temp_path.as_os_str())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(_) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(anyhow::anyhow!(
+                "FFmpeg couldn't write the metadata of: {}",
+                video.path
+            ));
+        }
+        Err(error) => {
+            let _ = fs::remove_file(&temp_path);
+            if error.kind() == ErrorKind::NotFound {
+                let error_message = "FFMpeg can't be found, we're stopping here. Please install FFMpeg and FFProbe and make sure they're in the path.".to_string();
+                return Err(VideoDataError::FFMpegNotFoundError(error_message).into());
+            } else {
+                return Err(anyhow::anyhow!("Couldn't run FFmpeg for file {}", video.path));
+            }
+        }
+    }
+
+    if let Some(modified) = modified {
+        if let Err(error) = fs::File::options()
+            .write(true)
+            .open(&temp_path)
+            .and_then(|file| file.set_modified(modified))
+        {
+            log::warn!("Could not preserve the modification time of {}: {}", video.path, error);
+        }
+    }
+    if let Err(error) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(anyhow::anyhow!(
+            "Could not replace {} with its new metadata: {}",
+            video.path,
+            error
+        ));
+    }
+    Ok(())
+}
+
+/// Gets the tags holding the resume of a video in its container.
+///
+/// ### Parameters
+/// - `extension`: The lowercase extension of the video.
+/// - `video`: The analysed video.
+///
+/// ### Returns
+/// The names and values of the tags, the keywords being only written in the containers that have a tag for them.
+fn get_tags(extension: &str, video: &Video) -> Vec<(&'static str, String)> {
+    let keywords = video.resume.keywords.join(", ");
+    match extension {
+        "mp4" | "m4v" | "mov" => vec![
+            ("title", video.resume.title.clone()),
+            ("comment", video.resume.description.clone()),
+            ("description", video.resume.description.clone()),
+            ("keywords", keywords),
+        ],
+        "mkv" | "webm" => vec![
+            ("title", video.resume.title.clone()),
+            ("DESCRIPTION", video.resume.description.clone()),
+            ("KEYWORDS", keywords),
+        ],
+        _ => vec![
+            ("title", video.resume.title.clone()),
+            ("comment", video.resume.description.clone()),
+        ],
+    }
+}
+
+/// Checks if the container of a video already holds its resume, e.g. written by a previous run, so that it is not written again.
+///
+/// ### Parameters
+/// - `video`: The analysed video.
+///
+/// ### Returns
+/// True if all the tags of the resume have the expected values, false if they differ or can't be read.
+pub(crate) fn has_metadata(video: &Video) -> bool {
+    let extension = Path::new(video.path.as_str())
+        .extension()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default()
+        .to_lowercase();
+    let Ok(output) = Command::new("ffprobe")
+        .arg("-v")
+        .arg("error")
+        .arg("-show_entries")
+        .arg("format_tags")
+        .arg("-of")
+        .arg("json")
+        .arg(video.path.as_str())
+        .output()
+    else {
+        return false;
+    };
+    let Ok(value) = serde_json::from_slice::<Value>(&output.stdout) else {
+        return false;
+    };
+    // The tags are matched without case, as their case depends on the container
+    let tags: HashMap<String, &str> = value["format"]["tags"]
+        .as_object()
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|(key, value)| value.as_str().map(|value| (key.to_lowercase(), value.trim())))
+                .collect()
+        })
+        .unwrap_or_default();
+    get_tags(extension.as_str(), video)
+        .iter()
+        .all(|(key, value)| tags.get(&key.to_lowercase()) == Some(&value.trim()))
+}
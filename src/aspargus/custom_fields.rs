@@ -0,0 +1,243 @@
+use std::{collections::BTreeMap, fmt};
+
+use schemars::gen::SchemaSettings;
+use schemars::schema::{RootSchema, Schema};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::video::Resume;
+
+/// The names that can't be used by the custom fields, as the resume already has them.
+const RESERVED_NAMES: &[&str] = &["title", "description", "keywords", "language", "translations"];
+
+/// Represents the type of the value of a custom field.
+/// ### Variants
+/// - `Text`: A free text, e.g. the event of the video.
+/// - `Number`: A number, e.g. the number of people in the video.
+/// - `Boolean`: True or false, e.g. if the video was shot indoor.
+/// - `Choice`: One of the given `values`, e.g. the season of the video.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FieldType {
+    Text,
+    Number,
+    Boolean,
+    Choice { values: Vec<String> },
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::Text => write!(f, "a text"),
+            FieldType::Number => write!(f, "a number"),
+            FieldType::Boolean => write!(f, "true or false"),
+            FieldType::Choice { values } => write!(
+                f,
+                "one of {}",
+                values
+                    .iter()
+                    .map(|value| format!("\"{}\"", value))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// Represents a field generated by the models in addition to the title, description and keywords, defined in the settings.
+/// ### Fields
+/// - `name`: The name of the field in the JSON results, e.g. `people_count`.
+/// - `description`: What the field is about, given to the models, e.g. `the number of people in the video`.
+/// - `kind`: The type of the value of the field.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct CustomField {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    #[serde(flatten)]
+    pub kind: FieldType,
+}
+
+impl CustomField {
+    /// Checks if a value suits the field.
+    ///
+    /// ### Parameters
+    /// - `value`: The value generated by a model.
+    ///
+    /// ### Returns
+    /// True if the value has the type of the field.
+    fn accepts(&self, value: &Value) -> bool {
+        match &self.kind {
+            FieldType::Text => value.is_string(),
+            FieldType::Number => value.is_number(),
+            FieldType::Boolean => value.is_boolean(),
+            FieldType::Choice { values } => value
+                .as_str()
+                .is_some_and(|value| values.iter().any(|choice| choice == value)),
+        }
+    }
+
+    /// Gets the JSON schema of the value of the field.
+    ///
+    /// ### Returns
+    /// The JSON schema of the field.
+    fn schema(&self) -> Schema {
+        let mut schema = match &self.kind {
+            FieldType::Text => serde_json::json!({"type": "string"}),
+            FieldType::Number => serde_json::json!({"type": "number"}),
+            FieldType::Boolean => serde_json::json!({"type": "boolean"}),
+            FieldType::Choice { values } => serde_json::json!({"type": "string", "enum": values}),
+        };
+        if !self.description.is_empty() {
+            schema["description"] = Value::from(self.description.as_str());
+        }
+        serde_json::from_value(schema).unwrap_or(Schema::Bool(true))
+    }
+}
+
+/// Checks that the custom fields defined in the settings are valid.
+///
+/// ### Parameters
+/// - `fields`: The custom fields.
+///
+/// ### Errors
+/// Returns an error with the first invalid field: a name that is empty, not made of lowercase letters, digits and underscores, already used, or a choice without values.
+pub fn check_fields(fields: &[CustomField]) -> Result<(), String> {
+    for (index, field) in fields.iter().enumerate() {
+        if field.name.is_empty()
+            || !field
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        {
+            return Err(format!(
+                "Invalid custom field name: \"{}\", only lowercase letters, digits and underscores are allowed",
+                field.name
+            ));
+        }
+        if RESERVED_NAMES.contains(&field.name.as_str())
+            || fields[..index].iter().any(|other| other.name == field.name)
+        {
+            return Err(format!("The custom field name {} is already used", field.name));
+        }
+        if matches!(&field.kind, FieldType::Choice { values } if values.is_empty()) {
+            return Err(format!("The custom field {} has no values to choose from", field.name));
+        }
+    }
+    Ok(())
+}
+
+/// Makes the JSON schema of the resume with the custom fields, given to the models as structured output format.
+///
+/// ### Parameters
+/// - `fields`: The custom fields.
+/// - `max_keywords`: The maximum number of keywords.
+///
+/// ### Returns
+/// The JSON schema of the resume.
+pub fn make_schema(fields: &[CustomField], max_keywords: usize) -> RootSchema {
+    // Ollama doesn't support the references in the schemas
+    let mut settings = SchemaSettings::draft07();
+    settings.inline_subschemas = true;
+    let mut schema = settings.into_generator().into_root_schema_for::<Resume>();
+    // The documentation of the resume is not meant for the models
+    schema.schema.metadata().description = None;
+    let object = schema.schema.object();
+    if let Some(Schema::Object(keywords)) = object.properties.get_mut("keywords") {
+        keywords.array().max_items = u32::try_from(max_keywords).ok();
+    }
+    for field in fields {
+        object.properties.insert(field.name.clone(), field.schema());
+        object.required.insert(field.name.clone());
+    }
+    schema
+}
+
+/// Gets the part of the prompts asking for the custom fields.
+///
+/// ### Parameters
+/// - `fields`: The custom fields.
+///
+/// ### Returns
+/// The part of the prompt, empty if there are no custom fields.
+pub fn get_prompt(fields: &[CustomField]) -> String {
+    if fields.is_empty() {
+        return String::default();
+    }
+    let fields = fields
+        .iter()
+        .map(|field| {
+            if field.description.is_empty() {
+                format!("\"{}\" ({})", field.name, field.kind)
+            } else {
+                format!("\"{}\", {} ({})", field.name, field.description, field.kind)
+            }
+        })
+        .collect::<Vec<_>>();
+    format!(" Please also add the following fields to the json: {}.", fields.join("; "))
+}
+
+/// Checks that the custom fields of a resume were generated with the right type.
+///
+/// ### Parameters
+/// - `values`: The values of the custom fields of the resume.
+/// - `fields`: The custom fields.
+///
+/// ### Returns
+/// The problems of the custom fields, empty if they are valid.
+pub fn check_values(values: &BTreeMap<String, Value>, fields: &[CustomField]) -> Vec<String> {
+    fields
+        .iter()
+        .filter_map(|field| match values.get(&field.name) {
+            None => Some(format!("the field {} is missing", field.name)),
+            Some(value) if !field.accepts(value) => {
+                Some(format!("the field {} must be {}", field.name, field.kind))
+            }
+            Some(_) => None,
+        })
+        .collect()
+}
+
+/// Removes the values of a resume that are not custom fields, and sets the custom fields that are missing or don't have the right type to null.
+///
+/// ### Parameters
+/// - `values`: The values of the custom fields of the resume.
+/// - `fields`: The custom fields.
+pub fn clean_values(values: &mut BTreeMap<String, Value>, fields: &[CustomField]) {
+    values.retain(|name, _| fields.iter().any(|field| field.name == *name));
+    for field in fields {
+        let value = values.entry(field.name.clone()).or_insert(Value::Null);
+        if !field.accepts(value) {
+            *value = Value::Null;
+        }
+    }
+}
+
+/// Formats the value of a custom field for a file name.
+///
+/// ### Parameters
+/// - `name`: The name of the custom field.
+/// - `value`: The value of the custom field.
+///
+/// ### Returns
+/// The value as text, the name of the field for true and empty for false, so that `[_%{indoor}V]` only adds `_indoor` to the indoor videos.
+pub fn format_value(name: &str, value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Bool(true) => name.to_string(),
+        Value::Bool(false) | Value::Null => String::default(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_the_keywords_in_the_schema() {
+        let schema = serde_json::to_value(make_schema(&[], 3)).unwrap();
+        assert_eq!(schema["properties"]["keywords"]["maxItems"], 3);
+        assert_eq!(schema["properties"]["keywords"]["type"], "array");
+    }
+}
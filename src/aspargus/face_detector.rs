@@ -0,0 +1,477 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, Rgb, RgbImage};
+
+use super::faces::Face;
+
+/// The maximum width and height of the images the faces are searched in, the larger images being shrunk first.
+const DETECTION_EDGE: u32 = 320;
+/// The minimum width and height of a face in the shrunk images, in pixels.
+const MIN_FACE_EDGE: u32 = 12;
+/// The minimum part of the image covered by a face.
+const MIN_FACE_AREA: f32 = 0.003;
+/// The minimum part of the upper half of a face that is not skin, i.e. its eyes and eyebrows.
+const MIN_FEATURES_AREA: f32 = 0.005;
+/// The width and height of the faces once normalised, before computing their embedding.
+const FACE_EDGE: u32 = 64;
+/// The width and height of the thumbnail of a face giving its shape.
+const THUMBNAIL_EDGE: u32 = 16;
+/// The number of cells along each side of a face, each cell giving its own histogram of patterns.
+const GRID_CELLS: u32 = 4;
+/// The number of uniform local binary patterns, plus one bin for all the others.
+const PATTERN_BINS: usize = 59;
+
+/// Represents the box of a face in an image.
+/// ### Fields
+/// - `x`: The left of the box in pixels.
+/// - `y`: The top of the box in pixels.
+/// - `width`: The width of the box in pixels.
+/// - `height`: The height of the box in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FaceBox {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Represents a group of connected skin pixels.
+/// ### Fields
+/// - `label`: The label of the pixels of the group.
+/// - `min_x`: The left of the group.
+/// - `min_y`: The top of the group.
+/// - `max_x`: The right of the group, included.
+/// - `max_y`: The bottom of the group, included.
+/// - `area`: The number of pixels of the group.
+struct SkinRegion {
+    label: u32,
+    min_x: u32,
+    min_y: u32,
+    max_x: u32,
+    max_y: u32,
+    area: u32,
+}
+
+impl SkinRegion {
+    fn width(&self) -> u32 {
+        self.max_x - self.min_x + 1
+    }
+
+    fn height(&self) -> u32 {
+        self.max_y - self.min_y + 1
+    }
+}
+
+/// Detects the faces of an image with the built-in detector, running on the CPU without any model: the faces are the regions of skin shaped like a face and with eyes, and their embedding is made of the histograms of the local binary patterns of their grey levels.
+/// It is much less accurate than a face recognition model, which can be used through a plug-in instead (see `FaceBackend`).
+///
+/// ### Parameters
+/// - `image_path`: The path of the image.
+///
+/// ### Returns
+/// A Result containing the faces found in the image.
+///
+/// ### Errors
+/// Returns an error if the image can't be read.
+pub(crate) fn detect_faces(image_path: &str) -> anyhow::Result<Vec<Face>> {
+    let image = image::open(image_path)?;
+    Ok(detect_faces_in_image(&image))
+}
+
+/// Detects the faces of a decoded image with the built-in detector.
+///
+/// ### Parameters
+/// - `image`: The image.
+///
+/// ### Returns
+/// The faces found in the image.
+fn detect_faces_in_image(image: &DynamicImage) -> Vec<Face> {
+    let grey = image.to_luma8();
+    find_face_boxes(&image.to_rgb8())
+        .iter()
+        .map(|face_box| Face {
+            embedding: compute_embedding(&grey, face_box),
+        })
+        .collect()
+}
+
+/// Checks if the colour of a pixel is a skin tone, from its chrominance.
+///
+/// ### Parameters
+/// - `pixel`: The pixel.
+///
+/// ### Returns
+/// `true` if the pixel looks like skin.
+fn is_skin(pixel: &Rgb<u8>) -> bool {
+    let [red, green, blue] = pixel.0.map(f32::from);
+    let luma = 0.299 * red + 0.587 * green + 0.114 * blue;
+    let blue_difference = 128.0 - 0.168736 * red - 0.331264 * green + 0.5 * blue;
+    let red_difference = 128.0 + 0.5 * red - 0.418688 * green - 0.081312 * blue;
+    luma > 40.0 && (77.0..=127.0).contains(&blue_difference) && (133.0..=173.0).contains(&red_difference)
+}
+
+/// Finds the boxes of the faces of an image.
+///
+/// ### Parameters
+/// - `image`: The image.
+///
+/// ### Returns
+/// The boxes of the faces, in the coordinates of the image.
+fn find_face_boxes(image: &RgbImage) -> Vec<FaceBox> {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let scale = (DETECTION_EDGE as f32 / width.max(height) as f32).min(1.0);
+    let small_width = ((width as f32 * scale).round() as u32).max(1);
+    let small_height = ((height as f32 * scale).round() as u32).max(1);
+    let small = if scale < 1.0 {
+        imageops::resize(image, small_width, small_height, FilterType::Triangle)
+    } else {
+        image.clone()
+    };
+    let mask: Vec<bool> = small.pixels().map(is_skin).collect();
+    // An erosion followed by a dilation removes the isolated skin pixels and thin bridges between regions
+    let mask = morph(&morph(&mask, small_width, small_height, false), small_width, small_height, true);
+    let (labels, regions) = label_regions(&mask, small_width, small_height);
+    regions
+        .iter()
+        .filter_map(|region| get_face_box(region, &labels, small_width, small_height))
+        .map(|face_box| {
+            let x = ((face_box.x as f32 / scale) as u32).min(width - 1);
+            let y = ((face_box.y as f32 / scale) as u32).min(height - 1);
+            FaceBox {
+                x,
+                y,
+                width: ((face_box.width as f32 / scale) as u32).clamp(1, width - x),
+                height: ((face_box.height as f32 / scale) as u32).clamp(1, height - y),
+            }
+        })
+        .collect()
+}
+
+/// Erodes or dilates a mask with a 3x3 square.
+///
+/// ### Parameters
+/// - `mask`: The mask, row by row.
+/// - `width`: The width of the mask.
+/// - `height`: The height of the mask.
+/// - `dilate`: Flag to dilate the mask, or to erode it.
+///
+/// ### Returns
+/// The new mask.
+fn morph(mask: &[bool], width: u32, height: u32, dilate: bool) -> Vec<bool> {
+    let (width, height) = (width as i64, height as i64);
+    let mut result = vec![false; mask.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let mut neighbours = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)));
+            let value = |(nx, ny): (i64, i64)| {
+                nx >= 0 && ny >= 0 && nx < width && ny < height && mask[(ny * width + nx) as usize]
+            };
+            result[(y * width + x) as usize] = if dilate {
+                neighbours.any(value)
+            } else {
+                neighbours.all(value)
+            };
+        }
+    }
+    result
+}
+
+/// Labels the groups of connected pixels of a mask.
+///
+/// ### Parameters
+/// - `mask`: The mask, row by row.
+/// - `width`: The width of the mask.
+/// - `height`: The height of the mask.
+///
+/// ### Returns
+/// The label of each pixel, 0 outside the mask, and the groups of pixels.
+fn label_regions(mask: &[bool], width: u32, height: u32) -> (Vec<u32>, Vec<SkinRegion>) {
+    let mut labels = vec![0; mask.len()];
+    let mut regions = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..mask.len() {
+        if !mask[start] || labels[start] != 0 {
+            continue;
+        }
+        let label = regions.len() as u32 + 1;
+        let (x, y) = ((start as u32) % width, (start as u32) / width);
+        let mut region = SkinRegion {
+            label,
+            min_x: x,
+            min_y: y,
+            max_x: x,
+            max_y: y,
+            area: 0,
+        };
+        labels[start] = label;
+        stack.push(start);
+        while let Some(index) = stack.pop() {
+            let (x, y) = ((index as u32) % width, (index as u32) / width);
+            region.min_x = region.min_x.min(x);
+            region.min_y = region.min_y.min(y);
+            region.max_x = region.max_x.max(x);
+            region.max_y = region.max_y.max(y);
+            region.area += 1;
+            let neighbours = [
+                (x > 0).then(|| index - 1),
+                (x + 1 < width).then(|| index + 1),
+                (y > 0).then(|| index - width as usize),
+                (y + 1 < height).then(|| index + width as usize),
+            ];
+            for neighbour in neighbours.into_iter().flatten() {
+                if mask[neighbour] && labels[neighbour] == 0 {
+                    labels[neighbour] = label;
+                    stack.push(neighbour);
+                }
+            }
+        }
+        regions.push(region);
+    }
+    (labels, regions)
+}
+
+/// Gets the box of the face of a region of skin, if the region is shaped like a face and has eyes.
+///
+/// ### Parameters
+/// - `region`: The region of skin.
+/// - `labels`: The label of each pixel of the image.
+/// - `width`: The width of the image.
+/// - `height`: The height of the image.
+///
+/// ### Returns
+/// The box of the face, None if the region is not a face.
+fn get_face_box(region: &SkinRegion, labels: &[u32], width: u32, height: u32) -> Option<FaceBox> {
+    if region.width() < MIN_FACE_EDGE
+        || region.height() < MIN_FACE_EDGE
+        || (region.area as f32) < MIN_FACE_AREA * (width * height) as f32
+    {
+        return None;
+    }
+    // The neck and the shoulders below the face are left out
+    let face_height = region.height().min(region.width() * 5 / 4);
+    let face_box = FaceBox {
+        x: region.min_x,
+        y: region.min_y,
+        width: region.width(),
+        height: face_height,
+    };
+    let ratio = face_box.height as f32 / face_box.width as f32;
+    if !(0.9..=1.6).contains(&ratio) {
+        return None;
+    }
+    let mut skin = 0;
+    let mut features = 0;
+    for y in face_box.y..face_box.y + face_box.height {
+        let row = &labels[(y * width) as usize..((y + 1) * width) as usize];
+        let columns = &row[face_box.x as usize..(face_box.x + face_box.width) as usize];
+        let in_region = |label: &u32| *label == region.label;
+        skin += columns.iter().filter(|label| in_region(label)).count();
+        // The eyes and the eyebrows are holes in the skin, between the top fifth and the middle of the face
+        if y < face_box.y + face_box.height / 5 || y > face_box.y + face_box.height / 2 {
+            continue;
+        }
+        if let (Some(left), Some(right)) = (columns.iter().position(in_region), columns.iter().rposition(in_region)) {
+            features += columns[left..=right].iter().filter(|label| !in_region(label)).count();
+        }
+    }
+    let area = (face_box.width * face_box.height) as f32;
+    let fill = skin as f32 / area;
+    if !(0.4..=0.95).contains(&fill) || (features as f32) < MIN_FEATURES_AREA * area {
+        return None;
+    }
+    Some(face_box)
+}
+
+/// Computes the embedding of a face, once its size and contrast are normalised: the grey levels of the face shrunk to a thumbnail give its shape, and the histograms of the uniform local binary patterns of a grid of cells over the face give its texture.
+///
+/// ### Parameters
+/// - `grey`: The image in grey levels.
+/// - `face_box`: The box of the face.
+///
+/// ### Returns
+/// The embedding of the face.
+fn compute_embedding(grey: &GrayImage, face_box: &FaceBox) -> Vec<f32> {
+    let face = imageops::crop_imm(grey, face_box.x, face_box.y, face_box.width, face_box.height).to_image();
+    let face = equalise(&imageops::resize(&face, FACE_EDGE, FACE_EDGE, FilterType::Triangle));
+    let thumbnail = imageops::resize(&face, THUMBNAIL_EDGE, THUMBNAIL_EDGE, FilterType::Triangle);
+    let mut shape: Vec<f32> = thumbnail.pixels().map(|pixel| f32::from(pixel[0])).collect();
+    centre_and_normalise(&mut shape);
+    let bins = get_pattern_bins();
+    let cell_edge = FACE_EDGE / GRID_CELLS;
+    let mut texture = vec![0.0; (GRID_CELLS * GRID_CELLS) as usize * PATTERN_BINS];
+    for y in 1..FACE_EDGE - 1 {
+        for x in 1..FACE_EDGE - 1 {
+            let center = face.get_pixel(x, y)[0];
+            let neighbours = [(-1, -1), (0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0)];
+            let pattern = neighbours.iter().enumerate().fold(0u8, |pattern, (bit, (dx, dy))| {
+                let neighbour = face.get_pixel((x as i32 + dx) as u32, (y as i32 + dy) as u32)[0];
+                pattern | (u8::from(neighbour >= center) << bit)
+            });
+            let cell = (y / cell_edge) * GRID_CELLS + x / cell_edge;
+            texture[cell as usize * PATTERN_BINS + bins[pattern as usize]] += 1.0;
+        }
+    }
+    // The square roots of the frequencies compare better than the frequencies
+    for cell in texture.chunks_mut(PATTERN_BINS) {
+        let total: f32 = cell.iter().sum();
+        if total > 0.0 {
+            cell.iter_mut().for_each(|value| *value = (*value / total).sqrt());
+        }
+    }
+    centre_and_normalise(&mut texture);
+    shape.extend(texture);
+    shape
+}
+
+/// Removes the mean of a vector and normalises it to a length of 1, so that only what differs between the faces is compared.
+///
+/// ### Parameters
+/// - `vector`: The vector.
+fn centre_and_normalise(vector: &mut [f32]) {
+    let mean = vector.iter().sum::<f32>() / vector.len().max(1) as f32;
+    vector.iter_mut().for_each(|value| *value -= mean);
+    let length = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if length > 0.0 {
+        vector.iter_mut().for_each(|value| *value /= length);
+    }
+}
+
+/// Equalises the histogram of an image in grey levels, so that the faces are compared whatever their lighting.
+///
+/// ### Parameters
+/// - `image`: The image.
+///
+/// ### Returns
+/// The equalised image.
+fn equalise(image: &GrayImage) -> GrayImage {
+    let mut histogram = [0u32; 256];
+    image.pixels().for_each(|pixel| histogram[pixel[0] as usize] += 1);
+    let total = image.pixels().len() as f32;
+    let mut cumulated = 0;
+    let mut levels = [0u8; 256];
+    for (level, count) in histogram.iter().enumerate() {
+        cumulated += count;
+        levels[level] = (255.0 * cumulated as f32 / total).round() as u8;
+    }
+    let mut equalised = image.clone();
+    equalised.pixels_mut().for_each(|pixel| pixel[0] = levels[pixel[0] as usize]);
+    equalised
+}
+
+/// Gets the histogram bin of each local binary pattern: the 58 uniform patterns, with at most two changes between 0 and 1 around the pixel, get their own bin, and all the others share the last one.
+///
+/// ### Returns
+/// The bin of each pattern.
+fn get_pattern_bins() -> [usize; 256] {
+    let mut bins = [PATTERN_BINS - 1; 256];
+    let mut next = 0;
+    for (pattern, bin) in bins.iter_mut().enumerate() {
+        let pattern = pattern as u8;
+        if (pattern ^ pattern.rotate_right(1)).count_ones() <= 2 {
+            *bin = next;
+            next += 1;
+        }
+    }
+    bins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKIN: Rgb<u8> = Rgb([224, 172, 138]);
+    const BACKGROUND: Rgb<u8> = Rgb([40, 90, 160]);
+    const DARK: Rgb<u8> = Rgb([40, 30, 30]);
+
+    /// Draws a filled ellipse.
+    fn draw_ellipse(image: &mut RgbImage, center: (f32, f32), radii: (f32, f32), colour: Rgb<u8>) {
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let dx = (x as f32 - center.0) / radii.0;
+            let dy = (y as f32 - center.1) / radii.1;
+            if dx * dx + dy * dy <= 1.0 {
+                *pixel = colour;
+            }
+        }
+    }
+
+    /// Draws a simple face, with its eyes and mouth, in an image.
+    fn draw_face(image: &mut RgbImage, center: (f32, f32), size: f32) {
+        draw_face_with_features(image, center, size, 0.15, 0.12);
+    }
+
+    /// Draws a face with some space between its eyes and some width of its mouth, in an image.
+    fn draw_face_with_features(image: &mut RgbImage, center: (f32, f32), size: f32, eyes: f32, mouth: f32) {
+        draw_ellipse(image, center, (size * 0.4, size * 0.5), SKIN);
+        draw_ellipse(image, (center.0 - size * eyes, center.1 - size * 0.12), (size * 0.07, size * 0.04), DARK);
+        draw_ellipse(image, (center.0 + size * eyes, center.1 - size * 0.12), (size * 0.07, size * 0.04), DARK);
+        draw_ellipse(image, (center.0, center.1 + size * 0.25), (size * mouth, size * 0.03), DARK);
+    }
+
+    /// Computes the cosine similarity of two embeddings.
+    fn similarity(a: &[f32], b: &[f32]) -> f32 {
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+        dot(a, b) / (dot(a, a).sqrt() * dot(b, b).sqrt())
+    }
+
+    #[test]
+    fn finds_a_face_with_eyes() {
+        let mut image = RgbImage::from_pixel(320, 240, BACKGROUND);
+        draw_face(&mut image, (160.0, 120.0), 100.0);
+        let boxes = find_face_boxes(&image);
+        assert_eq!(boxes.len(), 1);
+        assert!((boxes[0].x as i32 - 120).abs() <= 2);
+        assert!((boxes[0].y as i32 - 70).abs() <= 2);
+        assert!((boxes[0].width as i32 - 80).abs() <= 3);
+    }
+
+    #[test]
+    fn ignores_skin_without_eyes_and_images_without_skin() {
+        let mut image = RgbImage::from_pixel(320, 240, BACKGROUND);
+        assert!(find_face_boxes(&image).is_empty());
+        draw_ellipse(&mut image, (160.0, 120.0), (40.0, 50.0), SKIN);
+        assert!(find_face_boxes(&image).is_empty());
+        // A wall of the colour of the skin is not a face either
+        let wall = RgbImage::from_pixel(320, 240, SKIN);
+        assert!(find_face_boxes(&wall).is_empty());
+    }
+
+    #[test]
+    fn finds_the_faces_of_a_large_image() {
+        let mut image = RgbImage::from_pixel(1280, 720, BACKGROUND);
+        draw_face(&mut image, (300.0, 360.0), 300.0);
+        draw_face(&mut image, (900.0, 360.0), 240.0);
+        let faces = detect_faces_in_image(&DynamicImage::ImageRgb8(image));
+        assert_eq!(faces.len(), 2);
+        assert_eq!(
+            faces[0].embedding.len(),
+            (THUMBNAIL_EDGE * THUMBNAIL_EDGE) as usize + (GRID_CELLS * GRID_CELLS) as usize * PATTERN_BINS
+        );
+    }
+
+    #[test]
+    fn gives_closer_embeddings_to_the_same_face_than_to_another_face() {
+        let mut first = RgbImage::from_pixel(320, 240, BACKGROUND);
+        draw_face(&mut first, (100.0, 120.0), 100.0);
+        let mut same = RgbImage::from_pixel(640, 480, BACKGROUND);
+        draw_face(&mut same, (400.0, 200.0), 160.0);
+        let mut other = RgbImage::from_pixel(320, 240, BACKGROUND);
+        draw_face_with_features(&mut other, (100.0, 120.0), 100.0, 0.25, 0.2);
+        let first = detect_faces_in_image(&DynamicImage::ImageRgb8(first));
+        let same = detect_faces_in_image(&DynamicImage::ImageRgb8(same));
+        let other = detect_faces_in_image(&DynamicImage::ImageRgb8(other));
+        let same_similarity = similarity(&first[0].embedding, &same[0].embedding);
+        assert!(same_similarity > 0.95);
+        assert!(same_similarity > similarity(&first[0].embedding, &other[0].embedding));
+    }
+
+    #[test]
+    fn has_a_bin_for_each_uniform_pattern() {
+        let bins = get_pattern_bins();
+        assert_eq!(bins[0], 0);
+        assert_eq!(bins[0b0000_0001], 1);
+        assert_eq!(bins[0b0101_0101], PATTERN_BINS - 1);
+        assert_eq!(bins.iter().filter(|bin| **bin < PATTERN_BINS - 1).count(), PATTERN_BINS - 1);
+    }
+}
@@ -0,0 +1,499 @@
+use std::{
+    fmt, fs,
+    path::PathBuf,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::face_detector;
+
+/// The default minimum cosine similarity between two faces of the same person, for the embeddings of a face recognition model.
+pub const DEFAULT_FACE_THRESHOLD: f32 = 0.5;
+/// The default minimum cosine similarity between two faces of the same person, for the embeddings of the built-in detector.
+const BUILTIN_FACE_THRESHOLD: f32 = 0.9;
+
+/// Represents a face detection and embedding backend, run on the CPU.
+/// The faces are detected by the built-in detector, or by a plug-in, an external program chosen by the user (see `FaceBackend`).
+pub trait FaceDetector {
+    /// Detects the faces of an image, and computes an embedding for each of them.
+    ///
+    /// ### Parameters
+    /// - `image_path`: The path of the image, a thumbnail of a video.
+    ///
+    /// ### Returns
+    /// A Result containing the faces found in the image.
+    ///
+    /// ### Errors
+    /// Returns an error if the backend can't be run or fails.
+    fn detect(&self, image_path: &str) -> anyhow::Result<Vec<Face>>;
+}
+
+/// Represents the configuration of the face detection backend.
+/// ### Variants
+/// - `Builtin`: The built-in detector, needing no model: it finds the regions of skin shaped like a face and with eyes, and compares the textures of the faces. It is quick but much less accurate than a face recognition model.
+/// - `Plugin`: A face detection plug-in, any command run once per image, where `{image}` is replaced by the path of the image, and printing the faces of the image as a JSON array on its standard output, e.g. `[{"embedding": [0.12, -0.03, ...]}]`. Its arguments are split like a shell would.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum FaceBackend {
+    Builtin,
+    #[serde(alias = "command")]
+    Plugin { command: String },
+}
+
+impl fmt::Display for FaceBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaceBackend::Builtin => write!(f, "builtin"),
+            FaceBackend::Plugin { command } => write!(f, "plugin:{}", command),
+        }
+    }
+}
+
+impl FaceBackend {
+    /// Gets the default minimum cosine similarity between two faces of the same person for the backend, as the embeddings of the built-in detector are much closer to each other than those of a face recognition model.
+    ///
+    /// ### Returns
+    /// The default minimum cosine similarity.
+    pub fn get_default_threshold(&self) -> f32 {
+        match self {
+            FaceBackend::Builtin => BUILTIN_FACE_THRESHOLD,
+            FaceBackend::Plugin { .. } => DEFAULT_FACE_THRESHOLD,
+        }
+    }
+}
+
+impl FromStr for FaceBackend {
+    type Err = String;
+
+    /// Parses a face detection backend written as `builtin` or `plugin:<COMMAND>`, or `command:<COMMAND>` as it was first named.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.trim() == "builtin" {
+            return Ok(FaceBackend::Builtin);
+        }
+        match value.split_once(':') {
+            Some(("plugin" | "command", command))
+                if command.contains("{image}") && shlex::split(command).is_some() =>
+            {
+                Ok(FaceBackend::Plugin {
+                    command: command.trim().to_string(),
+                })
+            }
+            _ => Err(format!(
+                "Invalid face detection backend: {}, expected builtin, plugin:<COMMAND> with an {{image}} argument and balanced quotes, or none",
+                value
+            )),
+        }
+    }
+}
+
+impl FaceDetector for FaceBackend {
+    fn detect(&self, image_path: &str) -> anyhow::Result<Vec<Face>> {
+        let mut command = match self {
+            FaceBackend::Builtin => return face_detector::detect_faces(image_path),
+            FaceBackend::Plugin { command } => {
+                // The arguments are split like a shell would, so that quoted paths can contain spaces
+                let Some(parts) = shlex::split(command) else {
+                    return Err(anyhow::anyhow!("Invalid quotes in the face detection plug-in: {}", command));
+                };
+                let mut parts = parts.into_iter();
+                let Some(program) = parts.next() else {
+                    return Err(anyhow::anyhow!("Empty face detection plug-in"));
+                };
+                let mut the_command = Command::new(program);
+                the_command.args(parts.map(|part| part.replace("{image}", image_path)));
+                the_command
+            }
+        };
+        let output = command.stderr(Stdio::null()).output()?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "The face detection plug-in failed for: {}",
+                image_path
+            ));
+        }
+        let faces: Vec<Face> = serde_json::from_slice(&output.stdout)?;
+        Ok(faces
+            .into_iter()
+            .filter(|face| !face.embedding.is_empty())
+            .collect())
+    }
+}
+
+/// Represents a face found in an image.
+/// ### Fields
+/// - `embedding`: The embedding of the face, close to the embeddings of the same person.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Face {
+    pub embedding: Vec<f32>,
+}
+
+/// Represents a group of faces of the same person, across all the analysed videos.
+/// ### Fields
+/// - `id`: The ID of the cluster.
+/// - `name`: The name of the person, empty until it is given by the user.
+/// - `centroid`: The normalised mean of the embeddings of the faces.
+/// - `count`: The number of videos the person was seen in.
+/// - `sample`: The path of the video the person was first seen in, to help naming the cluster.
+/// - `videos`: The paths of the videos the person was seen in, so that a video analysed again is not counted twice.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FaceCluster {
+    pub id: u32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    centroid: Vec<f32>,
+    pub count: u32,
+    #[serde(default)]
+    pub sample: String,
+    #[serde(default)]
+    videos: Vec<String>,
+}
+
+/// Represents the persistent library of the people seen in the videos, stored as JSON in the work folder.
+/// ### Fields
+/// - `path`: The path of the library file.
+/// - `clusters`: The clusters of faces.
+#[derive(Default)]
+pub struct FaceLibrary {
+    path: PathBuf,
+    pub clusters: Vec<FaceCluster>,
+}
+
+impl FaceLibrary {
+    /// Loads the face library from the work folder.
+    ///
+    /// ### Parameters
+    /// - `work_folder`: The path of the work folder.
+    ///
+    /// ### Returns
+    /// The face library, empty if no library file exists yet or if it can't be read.
+    pub fn load(work_folder: &str) -> Self {
+        let mut path = PathBuf::from(work_folder);
+        path.push("faces.json");
+        let clusters = match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(clusters) => clusters,
+                Err(error) => {
+                    log::warn!("Ignoring invalid face library {}: {}", path.display(), error);
+                    Vec::new()
+                }
+            },
+            Err(_) => Vec::new(),
+        };
+        Self { path, clusters }
+    }
+
+    /// Saves the face library in the work folder.
+    ///
+    /// ### Returns
+    /// An empty Result in case of success.
+    ///
+    /// ### Errors
+    /// Returns an error if the library file can't be written.
+    pub fn save(&self) -> anyhow::Result<()> {
+        fs::write(&self.path, serde_json::to_string(&self.clusters)?)?;
+        Ok(())
+    }
+
+    /// Adds a person seen in a video to the cluster of the most similar person, or to a new cluster when no person is similar enough.
+    /// Each person must be added once per video (see `merge_faces`), so that the people seen in many frames don't weigh more than the others. A video already recorded in the cluster, e.g. analysed again with `--force` or in a continued run, leaves the cluster unchanged.
+    ///
+    /// ### Parameters
+    /// - `embedding`: The embedding of the person.
+    /// - `threshold`: The minimum cosine similarity between the face and a cluster to be the same person.
+    /// - `video_path`: The path of the video the face was found in.
+    ///
+    /// ### Returns
+    /// The ID of the cluster of the face.
+    pub fn assign(&mut self, embedding: &[f32], threshold: f32, video_path: &str) -> u32 {
+        let embedding = normalise(embedding);
+        let closest = self
+            .clusters
+            .iter_mut()
+            .filter(|cluster| cluster.centroid.len() == embedding.len())
+            .map(|cluster| (dot(&cluster.centroid, &embedding), cluster))
+            .filter(|(similarity, _)| *similarity >= threshold)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b));
+        if let Some((_, cluster)) = closest {
+            if cluster.videos.iter().any(|path| path == video_path) {
+                return cluster.id;
+            }
+            cluster.videos.push(video_path.to_string());
+            let count = cluster.count as f32;
+            let mean: Vec<f32> = cluster
+                .centroid
+                .iter()
+                .zip(&embedding)
+                .map(|(centroid, value)| (centroid * count + value) / (count + 1.0))
+                .collect();
+            cluster.centroid = normalise(&mean);
+            cluster.count += 1;
+            return cluster.id;
+        }
+        let id = self.clusters.iter().map(|cluster| cluster.id).max().unwrap_or_default() + 1;
+        self.clusters.push(FaceCluster {
+            id,
+            name: String::default(),
+            centroid: embedding,
+            count: 1,
+            sample: video_path.to_string(),
+            videos: vec![video_path.to_string()],
+        });
+        id
+    }
+
+    /// Names the person of a cluster.
+    ///
+    /// ### Parameters
+    /// - `id`: The ID of the cluster.
+    /// - `name`: The name of the person, empty to forget it.
+    ///
+    /// ### Errors
+    /// Returns an error if the cluster doesn't exist.
+    pub fn name_cluster(&mut self, id: u32, name: &str) -> Result<(), String> {
+        match self.clusters.iter_mut().find(|cluster| cluster.id == id) {
+            Some(cluster) => {
+                cluster.name = name.trim().to_string();
+                Ok(())
+            }
+            None => Err(format!("No face cluster with the ID {}", id)),
+        }
+    }
+
+    /// Gets the names of the people of some clusters, the unnamed ones being left out.
+    ///
+    /// ### Parameters
+    /// - `ids`: The IDs of the clusters.
+    ///
+    /// ### Returns
+    /// The names of the people, without duplicates.
+    pub fn get_names(&self, ids: &[u32]) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for id in ids {
+            if let Some(cluster) = self.clusters.iter().find(|cluster| cluster.id == *id) {
+                if !cluster.name.is_empty() && !names.contains(&cluster.name) {
+                    names.push(cluster.name.clone());
+                }
+            }
+        }
+        names
+    }
+}
+
+/// Extracts the faces of the thumbnails of a video. A thumbnail the backend fails for is ignored.
+///
+/// ### Parameters
+/// - `detector`: The face detection backend.
+/// - `thumbnails`: The paths of the thumbnails.
+///
+/// ### Returns
+/// The embeddings of the faces found in the thumbnails.
+pub(crate) fn extract_faces_from_thumbnails(detector: &dyn FaceDetector, thumbnails: &[String]) -> Vec<Vec<f32>> {
+    let mut embeddings = Vec::new();
+    for thumbnail in thumbnails {
+        match detector.detect(thumbnail) {
+            Ok(faces) => embeddings.extend(faces.into_iter().map(|face| face.embedding)),
+            Err(error) => log::warn!("Error while detecting the faces of {}: {}", thumbnail, error),
+        }
+    }
+    embeddings
+}
+
+/// Merges the faces of the same person found in the frames of a video, so that each person of the video is added once to the face library.
+///
+/// ### Parameters
+/// - `embeddings`: The embeddings of the faces found in the frames of the video.
+/// - `threshold`: The minimum cosine similarity between two faces to be the same person.
+///
+/// ### Returns
+/// The normalised mean embedding of each person of the video, in the order they were first seen.
+pub fn merge_faces(embeddings: &[Vec<f32>], threshold: f32) -> Vec<Vec<f32>> {
+    // Each person keeps the sum of the normalised embeddings of their faces, its direction being their mean
+    let mut people: Vec<Vec<f32>> = Vec::new();
+    for embedding in embeddings {
+        let embedding = normalise(embedding);
+        let closest = people
+            .iter_mut()
+            .filter(|person| person.len() == embedding.len())
+            .map(|person| (dot(&normalise(person), &embedding), person))
+            .filter(|(similarity, _)| *similarity >= threshold)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b));
+        match closest {
+            Some((_, person)) => {
+                for (sum, value) in person.iter_mut().zip(&embedding) {
+                    *sum += value;
+                }
+            }
+            None => people.push(embedding),
+        }
+    }
+    people.iter().map(|person| normalise(person)).collect()
+}
+
+/// Normalises a vector to a length of 1, so that the dot product of two vectors is their cosine similarity.
+///
+/// ### Parameters
+/// - `vector`: The vector.
+///
+/// ### Returns
+/// The normalised vector, unchanged if its length is 0.
+fn normalise(vector: &[f32]) -> Vec<f32> {
+    let length = dot(vector, vector).sqrt();
+    if length > 0.0 {
+        vector.iter().map(|value| value / length).collect()
+    } else {
+        vector.to_vec()
+    }
+}
+
+/// Computes the dot product of two vectors.
+///
+/// ### Parameters
+/// - `a`: The first vector.
+/// - `b`: The second vector.
+///
+/// ### Returns
+/// The dot product.
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that two vectors are equal, give or take the rounding errors.
+    fn assert_close(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn normalises_to_a_length_of_one() {
+        assert_close(&normalise(&[3.0, 4.0]), &[0.6, 0.8]);
+        assert_close(&normalise(&[0.0, 0.0]), &[0.0, 0.0]);
+        let normalised = normalise(&[1.0, -2.0, 2.0]);
+        assert!((dot(&normalised, &normalised) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn assigns_similar_faces_to_the_same_cluster() {
+        let mut library = FaceLibrary::default();
+        let first = library.assign(&[1.0, 0.0], 0.5, "a.mp4");
+        let second = library.assign(&[0.9, 0.1], 0.5, "b.mp4");
+        let other = library.assign(&[0.0, 1.0], 0.5, "c.mp4");
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_eq!(library.clusters.len(), 2);
+        assert_eq!(library.clusters[0].count, 2);
+        assert_eq!(library.clusters[0].sample, "a.mp4");
+        assert_eq!(library.clusters[1].count, 1);
+        assert_eq!(library.clusters[1].sample, "c.mp4");
+    }
+
+    #[test]
+    fn counts_a_video_analysed_again_once() {
+        let mut library = FaceLibrary::default();
+        let first = library.assign(&[1.0, 0.0], 0.5, "a.mp4");
+        library.assign(&[1.0, 1.0], 0.5, "b.mp4");
+        let centroid = library.clusters[0].centroid.clone();
+        assert_eq!(library.assign(&[1.0, 0.0], 0.5, "a.mp4"), first);
+        assert_eq!(library.assign(&[1.0, 1.0], 0.5, "b.mp4"), first);
+        assert_eq!(library.clusters.len(), 1);
+        assert_eq!(library.clusters[0].count, 2);
+        assert_close(&library.clusters[0].centroid, &centroid);
+    }
+
+    #[test]
+    fn moves_the_centroid_towards_the_new_faces() {
+        let mut library = FaceLibrary::default();
+        library.assign(&[1.0, 0.0], 0.5, "a.mp4");
+        library.assign(&[1.0, 1.0], 0.5, "b.mp4");
+        let expected = normalise(&[(1.0 + 0.5f32.sqrt()) / 2.0, 0.5f32.sqrt() / 2.0]);
+        assert_close(&library.clusters[0].centroid, &expected);
+    }
+
+    #[test]
+    fn assigns_to_the_most_similar_cluster() {
+        let mut library = FaceLibrary::default();
+        let first = library.assign(&[1.0, 0.0, 0.0], 0.9, "a.mp4");
+        let second = library.assign(&[0.0, 1.0, 0.0], 0.9, "b.mp4");
+        assert_eq!(library.assign(&[0.2, 1.0, 0.0], 0.5, "c.mp4"), second);
+        assert_eq!(library.assign(&[1.0, 0.3, 0.0], 0.5, "d.mp4"), first);
+        // Embeddings of another size come from another model
+        let other = library.assign(&[1.0, 0.0], 0.5, "e.mp4");
+        assert_ne!(other, first);
+        assert_eq!(library.clusters.len(), 3);
+    }
+
+    #[test]
+    fn merges_the_faces_of_a_video_by_person() {
+        let embeddings = vec![
+            vec![1.0, 0.0],
+            vec![0.0, 2.0],
+            vec![0.9, 0.1],
+            vec![1.0, 0.0],
+            vec![0.1, 1.0],
+        ];
+        let people = merge_faces(&embeddings, 0.8);
+        assert_eq!(people.len(), 2);
+        assert!(dot(&people[0], &[1.0, 0.0]) > 0.99);
+        assert!(dot(&people[1], &[0.0, 1.0]) > 0.99);
+        assert!((dot(&people[0], &people[0]) - 1.0).abs() < 1e-5);
+        assert!(merge_faces(&[], 0.8).is_empty());
+    }
+
+    #[test]
+    fn counts_a_person_seen_in_many_frames_once_per_video() {
+        let mut library = FaceLibrary::default();
+        let frames = vec![vec![1.0, 0.0]; 10];
+        for person in merge_faces(&frames, 0.5) {
+            library.assign(&person, 0.5, "a.mp4");
+        }
+        for person in merge_faces(&[vec![0.95, 0.05]], 0.5) {
+            library.assign(&person, 0.5, "b.mp4");
+        }
+        assert_eq!(library.clusters.len(), 1);
+        assert_eq!(library.clusters[0].count, 2);
+    }
+
+    #[test]
+    fn parses_the_plugin_backend() {
+        let backend = "plugin:python3 '/opt/my faces/embed.py' {image}".parse::<FaceBackend>().unwrap();
+        assert_eq!(
+            backend,
+            FaceBackend::Plugin {
+                command: "python3 '/opt/my faces/embed.py' {image}".to_string()
+            }
+        );
+        assert!("command:my-faces {image}".parse::<FaceBackend>().is_ok());
+        assert!("plugin:my-faces".parse::<FaceBackend>().is_err());
+        assert!("plugin:my-faces '{image}".parse::<FaceBackend>().is_err());
+        assert!("builtin-model".parse::<FaceBackend>().is_err());
+    }
+
+    #[test]
+    fn parses_and_saves_the_builtin_backend() {
+        let backend = "builtin".parse::<FaceBackend>().unwrap();
+        assert_eq!(backend, FaceBackend::Builtin);
+        assert_eq!(backend.to_string(), "builtin");
+        let saved = serde_json::to_string(&backend).unwrap();
+        assert_eq!(saved, r#"{"backend":"builtin"}"#);
+        assert_eq!(serde_json::from_str::<FaceBackend>(&saved).unwrap(), backend);
+    }
+
+    #[test]
+    fn loads_the_backend_saved_under_its_first_name() {
+        let backend: FaceBackend =
+            serde_json::from_str(r#"{"backend": "command", "command": "my-faces {image}"}"#).unwrap();
+        assert_eq!(
+            backend,
+            FaceBackend::Plugin {
+                command: "my-faces {image}".to_string()
+            }
+        );
+    }
+}
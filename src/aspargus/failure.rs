@@ -0,0 +1,166 @@
+use std::{fmt, future::Future, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use super::aspargus_helper::VideoDataError;
+use super::journal::Stage;
+use super::AspargusError;
+
+/// Represents what to do when a video fails a stage of the analysis.
+/// ### Variants
+/// - `FailFast`: The run is stopped.
+/// - `Skip`: The video is skipped and the run goes on with the other videos.
+/// - `Retry`: The stage is tried again up to `attempts` more times, then the video is skipped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum FailurePolicy {
+    FailFast,
+    #[default]
+    Skip,
+    Retry { attempts: u32 },
+}
+
+impl FailurePolicy {
+    /// Gets the number of times a stage is tried for a video.
+    ///
+    /// ### Returns
+    /// The number of tries, at least 1.
+    pub fn tries(&self) -> u32 {
+        match self {
+            FailurePolicy::Retry { attempts } => attempts + 1,
+            _ => 1,
+        }
+    }
+
+    /// Checks if the run must be stopped after a video failed a stage.
+    ///
+    /// ### Parameters
+    /// - `error`: The error of the video.
+    ///
+    /// ### Returns
+    /// True if the run must be stopped, always the case for generic errors such as a missing FFmpeg, which concern all the videos.
+    pub fn must_stop(&self, error: &AspargusError) -> bool {
+        *self == FailurePolicy::FailFast || matches!(error, AspargusError::GenericError(_))
+    }
+}
+
+impl fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailurePolicy::FailFast => write!(f, "fail_fast"),
+            FailurePolicy::Skip => write!(f, "skip"),
+            FailurePolicy::Retry { attempts } => write!(f, "retry:{}", attempts),
+        }
+    }
+}
+
+impl FromStr for FailurePolicy {
+    type Err = String;
+
+    /// Parses a failure policy written as `fail_fast`, `skip` or `retry:<N>`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (policy, parameter) = value.split_once(':').unwrap_or((value, ""));
+        match policy.trim() {
+            "fail_fast" | "fail-fast" => Ok(FailurePolicy::FailFast),
+            "skip" => Ok(FailurePolicy::Skip),
+            "retry" => match parameter.trim().parse::<u32>() {
+                Ok(attempts) if attempts > 0 => Ok(FailurePolicy::Retry { attempts }),
+                _ => Err(format!("Invalid number of attempts in failure policy: {}", value)),
+            },
+            _ => Err(format!(
+                "Unknown failure policy: {}, expected fail_fast, skip or retry:<N>",
+                value
+            )),
+        }
+    }
+}
+
+/// Represents a video skipped during the run.
+/// ### Fields
+/// - `path`: The path of the video.
+/// - `stage`: The stage the video failed.
+/// - `error`: The error of the last try.
+pub struct Failure {
+    pub path: String,
+    pub stage: Stage,
+    pub error: AspargusError,
+}
+
+/// Classifies the error of a stage of the analysis.
+///
+/// ### Parameters
+/// - `error`: The error returned by the stage.
+///
+/// ### Returns
+/// A generic error if FFmpeg or FFprobe are missing, a parse error if the output of a model can't be read, an IO error if a file can't be read or written, and a processing error otherwise.
+pub fn classify_error(error: anyhow::Error) -> AspargusError {
+    if let Some(
+        VideoDataError::FFMpegNotFoundError(message) | VideoDataError::FFProbeNotFoundError(message),
+    ) = error.downcast_ref::<VideoDataError>()
+    {
+        AspargusError::GenericError(message.clone())
+    } else if error.downcast_ref::<serde_json::Error>().is_some() {
+        AspargusError::ParseError(error.to_string())
+    } else if error.downcast_ref::<std::io::Error>().is_some() {
+        AspargusError::Io(error.to_string())
+    } else {
+        AspargusError::ProcessingError(error.to_string())
+    }
+}
+
+/// Runs a stage for a video, trying it again according to the failure policy. Generic errors are not retried, as they concern all the videos.
+///
+/// ### Parameters
+/// - `policy`: The failure policy.
+/// - `label`: The description of the stage and of the video, for the logs.
+/// - `stage`: The stage to run.
+///
+/// ### Returns
+/// The result of the last try.
+pub fn run_with_retries<T>(
+    policy: &FailurePolicy,
+    label: &str,
+    mut stage: impl FnMut() -> Result<T, AspargusError>,
+) -> Result<T, AspargusError> {
+    let mut tries = 1;
+    loop {
+        match stage() {
+            Err(error) if tries < policy.tries() && !matches!(error, AspargusError::GenericError(_)) => {
+                log::warn!("{}: {}, trying again ({}/{})", label, error, tries, policy.tries() - 1);
+                tries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Runs an asynchronous stage for a video, trying it again according to the failure policy after a short pause, which leaves some time to the model server to recover.
+///
+/// ### Parameters
+/// - `policy`: The failure policy.
+/// - `label`: The description of the stage and of the video, for the logs.
+/// - `stage`: The stage to run.
+///
+/// ### Returns
+/// The result of the last try.
+pub async fn run_with_retries_async<T, F, Fut>(
+    policy: &FailurePolicy,
+    label: &str,
+    mut stage: F,
+) -> Result<T, AspargusError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AspargusError>>,
+{
+    let mut tries = 1;
+    loop {
+        match stage().await {
+            Err(error) if tries < policy.tries() && !matches!(error, AspargusError::GenericError(_)) => {
+                log::warn!("{}: {}, trying again ({}/{})", label, error, tries, policy.tries() - 1);
+                tokio::time::sleep(Duration::from_secs(tries as u64)).await;
+                tries += 1;
+            }
+            result => return result,
+        }
+    }
+}
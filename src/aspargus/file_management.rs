@@ -1,11 +1,15 @@
-use chrono::Datelike;
 use directories::ProjectDirs;
 use glob::glob;
 use std::{
-    fs,
+    collections::HashSet,
+    fs::{self, File, TryLockError},
+    io::{ErrorKind, Read, Seek, SeekFrom},
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
+use super::rename_template::RenameTemplate;
+use super::sanitiser::{self, FileNameRules};
 use super::Video;
 
 /// Lists the file paths matching a specific pattern, for retreiving the video thumbnails.
@@ -36,6 +40,121 @@ pub fn list_matching_files(temp_folder: &str, video_id: &str) -> Vec<String> {
     matching_files
 }
 
+/// Removes a list of files, errors are logged as they are not blocking.
+///
+/// ### Parameters
+/// - `files`: The paths of the files to remove.
+pub fn remove_files(files: &[String]) {
+    for file in files {
+        if let Err(error) = fs::remove_file(file) {
+            log::warn!("Could not remove file {}: {}", file, error);
+        }
+    }
+}
+
+/// The lock file held by a run in its temp folder while the run is in progress.
+const RUN_LOCK_FILE: &str = ".lock";
+
+/// Creates the temp folder of a run inside the temp folder, so that the frames of a run never mix with those of another run. The folder contains a lock file, locked until the returned file is dropped, so that other runs never see it as orphaned.
+///
+/// ### Parameters
+/// - `temp_folder`: The path of the temp folder.
+/// - `run_id`: The ID of the run.
+///
+/// ### Returns
+/// A Result containing the path of the temp folder of the run, and its locked lock file.
+///
+/// ### Errors
+/// Returns an error if the folder or its lock file can't be created, or if the folder is already locked by another run.
+pub fn make_run_temp_folder(temp_folder: &str, run_id: &str) -> anyhow::Result<(String, File)> {
+    let mut path = PathBuf::from(temp_folder);
+    path.push(run_id);
+    fs::create_dir_all(&path)?;
+    let lock = File::create(path.join(RUN_LOCK_FILE))?;
+    if let Err(error) = lock.try_lock() {
+        return Err(anyhow::anyhow!("The folder {} is locked: {}", path.display(), error));
+    }
+    Ok((path.to_str().unwrap_or_default().to_string(), lock))
+}
+
+/// Checks if a run folder is locked by a run in progress.
+///
+/// ### Parameters
+/// - `folder`: The path of the run folder.
+///
+/// ### Returns
+/// `true` if the lock file of the folder is held by another run.
+fn is_run_folder_locked(folder: &Path) -> bool {
+    match File::open(folder.join(RUN_LOCK_FILE)) {
+        Ok(lock) => matches!(lock.try_lock_shared(), Err(TryLockError::WouldBlock)),
+        Err(_) => false,
+    }
+}
+
+/// Finds the last modification of a file, or of a folder and everything inside it.
+///
+/// ### Parameters
+/// - `path`: The path of the file or folder.
+///
+/// ### Returns
+/// The newest modification time, or `None` if it can't be read.
+fn get_last_modification(path: &Path) -> Option<SystemTime> {
+    let mut modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+    if path.is_dir() {
+        for entry in fs::read_dir(path).ok()?.filter_map(Result::ok) {
+            match get_last_modification(&entry.path()) {
+                Some(entry_modified) => modified = modified.max(entry_modified),
+                None => return None,
+            }
+        }
+    }
+    Some(modified)
+}
+
+/// Removes the files and run folders of the temp folder left by the runs that were interrupted, i.e. not locked by a run in progress and with nothing inside modified for a while. Errors are logged as they are not blocking.
+///
+/// ### Parameters
+/// - `temp_folder`: The path of the temp folder.
+/// - `run_id`: The ID of the current run, whose folder is kept.
+/// - `max_age`: The time after which a file or a run folder that was not modified is orphaned.
+pub fn remove_orphaned_temp_files(temp_folder: &str, run_id: &str, max_age: Duration) {
+    let Ok(entries) = fs::read_dir(temp_folder) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        if entry.file_name() == run_id {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() && is_run_folder_locked(&path) {
+            continue;
+        }
+        let age = get_last_modification(&path).and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        if age.is_none_or(|age| age < max_age) {
+            continue;
+        }
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        match result {
+            Ok(_) => log::debug!("Removed orphaned temp files: {}", path.display()),
+            Err(error) => log::warn!("Could not remove orphaned temp files {}: {}", path.display(), error),
+        }
+    }
+}
+
+/// Removes a folder and its content, errors are logged as they are not blocking.
+///
+/// ### Parameters
+/// - `folder`: The path of the folder to remove.
+pub fn remove_folder(folder: &str) {
+    if let Err(error) = fs::remove_dir_all(folder) {
+        log::warn!("Could not remove folder {}: {}", folder, error);
+    }
+}
+
 /// Retreives the application's folders, and creates them if they do not exist.
 ///
 /// ### Returns
@@ -65,6 +184,36 @@ pub fn make_app_folders() -> anyhow::Result<(String, String)> {
     }
 }
 
+/// The size of the parts at the start and at the end of a file used to compute its content key.
+const CONTENT_KEY_PART_SIZE: u64 = 1024 * 1024;
+
+/// Computes a key of the content of a file, so that it can be recognised even after being renamed or moved. Only the size of the file and its first and last MiB are hashed, so that large videos, e.g. on a NAS, are not read entirely.
+///
+/// ### Parameters
+/// - `file_path`: The path of the file.
+///
+/// ### Returns
+/// A Result containing the MD5 hash of the size and of the first and last MiB of the file, in lowercase hexadecimal.
+///
+/// ### Errors
+/// Returns an error if the file can't be read.
+pub fn get_content_key(file_path: &str) -> anyhow::Result<String> {
+    let mut file = fs::File::open(file_path)?;
+    let size = file.metadata()?.len();
+    let mut context = md5::Context::new();
+    context.consume(size.to_le_bytes());
+    let mut buffer = Vec::new();
+    file.by_ref().take(CONTENT_KEY_PART_SIZE).read_to_end(&mut buffer)?;
+    context.consume(&buffer);
+    if size > 2 * CONTENT_KEY_PART_SIZE {
+        file.seek(SeekFrom::End(-(CONTENT_KEY_PART_SIZE as i64)))?;
+    }
+    buffer.clear();
+    file.read_to_end(&mut buffer)?;
+    context.consume(&buffer);
+    Ok(format!("{:x}", context.compute()))
+}
+
 /// Creates a new path in order to rename a file.
 ///
 /// ### Parameters
@@ -72,22 +221,66 @@ pub fn make_app_folders() -> anyhow::Result<(String, String)> {
 /// - `new_name`: The new file name.
 ///
 /// ### Returns
-/// The new path.
+/// The new path, with the extension of the current file if it has one.
 pub fn create_new_path(file_path: &str, new_name: &str) -> String {
     let the_file_path = Path::new(file_path);
     let parent = the_file_path.parent();
-    let extension = the_file_path.extension();
+    let extension = the_file_path.extension().and_then(|extension| extension.to_str());
     let mut new_path = PathBuf::new();
-    let new_file_name = format!(
-        "{}.{}",
-        new_name,
-        extension.unwrap_or_default().to_str().unwrap_or_default()
-    );
-    new_path.push(parent.unwrap());
+    // A file whose type was detected from its content may have no extension
+    let new_file_name = match extension {
+        Some(extension) => format!("{}.{}", new_name, extension),
+        None => new_name.to_string(),
+    };
+    new_path.push(parent.unwrap_or(Path::new("")));
     new_path.push(new_file_name);
     new_path.to_str().unwrap_or(file_path).to_string()
 }
 
+/// Makes a path unique by adding a `_2`, `_3`... suffix to the file name, if the path is already taken by another file or by another path of the same batch. The file name is shortened to make space for the suffix when needed.
+///
+/// ### Parameters
+/// - `file_path`: The current file path, which doesn't collide with the new path.
+/// - `new_path`: The wished new path, with the extension of the current file if it has one.
+/// - `taken`: The paths already taken by the other files of the batch.
+/// - `max_length`: The maximum length of the file name in bytes, extension and suffix included.
+///
+/// ### Returns
+/// A path that is not taken.
+pub fn make_unique_path(file_path: &str, new_path: &str, taken: &HashSet<String>, max_length: usize) -> String {
+    let is_taken =
+        |path: &str| path != file_path && (taken.contains(path) || Path::new(path).exists());
+    if !is_taken(new_path) {
+        return new_path.to_string();
+    }
+    let the_new_path = Path::new(new_path);
+    let file_name = the_new_path.file_name().unwrap_or_default().to_str().unwrap_or_default();
+    // The extension is the one of the current file, as the new name may contain dots
+    let extension = Path::new(file_path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| file_name.ends_with(&format!(".{}", extension)));
+    let stem = match extension {
+        Some(extension) => &file_name[..file_name.len() - extension.len() - 1],
+        None => file_name,
+    };
+    let extension = extension.map(|extension| format!(".{}", extension)).unwrap_or_default();
+    let mut suffix = 2;
+    loop {
+        let suffix_text = format!("_{}", suffix);
+        let stem = sanitiser::truncate_file_name(
+            stem,
+            max_length.saturating_sub(extension.len() + suffix_text.len()),
+        );
+        let candidate = the_new_path.with_file_name(format!("{}{}{}", stem, suffix_text, extension));
+        let candidate = candidate.to_str().unwrap_or(new_path).to_string();
+        if !is_taken(candidate.as_str()) {
+            return candidate;
+        }
+        suffix += 1;
+    }
+}
+
 /// Gets the file name from the path.
 ///
 /// ### Parameters
@@ -115,8 +308,14 @@ pub fn get_file_name(file_path: &str) -> String {
 /// An empty Result in case of success.
 ///
 /// ### Errors
-/// Returns an error if the rename operation fails.
+/// Returns an error if the rename operation fails, or if a file already exists at the new path.
 pub fn rename_file(original_path: &str, new_path: &str) -> anyhow::Result<()> {
+    if original_path != new_path && Path::new(new_path).exists() {
+        return Err(anyhow::Error::msg(format!(
+            "Could not rename file: {}, {} already exists",
+            original_path, new_path
+        )));
+    }
     match fs::rename(original_path, new_path) {
         Ok(()) => Ok(()),
         Err(_) => Err(anyhow::Error::msg(format!(
@@ -126,23 +325,249 @@ pub fn rename_file(original_path: &str, new_path: &str) -> anyhow::Result<()> {
     }
 }
 
+/// Moves a file to another path. When the new path is on another file system, the file is copied, verified and then deleted.
+///
+/// ### Parameters
+/// - `original_path`: The current file path.
+/// - `new_path`: The new file path, in an existing folder.
+///
+/// ### Returns
+/// An empty Result in case of success.
+///
+/// ### Errors
+/// Returns an error if a file already exists at the new path, or if the file can't be moved.
+pub fn move_file(original_path: &str, new_path: &str) -> anyhow::Result<()> {
+    if original_path != new_path && Path::new(new_path).exists() {
+        return Err(anyhow::Error::msg(format!(
+            "Could not move file: {}, {} already exists",
+            original_path, new_path
+        )));
+    }
+    match fs::rename(original_path, new_path) {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == ErrorKind::CrossesDevices => {
+            copy_file(original_path, new_path)?;
+            fs::remove_file(original_path).map_err(|error| {
+                anyhow::Error::msg(format!(
+                    "Copied {} to {} but could not delete it: {}",
+                    original_path, new_path, error
+                ))
+            })
+        }
+        Err(error) => Err(anyhow::Error::msg(format!(
+            "Could not move file: {}, {}",
+            original_path, error
+        ))),
+    }
+}
+
+/// Copies a file to another path, keeping its modification time, and verifies that the copy has the same size and content key as the original file.
+///
+/// ### Parameters
+/// - `original_path`: The current file path.
+/// - `new_path`: The path of the copy, in an existing folder.
+///
+/// ### Returns
+/// An empty Result in case of success.
+///
+/// ### Errors
+/// Returns an error if a file already exists at the new path, or if the file can't be copied or the copy differs from the original file. An incomplete copy is removed.
+pub fn copy_file(original_path: &str, new_path: &str) -> anyhow::Result<()> {
+    if Path::new(new_path).exists() {
+        return Err(anyhow::Error::msg(format!(
+            "Could not copy file: {}, {} already exists",
+            original_path, new_path
+        )));
+    }
+    let copy = || -> anyhow::Result<()> {
+        fs::copy(original_path, new_path)?;
+        if let Ok(modified) = fs::metadata(original_path).and_then(|metadata| metadata.modified()) {
+            fs::File::options()
+                .write(true)
+                .open(new_path)
+                .and_then(|file| file.set_modified(modified))?;
+        }
+        if fs::metadata(original_path)?.len() != fs::metadata(new_path)?.len()
+            || get_content_key(original_path)? != get_content_key(new_path)?
+        {
+            return Err(anyhow::Error::msg("the copy differs from the original file"));
+        }
+        Ok(())
+    };
+    copy().map_err(|error| {
+        let _ = fs::remove_file(new_path);
+        anyhow::Error::msg(format!(
+            "Could not copy file: {} to {}, {}",
+            original_path, new_path, error
+        ))
+    })
+}
+
+/// Creates a folder and its missing parents.
+///
+/// ### Parameters
+/// - `folder`: The path of the folder.
+///
+/// ### Returns
+/// A Result containing the folders that were created, from the outermost to the innermost.
+///
+/// ### Errors
+/// Returns an error if a folder can't be created.
+pub fn create_folders(folder: &Path) -> anyhow::Result<Vec<String>> {
+    let missing_folders: Vec<&Path> = folder
+        .ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .collect();
+    fs::create_dir_all(folder)?;
+    Ok(missing_folders
+        .iter()
+        .rev()
+        .map(|missing_folder| missing_folder.to_str().unwrap_or_default().to_string())
+        .collect())
+}
+
 /// Creates a new file name for a video based on a template.
 ///
 /// ### Parameters
 /// - `video`: The video to rename.
 /// - `template`: The new file name template.
+/// - `rules`: The rules applied to sanitise and limit the length of the new file name.
 ///
 /// ### Returns
-/// A new file name.
-pub fn create_new_file_name(video: &Video, template: &str) -> String {
-    let creation_date = video.creation_date;
-    let mut new_name = template.to_string();
-    new_name = new_name.replace("%Y", creation_date.year().to_string().as_str());
-    new_name = new_name.replace("%M", creation_date.format("%m").to_string().as_str());
-    new_name = new_name.replace("%D", creation_date.format("%d").to_string().as_str());
-    new_name = new_name.replace("%T", &video.resume.title);
-    new_name = new_name.replace("%K", &video.resume.keywords.join("-"));
-    new_name = new_name.replace("%J", &video.resume.keywords.join(", "));
-    new_name = new_name.replace("%F", get_file_name(&video.path).as_str());
-    new_name
-}
\ No newline at end of file
+/// A new file name, without extension.
+pub fn create_new_file_name(video: &Video, template: &RenameTemplate, rules: &FileNameRules) -> String {
+    let new_name = template.render(video);
+    let mut new_name = sanitiser::sanitise_file_name(&new_name, rules);
+    if new_name.is_empty() {
+        new_name = sanitiser::sanitise_file_name(&get_file_name(&video.path), rules);
+    }
+    let extension_length = Path::new(&video.path)
+        .extension()
+        .map(|extension| extension.len() + 1)
+        .unwrap_or_default();
+    sanitiser::truncate_file_name(&new_name, rules.max_length.saturating_sub(extension_length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+    /// Creates an empty temp folder for a test.
+    fn make_temp_folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("aspargus-temp-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    /// Creates a run folder whose content was last modified two hours ago.
+    fn make_old_run_folder(temp_folder: &Path, run_id: &str) -> PathBuf {
+        let old = SystemTime::now() - Duration::from_secs(2 * 60 * 60);
+        let folder = temp_folder.join(run_id);
+        fs::create_dir_all(&folder).unwrap();
+        let frame = folder.join("frame.png");
+        fs::write(&frame, b"frame").unwrap();
+        File::options().write(true).open(&frame).unwrap().set_modified(old).unwrap();
+        File::open(&folder).unwrap().set_modified(old).unwrap();
+        folder
+    }
+
+    #[test]
+    fn create_new_path_keeps_the_extension_if_any() {
+        assert_eq!(create_new_path("/videos/VID_0001.mp4", "Beach"), "/videos/Beach.mp4");
+        assert_eq!(create_new_path("/videos/VID_0001", "Beach"), "/videos/Beach");
+        assert_eq!(create_new_path("VID_0001.MOV", "Beach"), "Beach.MOV");
+    }
+
+    #[test]
+    fn make_unique_path_keeps_a_suffixed_name_within_the_limit() {
+        let name = "a".repeat(16);
+        let new_path = format!("/videos/{}.mp4", name);
+        let mut taken = HashSet::new();
+        assert_eq!(make_unique_path("/videos/VID_0001.mp4", &new_path, &taken, 20), new_path);
+        taken.insert(new_path.clone());
+        let unique = make_unique_path("/videos/VID_0001.mp4", &new_path, &taken, 20);
+        assert_eq!(unique, format!("/videos/{}_2.mp4", "a".repeat(14)));
+        assert_eq!(Path::new(&unique).file_name().unwrap().len(), 20);
+        taken.insert(unique);
+        let unique = make_unique_path("/videos/VID_0002.mp4", &new_path, &taken, 20);
+        assert_eq!(unique, format!("/videos/{}_3.mp4", "a".repeat(14)));
+    }
+
+    #[test]
+    fn make_unique_path_keeps_the_dots_of_the_new_name() {
+        let taken = HashSet::from(["/videos/Mr. Smith".to_string(), "/videos/Mr. Smith.mp4".to_string()]);
+        assert_eq!(make_unique_path("/videos/VID_0001", "/videos/Mr. Smith", &taken, 255), "/videos/Mr. Smith_2");
+        assert_eq!(
+            make_unique_path("/videos/VID_0001.mp4", "/videos/Mr. Smith.mp4", &taken, 255),
+            "/videos/Mr. Smith_2.mp4"
+        );
+    }
+
+    #[test]
+    fn content_key_survives_renames_and_reads_only_the_ends_of_large_files() {
+        let temp_folder = make_temp_folder("content-key");
+        let small = temp_folder.join("small.mp4");
+        fs::write(&small, b"small video").unwrap();
+        let key = get_content_key(small.to_str().unwrap()).unwrap();
+        let renamed = temp_folder.join("renamed.mp4");
+        fs::rename(&small, &renamed).unwrap();
+        assert_eq!(get_content_key(renamed.to_str().unwrap()).unwrap(), key);
+        fs::write(&renamed, b"small videO").unwrap();
+        assert_ne!(get_content_key(renamed.to_str().unwrap()).unwrap(), key);
+
+        let large = temp_folder.join("large.mp4");
+        let mut content = vec![0u8; 3 * CONTENT_KEY_PART_SIZE as usize];
+        fs::write(&large, &content).unwrap();
+        let key = get_content_key(large.to_str().unwrap()).unwrap();
+        // The middle of a large file is not read
+        content[CONTENT_KEY_PART_SIZE as usize + 10] = 1;
+        fs::write(&large, &content).unwrap();
+        assert_eq!(get_content_key(large.to_str().unwrap()).unwrap(), key);
+        let last = content.len() - 1;
+        content[last] = 1;
+        fs::write(&large, &content).unwrap();
+        assert_ne!(get_content_key(large.to_str().unwrap()).unwrap(), key);
+        content.push(0);
+        fs::write(&large, &content).unwrap();
+        assert_ne!(get_content_key(large.to_str().unwrap()).unwrap(), key);
+        let _ = fs::remove_dir_all(&temp_folder);
+    }
+
+    #[test]
+    fn remove_orphaned_temp_files_removes_an_old_unlocked_run_folder() {
+        let temp_folder = make_temp_folder("orphaned");
+        let orphaned = make_old_run_folder(&temp_folder, "old-run");
+
+        remove_orphaned_temp_files(temp_folder.to_str().unwrap(), "current-run", MAX_AGE);
+        assert!(!orphaned.exists());
+        let _ = fs::remove_dir_all(&temp_folder);
+    }
+
+    #[test]
+    fn remove_orphaned_temp_files_keeps_a_run_folder_with_a_recent_file() {
+        let temp_folder = make_temp_folder("recent");
+        let folder = make_old_run_folder(&temp_folder, "old-run");
+        fs::create_dir_all(folder.join("audio")).unwrap();
+        File::open(&folder).unwrap().set_modified(SystemTime::now() - Duration::from_secs(2 * 60 * 60)).unwrap();
+
+        remove_orphaned_temp_files(temp_folder.to_str().unwrap(), "current-run", MAX_AGE);
+        assert!(folder.exists());
+        let _ = fs::remove_dir_all(&temp_folder);
+    }
+
+    #[test]
+    fn remove_orphaned_temp_files_keeps_a_locked_run_folder() {
+        let temp_folder = make_temp_folder("locked");
+        let (folder, _lock) = make_run_temp_folder(temp_folder.to_str().unwrap(), "running").unwrap();
+        let folder = make_old_run_folder(&temp_folder, Path::new(&folder).file_name().unwrap().to_str().unwrap());
+        let lock_file = File::options().write(true).open(folder.join(RUN_LOCK_FILE)).unwrap();
+        lock_file.set_modified(SystemTime::now() - Duration::from_secs(2 * 60 * 60)).unwrap();
+
+        remove_orphaned_temp_files(temp_folder.to_str().unwrap(), "current-run", MAX_AGE);
+        assert!(folder.exists());
+        let _ = fs::remove_dir_all(&temp_folder);
+    }
+}
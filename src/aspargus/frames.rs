@@ -0,0 +1,146 @@
+use std::{fmt, fs, path::PathBuf, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::video::Video;
+
+/// Represents where the frames extracted from the videos are held until they are sent to the models.
+/// ### Variants
+/// - `Disk`: FFmpeg writes the frames as PNG files in the temp folder of the run, which are then resized and read again.
+/// - `Memory`: FFmpeg pipes the frames already resized as JPEG images, which are kept in memory with their video.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameStorage {
+    #[default]
+    Disk,
+    Memory,
+}
+
+impl fmt::Display for FrameStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameStorage::Disk => write!(f, "disk"),
+            FrameStorage::Memory => write!(f, "memory"),
+        }
+    }
+}
+
+impl FromStr for FrameStorage {
+    type Err = String;
+
+    /// Parses a frame storage written as `disk` or `memory`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "disk" => Ok(FrameStorage::Disk),
+            "memory" => Ok(FrameStorage::Memory),
+            _ => Err(format!(
+                "Unknown frame storage: {}, expected disk or memory",
+                value
+            )),
+        }
+    }
+}
+
+/// Splits the output of FFmpeg's `image2pipe` muxer into JPEG images, by following the markers of each image from its start to its end.
+///
+/// ### Parameters
+/// - `stream`: The concatenated JPEG images.
+///
+/// ### Returns
+/// The JPEG images, an incomplete image at the end of the stream being dropped.
+pub(crate) fn split_jpeg_stream(stream: &[u8]) -> Vec<Vec<u8>> {
+    let mut images = Vec::new();
+    let mut start = None;
+    let mut position = 0;
+    while position + 1 < stream.len() {
+        if stream[position] != 0xFF {
+            position += 1;
+            continue;
+        }
+        let marker = stream[position + 1];
+        let Some(image_start) = start else {
+            if marker == 0xD8 {
+                start = Some(position);
+                position += 2;
+            } else {
+                position += 1;
+            }
+            continue;
+        };
+        match marker {
+            // End of image
+            0xD9 => {
+                images.push(stream[image_start..position + 2].to_vec());
+                start = None;
+                position += 2;
+            }
+            // Escaped 0xFF in the compressed data, restart markers and markers without length
+            0x00 | 0x01 | 0xD0..=0xD8 => position += 2,
+            // Fill byte
+            0xFF => position += 1,
+            // Segment with a length, skipped as it may contain 0xFF bytes, the compressed data following a start of scan being read byte by byte
+            _ => {
+                if position + 4 > stream.len() {
+                    break;
+                }
+                let length = u16::from_be_bytes([stream[position + 2], stream[position + 3]]) as usize;
+                position += 2 + length;
+            }
+        }
+    }
+    images
+}
+
+/// Writes the frames held in memory of a video as JPEG files, e.g. to keep them for debugging or to give them to a backend expecting files. Errors are logged as they are not blocking.
+///
+/// ### Parameters
+/// - `folder`: The folder to write the frames in.
+/// - `video`: The video.
+///
+/// ### Returns
+/// The paths of the written frames.
+pub(crate) fn write_frames(folder: &str, video: &Video) -> Vec<String> {
+    let mut paths = Vec::new();
+    for (index, frame) in video.frames.iter().enumerate() {
+        let mut path = PathBuf::from(folder);
+        path.push(format!("{}_{:04}.jpg", video.id, index + 1));
+        match fs::write(&path, frame) {
+            Ok(_) => paths.push(path.to_str().unwrap_or_default().to_string()),
+            Err(error) => log::warn!("Could not write frame {}: {}", path.display(), error),
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A JPEG image with a segment and compressed data containing 0xFF bytes.
+    const IMAGE: [u8; 21] = [
+        0xFF, 0xD8, // Start of image
+        0xFF, 0xE0, 0x00, 0x04, 0xFF, 0xD9, // Segment whose content looks like an end of image
+        0xFF, 0xDA, 0x00, 0x02, // Start of scan
+        0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD0, 0x56, // Compressed data with an escaped 0xFF and a restart marker
+        0xFF, 0xD9, // End of image
+    ];
+
+    #[test]
+    fn split_jpeg_stream_splits_images_with_escapes_and_fill_bytes() {
+        let filled = [0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02, 0x78, 0xFF, 0xFF, 0xFF, 0xD9];
+        let stream = [IMAGE.as_slice(), filled.as_slice()].concat();
+
+        let images = split_jpeg_stream(&stream);
+        assert_eq!(images, vec![IMAGE.to_vec(), filled.to_vec()]);
+    }
+
+    #[test]
+    fn split_jpeg_stream_drops_a_truncated_last_image() {
+        let truncated = [0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02, 0x9A, 0xFF, 0x00];
+        let stream = [IMAGE.as_slice(), truncated.as_slice()].concat();
+
+        assert_eq!(split_jpeg_stream(&stream), vec![IMAGE.to_vec()]);
+        // Cut in the middle of a segment length
+        assert_eq!(split_jpeg_stream(&IMAGE[..4]), Vec::<Vec<u8>>::new());
+    }
+}
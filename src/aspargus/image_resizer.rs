@@ -1,52 +1,150 @@
-use image::imageops::FilterType;
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::{fmt, io::Cursor};
 
-/// Resizes an image, that will be overwritten.
-/// 
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// Represents how the frames are prepared before being sent to a model, defined in the settings for the models whose name starts with `model`.
+/// ### Fields
+/// - `model`: The beginning of the name of the models the profile applies to, e.g. `llava` or `qwen2.5vl:7b`.
+/// - `max_edge`: The maximum width and height of the images in pixels.
+/// - `square`: Flag to pad the images with black borders to make them square, instead of only preserving their aspect ratio.
+/// - `upscale`: Flag to enlarge the images smaller than the maximum size.
+/// - `jpeg_quality`: The quality of the JPEG images sent to the model, from 1 to 100.
+/// - `grayscale`: Flag to send the images in shades of grey, making them lighter.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ImageProfile {
+    #[serde(default)]
+    pub model: String,
+    #[serde(default = "get_default_max_edge")]
+    pub max_edge: u32,
+    #[serde(default)]
+    pub square: bool,
+    #[serde(default)]
+    pub upscale: bool,
+    #[serde(default = "get_default_jpeg_quality")]
+    pub jpeg_quality: u8,
+    #[serde(default)]
+    pub grayscale: bool,
+}
+
+impl Default for ImageProfile {
+    fn default() -> Self {
+        Self {
+            model: String::default(),
+            max_edge: get_default_max_edge(),
+            square: false,
+            upscale: false,
+            jpeg_quality: get_default_jpeg_quality(),
+            grayscale: false,
+        }
+    }
+}
+
+impl fmt::Display for ImageProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "up to {} px, {}, {}, JPEG quality {}{}",
+            self.max_edge,
+            if self.square { "square" } else { "aspect ratio preserved" },
+            if self.upscale { "upscaled" } else { "not upscaled" },
+            self.jpeg_quality,
+            if self.grayscale { ", grayscale" } else { "" }
+        )
+    }
+}
+
+/// Gets the default maximum width and height of the images.
+///
+/// ### Returns
+/// The default maximum size in pixels.
+#[doc(hidden)]
+fn get_default_max_edge() -> u32 {
+    672
+}
+
+/// Gets the default quality of the JPEG images.
+///
+/// ### Returns
+/// The default JPEG quality.
+#[doc(hidden)]
+fn get_default_jpeg_quality() -> u8 {
+    85
+}
+
+/// Selects the profile of a model, the profile with the longest matching model name winning.
+///
+/// ### Parameters
+/// - `profiles`: The profiles of the settings.
+/// - `model`: The name of the model.
+///
+/// ### Returns
+/// The profile of the model, the default profile if none matches.
+pub fn select_profile(profiles: &[ImageProfile], model: &str) -> ImageProfile {
+    profiles
+        .iter()
+        .filter(|profile| model.starts_with(profile.model.as_str()))
+        .max_by_key(|profile| profile.model.len())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Prepares an image for a model according to a profile: resized, padded and turned to grey as needed, then encoded as JPEG.
+///
 /// ### Parameters
-/// - `image_path`: The Aspargus settings.
-/// 
+/// - `image`: The decoded image.
+/// - `profile`: The profile of the model.
+///
 /// ### Returns
-/// An empty Result in case of success.
-/// 
+/// A Result containing the JPEG image.
+///
 /// ### Errors
-/// Returns an error if the resize operation fails.
-pub fn resize_image(image_path: &str) -> anyhow::Result<()> {
-    const MAX_SIZE: u32 = 672;
-    let img = image::open(image_path)?;
-    let (width, height) = calculate_new_size(img.width(), img.height(), MAX_SIZE, MAX_SIZE);
-    let resized = img.resize_exact(width, height, FilterType::Lanczos3);
-    resized.save(image_path)?;
-    Ok(())
-}
-
-/// Calculates the new size of an image given some boundaries, while keeping the image ratio.
-/// 
+/// Returns an error if the image can't be encoded.
+pub fn prepare_image(image: DynamicImage, profile: &ImageProfile) -> anyhow::Result<Vec<u8>> {
+    let (width, height) = calculate_new_size(image.width(), image.height(), profile.max_edge, profile.upscale);
+    let mut image = if (width, height) == image.dimensions() {
+        image
+    } else {
+        image.resize_exact(width, height, FilterType::Lanczos3)
+    };
+    if profile.square && width != height {
+        let edge = width.max(height);
+        let mut canvas = RgbaImage::from_pixel(edge, edge, Rgba([0, 0, 0, 255]));
+        imageops::overlay(
+            &mut canvas,
+            &image.to_rgba8(),
+            ((edge - width) / 2) as i64,
+            ((edge - height) / 2) as i64,
+        );
+        image = DynamicImage::ImageRgba8(canvas);
+    }
+    let image = if profile.grayscale {
+        DynamicImage::ImageLuma8(image.to_luma8())
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    };
+    let mut jpeg = Cursor::new(Vec::new());
+    image.write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, profile.jpeg_quality.clamp(1, 100)))?;
+    Ok(jpeg.into_inner())
+}
+
+/// Calculates the new size of an image so that its longest side doesn't exceed a maximum size, while keeping the image ratio.
+///
 /// ### Parameters
 /// - `width`: The current width of the image.
 /// - `height`: The current height of the image.
-/// - `max_width`: The maximum width of the image.
-/// - `max_height`: The maximum height of the image.
-/// 
+/// - `max_edge`: The maximum width and height of the image.
+/// - `upscale`: Flag to enlarge the image if it is smaller than the maximum size.
+///
 /// ### Returns
 /// A tuple with the new width and height.
-fn calculate_new_size(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
-    let mut new_width = max_width;
-    let mut new_height = max_height;
-    if width > height {
-        new_height = new_width * height / width;
-    } else if width < height {
-        new_width = new_height * width / height;
+pub(crate) fn calculate_new_size(width: u32, height: u32, max_edge: u32, upscale: bool) -> (u32, u32) {
+    let longest = width.max(height);
+    if longest == 0 || (longest <= max_edge && !upscale) {
+        return (width, height);
     }
-    return (new_width, new_height);
-}
-
-/// Resizes a list of images.
-/// 
-/// ### Parameters
-/// - `images`: An array of images paths.
-pub fn resize_images(images: &Vec<String>) {
-    images.par_iter().for_each(|image| {
-        let _ = resize_image(image.as_str());
-    });
+    let scale = |side: u32| ((side as u64 * max_edge as u64 / longest as u64) as u32).max(1);
+    (scale(width), scale(height))
 }
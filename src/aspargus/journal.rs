@@ -0,0 +1,343 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use super::captions::FrameCaption;
+use super::video::{Resume, Video};
+
+/// Represents the processing stage reached by a video, in the order they are run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    #[default]
+    Queued,
+    Metadata,
+    Frames,
+    Faces,
+    Transcript,
+    Captions,
+    Story,
+    Resume,
+    Renamed,
+    Organised,
+}
+
+/// Represents a line of the journal, recorded each time a video completes a stage.
+/// ### Fields
+/// - `path`: The path of the video when it was queued, used to identify the video across the journal.
+/// - `stage`: The stage completed by the video.
+/// - `timestamps`: The time of each frame in the video, only recorded with the frames stage.
+/// - `faces`: The IDs of the clusters of the faces found in the video, only recorded with the faces stage.
+/// - `transcript`: The transcript of the video's audio, only recorded with the transcript stage.
+/// - `timeline`: The captions of the frames, only recorded with the captions stage.
+/// - `story`: The story generated by the CV model, only recorded with the story stage.
+/// - `resume`: The resume generated for the video, only recorded with the resume stage.
+/// - `new_path`: The new path of the video, only recorded with the renamed and organised stages.
+#[derive(Default, Deserialize, Serialize)]
+pub struct JournalEntry {
+    pub path: String,
+    pub stage: Stage,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timestamps: Vec<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub faces: Vec<u32>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub transcript: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timeline: Vec<FrameCaption>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub story: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume: Option<Resume>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_path: Option<String>,
+}
+
+/// Represents the journal of a run, stored as JSON lines in the `runs` folder of the work folder.
+/// A default journal is disabled and doesn't record anything.
+/// ### Fields
+/// - `run_id`: The ID of the run.
+/// - `file`: The journal file, opened in append mode.
+#[derive(Default)]
+pub struct Journal {
+    pub run_id: String,
+    file: Option<Mutex<File>>,
+}
+
+impl Journal {
+    /// Creates the journal of a new run.
+    ///
+    /// ### Parameters
+    /// - `work_folder`: The path of the work folder.
+    ///
+    /// ### Returns
+    /// A Result containing the new journal.
+    ///
+    /// ### Errors
+    /// Returns an error if the journal file can't be created.
+    pub fn create(work_folder: &str) -> anyhow::Result<Self> {
+        let run_id = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        Self::open(work_folder, run_id)
+    }
+
+    /// Loads the journal of a previous run, in order to continue it.
+    ///
+    /// ### Parameters
+    /// - `work_folder`: The path of the work folder.
+    /// - `run_id`: The ID of the run to continue.
+    ///
+    /// ### Returns
+    /// A Result containing the journal, and the latest known state of each video in the order they were queued.
+    ///
+    /// ### Errors
+    /// Returns an error if the journal file doesn't exist or can't be read.
+    pub fn load(work_folder: &str, run_id: &str) -> anyhow::Result<(Self, Vec<JournalEntry>)> {
+        let path = get_journal_path(work_folder, run_id);
+        let contents = fs::read_to_string(&path)
+            .map_err(|_| anyhow::anyhow!("No journal found for run: {}", run_id))?;
+        let mut states: Vec<JournalEntry> = Vec::new();
+        let mut indexes: HashMap<String, usize> = HashMap::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let entry = match serde_json::from_str::<JournalEntry>(line) {
+                Ok(entry) => entry,
+                Err(error) => {
+                    log::warn!("Ignoring invalid journal entry: {}", error);
+                    continue;
+                }
+            };
+            let new_path = entry.new_path.clone();
+            let index = match indexes.get(&entry.path) {
+                Some(&index) => {
+                    merge_entry(&mut states[index], entry);
+                    index
+                }
+                None => {
+                    indexes.insert(entry.path.clone(), states.len());
+                    states.push(entry);
+                    states.len() - 1
+                }
+            };
+            // The video is followed under its new path, in case it is moved again
+            if let Some(new_path) = new_path {
+                indexes.insert(new_path, index);
+            }
+        }
+        Ok((Self::open(work_folder, run_id.to_string())?, states))
+    }
+
+    /// Opens a journal file in append mode, and creates the runs folder if needed.
+    ///
+    /// ### Parameters
+    /// - `work_folder`: The path of the work folder.
+    /// - `run_id`: The ID of the run.
+    ///
+    /// ### Returns
+    /// A Result containing the journal.
+    ///
+    /// ### Errors
+    /// Returns an error if the journal file can't be opened.
+    fn open(work_folder: &str, run_id: String) -> anyhow::Result<Self> {
+        let path = get_journal_path(work_folder, run_id.as_str());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            run_id,
+            file: Some(Mutex::new(file)),
+        })
+    }
+
+    /// Records that a video has been queued for this run.
+    ///
+    /// ### Parameters
+    /// - `path`: The path of the video.
+    pub fn record_queued(&self, path: &str) {
+        self.write(JournalEntry {
+            path: path.to_string(),
+            ..Default::default()
+        });
+    }
+
+    /// Records that a video has completed its current stage.
+    ///
+    /// ### Parameters
+    /// - `video`: The video, with its stage and results up to date.
+    pub fn record(&self, video: &Video) {
+        self.write(JournalEntry {
+            path: video.path.clone(),
+            stage: video.stage,
+            timestamps: if video.stage == Stage::Frames {
+                video.timestamps.clone()
+            } else {
+                Vec::new()
+            },
+            faces: if video.stage == Stage::Faces {
+                video.face_clusters.clone()
+            } else {
+                Vec::new()
+            },
+            transcript: if video.stage == Stage::Transcript {
+                video.transcript.clone()
+            } else {
+                String::default()
+            },
+            timeline: if video.stage == Stage::Captions {
+                video.timeline.clone()
+            } else {
+                Vec::new()
+            },
+            story: if video.stage == Stage::Story {
+                video.story.clone()
+            } else {
+                String::default()
+            },
+            resume: if video.stage == Stage::Resume {
+                Some(video.resume.clone())
+            } else {
+                None
+            },
+            new_path: None,
+        });
+    }
+
+    /// Records that a video has been renamed or organised.
+    ///
+    /// ### Parameters
+    /// - `original_path`: The path of the video before being renamed or organised.
+    /// - `new_path`: The path of the video after being renamed or organised.
+    /// - `stage`: The renamed or organised stage.
+    pub fn record_moved(&self, original_path: &str, new_path: &str, stage: Stage) {
+        self.write(JournalEntry {
+            path: original_path.to_string(),
+            stage,
+            new_path: Some(new_path.to_string()),
+            ..Default::default()
+        });
+    }
+
+    /// Appends an entry to the journal file. Errors are logged as the journal must not stop the run.
+    ///
+    /// ### Parameters
+    /// - `entry`: The entry to append.
+    fn write(&self, entry: JournalEntry) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let mut line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(error) => {
+                log::error!("Error while serializing the journal entry: {}", error);
+                return;
+            }
+        };
+        line.push('\n');
+        let mut file = file.lock().unwrap();
+        if let Err(error) = file.write_all(line.as_bytes()) {
+            log::error!("Error while writing the journal of run {}: {}", self.run_id, error);
+        }
+    }
+}
+
+/// Merges a newer journal entry into the known state of a video. The stage never goes backwards.
+///
+/// ### Parameters
+/// - `state`: The known state of the video.
+/// - `entry`: The newer entry.
+fn merge_entry(state: &mut JournalEntry, entry: JournalEntry) {
+    state.stage = state.stage.max(entry.stage);
+    if !entry.timestamps.is_empty() {
+        state.timestamps = entry.timestamps;
+    }
+    if !entry.faces.is_empty() {
+        state.faces = entry.faces;
+    }
+    if !entry.transcript.is_empty() {
+        state.transcript = entry.transcript;
+    }
+    if !entry.timeline.is_empty() {
+        state.timeline = entry.timeline;
+    }
+    if !entry.story.is_empty() {
+        state.story = entry.story;
+    }
+    if entry.resume.is_some() {
+        state.resume = entry.resume;
+    }
+    if entry.new_path.is_some() {
+        state.new_path = entry.new_path;
+    }
+}
+
+/// Gets the path of the journal file of a run.
+///
+/// ### Parameters
+/// - `work_folder`: The path of the work folder.
+/// - `run_id`: The ID of the run.
+///
+/// ### Returns
+/// The path of the journal file.
+fn get_journal_path(work_folder: &str, run_id: &str) -> PathBuf {
+    let mut path = PathBuf::from(work_folder);
+    path.push("runs");
+    path.push(format!("{}.jsonl", run_id));
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an empty work folder for a test.
+    fn make_work_folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("aspargus-journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[test]
+    fn load_follows_a_renamed_video_under_its_new_path() {
+        let folder = make_work_folder("renamed");
+        let work_folder = folder.to_str().unwrap();
+        let journal = Journal::open(work_folder, "run".to_string()).unwrap();
+        journal.record_queued("/videos/a.mp4");
+        journal.record_queued("/videos/b.mp4");
+        let mut video = Video {
+            path: "/videos/a.mp4".to_string(),
+            stage: Stage::Resume,
+            ..Default::default()
+        };
+        video.resume.title = "A day at the beach".to_string();
+        journal.record(&video);
+        journal.record_moved("/videos/a.mp4", "/videos/beach.mp4", Stage::Renamed);
+        // A resumed run records the video under its new path
+        video.path = "/videos/beach.mp4".to_string();
+        journal.record(&video);
+        journal.record_moved("/videos/beach.mp4", "/library/beach.mp4", Stage::Organised);
+
+        let (_, states) = Journal::load(work_folder, "run").unwrap();
+        assert_eq!(states.len(), 2);
+        assert_eq!(states[0].path, "/videos/a.mp4");
+        assert_eq!(states[0].stage, Stage::Organised);
+        assert_eq!(states[0].new_path.as_deref(), Some("/library/beach.mp4"));
+        assert_eq!(states[0].resume.as_ref().unwrap().title, "A day at the beach");
+        assert_eq!(states[1].path, "/videos/b.mp4");
+        assert_eq!(states[1].stage, Stage::Queued);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn load_fails_for_an_unknown_run() {
+        let folder = make_work_folder("unknown");
+        assert!(Journal::load(folder.to_str().unwrap(), "missing").is_err());
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// The languages the models can be asked to answer in, as ISO 639-1 code and English name.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("ar", "Arabic"),
+    ("cs", "Czech"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fi", "Finnish"),
+    ("fr", "French"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("hu", "Hungarian"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("no", "Norwegian"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ro", "Romanian"),
+    ("ru", "Russian"),
+    ("sv", "Swedish"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("zh", "Chinese"),
+];
+
+/// Represents a language of the generated titles, descriptions and keywords.
+/// ### Fields
+/// - `code`: The ISO 639-1 code of the language, e.g. `fr`.
+/// - `name`: The English name of the language, used in the prompts, e.g. `French`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Language {
+    pub code: String,
+    pub name: String,
+}
+
+impl Default for Language {
+    fn default() -> Self {
+        Self {
+            code: "en".to_string(),
+            name: "English".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code)
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    /// Parses a language written as its code (e.g. `fr`) or its English name (e.g. `French`), whatever the case.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim().to_lowercase();
+        LANGUAGES
+            .iter()
+            .find(|(code, name)| *code == value || name.to_lowercase() == value)
+            .map(|(code, name)| Language {
+                code: code.to_string(),
+                name: name.to_string(),
+            })
+            .ok_or_else(|| {
+                format!(
+                    "Unknown language: {}, expected one of {}",
+                    value,
+                    LANGUAGES
+                        .iter()
+                        .map(|(code, _)| *code)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+impl TryFrom<String> for Language {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Language> for String {
+    fn from(language: Language) -> Self {
+        language.code
+    }
+}
+
+/// Parses a comma separated list of languages, e.g. `fr,de`, the first one being the main language.
+///
+/// ### Parameters
+/// - `value`: The list of languages.
+///
+/// ### Returns
+/// A Result containing the languages, without duplicates.
+///
+/// ### Errors
+/// Returns an error if a language is unknown or if the list is empty.
+pub fn parse_languages(value: &str) -> Result<Vec<Language>, String> {
+    let mut languages: Vec<Language> = Vec::new();
+    for language in value.split(',').filter(|language| !language.trim().is_empty()) {
+        let language = language.parse::<Language>()?;
+        if !languages.contains(&language) {
+            languages.push(language);
+        }
+    }
+    if languages.is_empty() {
+        return Err("At least one language must be given".to_string());
+    }
+    Ok(languages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_list_of_languages_without_duplicates() {
+        let languages = parse_languages("fr, de,fr").unwrap();
+        let codes: Vec<&str> = languages.iter().map(|language| language.code.as_str()).collect();
+        assert_eq!(codes, vec!["fr", "de"]);
+        assert!(parse_languages("fr,xx").is_err());
+    }
+
+    #[test]
+    fn rejects_an_empty_list_of_languages() {
+        assert!(parse_languages("").is_err());
+        assert!(parse_languages(" , ").is_err());
+    }
+}
@@ -1,19 +1,61 @@
+use self::cache::{AnalysisCache, AnalysisSettings};
+use self::captions::CaptionMode;
+use self::contact_sheet::ContactSheetMode;
+use self::custom_fields::CustomField;
+use self::faces::{FaceBackend, FaceLibrary};
+use self::failure::{Failure, FailurePolicy};
+use self::frames::FrameStorage;
+use self::image_resizer::ImageProfile;
+use self::journal::{Journal, Stage};
+use self::language::Language;
+use self::prompts::{PromptSet, PromptStage};
+use self::rename_template::{FolderTemplate, RenameTemplate};
+use self::sampling::SamplingStrategy;
+use self::sanitiser::{FileNameRules, SanitisationProfile};
+use self::server_pool::{DispatchStrategy, ServerPool};
 use self::settings::AspargusSettings;
-use anyhow;
+use self::transcription::TranscriptionBackend;
+use self::undo::{OperationKind, UndoManifest, UndoOperation};
 use aspargus_helper::VideoDataError;
-use ollama_rs::Ollama;
+use futures::stream::{self, StreamExt};
 use rayon::prelude::*;
 use serde_json;
+use std::collections::HashSet;
+use std::fmt;
 use std::fs;
 use std::path::Path;
-use std::sync::{Arc, Mutex};
-use video::Video;
-use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use video::{Resume, Video};
 mod aspargus_helper;
+mod cache;
+mod captions;
+mod contact_sheet;
+mod container_metadata;
+mod custom_fields;
+mod face_detector;
+mod faces;
+mod failure;
 mod file_management;
+mod frames;
 mod image_resizer;
+mod journal;
+mod language;
+mod prompts;
+mod rename_template;
+mod sampling;
+mod sanitiser;
+mod server_pool;
 mod settings;
+mod transcription;
+mod undo;
 mod video;
+mod xmp;
+
+/// The time after which the temp files of a run that was not modified are removed, the run being interrupted.
+const ORPHANED_TEMP_FILES_AGE: Duration = Duration::from_secs(24 * 60 * 60);
 
 /// Represents an Aspargus error.
 #[derive(Debug)]
@@ -37,20 +79,198 @@ impl fmt::Display for AspargusError {
 
 impl std::error::Error for AspargusError {}
 
+impl AspargusError {
+    /// Adds some context to the message of the error, such as the path of the video, keeping its kind. Generic errors are kept as is, as they concern all the videos.
+    ///
+    /// ### Parameters
+    /// - `context`: The context of the error.
+    ///
+    /// ### Returns
+    /// The error with the context at the start of its message.
+    fn with_context(self, context: &str) -> Self {
+        match self {
+            AspargusError::Io(msg) => AspargusError::Io(format!("{}, {}", context, msg)),
+            AspargusError::ParseError(msg) => AspargusError::ParseError(format!("{}, {}", context, msg)),
+            AspargusError::GenericError(msg) => AspargusError::GenericError(msg),
+            AspargusError::ProcessingError(msg) => AspargusError::ProcessingError(format!("{}, {}", context, msg)),
+        }
+    }
+}
+
 /// Represents an Aspargus instance.
 ///
 /// ### Fields
 /// - `videos`: An array of videos to be analysed.
 /// - `settings`: The Aspargus settings loaded from a file.
-/// - `cv_ollama`: The computer vision model prompter.
-/// - `text_ollama`: The text model prompter.
+/// - `cv_servers`: The computer vision servers, between which the requests are balanced.
+/// - `text_servers`: The text servers, between which the requests are balanced.
 /// - `videos_number`: The number of videos in the queue.
+/// - `cache`: The persistent analysis cache.
+/// - `force`: Flag if the videos must be analysed again even if they are in the cache.
+/// - `keep_frames`: Flag if the extracted frames must be kept in the temp folder of the run, for debugging.
+/// - `run_temp_folder`: The temp folder of the current run, where its frames and audio files are extracted.
+/// - `run_lock`: The lock file of the temp folder of the current run, held while the run is in progress.
+/// - `journal`: The journal of the current run, recording the stage reached by each video.
+/// - `undo_manifest`: The file operations of the current run, saved so that they can be reverted.
+/// - `failures`: The videos skipped during the current run, with the stage they failed and their error.
+/// - `prompts`: The prompt templates sent to the models.
+/// - `languages`: The languages of the resumes for the current run, the first one being the main language.
+/// - `custom_fields`: The valid custom fields of the settings, generated in addition to the title, description and keywords.
+/// - `faces`: The persistent library of the people seen in the videos.
 pub struct Aspargus {
     videos: Vec<Video>,
     settings: AspargusSettings,
-    cv_ollama: Ollama,
-    text_ollama: Ollama,
+    cv_servers: ServerPool,
+    text_servers: ServerPool,
+    videos_number: i32,
+    cache: AnalysisCache,
+    force: bool,
+    keep_frames: bool,
+    run_temp_folder: String,
+    run_lock: Option<fs::File>,
+    journal: Journal,
+    undo_manifest: UndoManifest,
+    failures: Vec<Failure>,
+    prompts: PromptSet,
+    languages: Vec<Language>,
+    custom_fields: Vec<CustomField>,
+    faces: FaceLibrary,
+}
+
+/// Represents what the videos share while a model stage runs on several of them at the same time.
+///
+/// ### Fields
+/// - `computer_vision_model`: The name of the computer vision model.
+/// - `text_model`: The name of the text model.
+/// - `prompts`: The prompt templates.
+/// - `languages`: The languages of the resumes, the first one being the main language.
+/// - `fields`: The custom fields of the resumes.
+/// - `captions`: Whether the frames are captioned one by one before the story is told.
+/// - `contact_sheet`: Whether the frames are sent to the computer vision model as a single contact sheet.
+/// - `image_profile`: How the frames are prepared for the computer vision model.
+/// - `keep_frames`: Whether the frames are kept once the models don't need them anymore.
+/// - `policy`: What to do when a video fails.
+/// - `videos_number`: The number of videos in the queue.
+/// - `journal`: The journal of the current run.
+/// - `cache`: The persistent analysis cache.
+/// - `failures`: The videos that failed.
+/// - `stop`: Flag set when a video failed and the run must be stopped, so that no other video is started.
+struct ModelContext<'a> {
+    computer_vision_model: &'a str,
+    text_model: &'a str,
+    prompts: &'a PromptSet,
+    languages: &'a [Language],
+    fields: &'a [CustomField],
+    captions: CaptionMode,
+    contact_sheet: ContactSheetMode,
+    image_profile: ImageProfile,
+    keep_frames: bool,
+    policy: FailurePolicy,
     videos_number: i32,
+    journal: &'a Journal,
+    cache: Mutex<&'a mut AnalysisCache>,
+    failures: Mutex<Vec<Failure>>,
+    stop: AtomicBool,
+}
+
+impl<'a> ModelContext<'a> {
+    /// Creates the context of a model stage.
+    ///
+    /// ### Parameters
+    /// - `settings`: The Aspargus settings.
+    /// - `prompts`: The prompt templates.
+    /// - `languages`: The languages of the resumes.
+    /// - `fields`: The custom fields of the resumes.
+    /// - `keep_frames`: Whether the frames are kept once the models don't need them anymore.
+    /// - `videos_number`: The number of videos in the queue.
+    /// - `journal`: The journal of the current run.
+    /// - `cache`: The persistent analysis cache.
+    ///
+    /// ### Returns
+    /// A new context, without failures.
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        settings: &'a AspargusSettings,
+        prompts: &'a PromptSet,
+        languages: &'a [Language],
+        fields: &'a [CustomField],
+        keep_frames: bool,
+        videos_number: i32,
+        journal: &'a Journal,
+        cache: &'a mut AnalysisCache,
+    ) -> Self {
+        Self {
+            computer_vision_model: &settings.computer_vision_model,
+            text_model: &settings.text_model,
+            prompts,
+            languages,
+            fields,
+            captions: settings.captions,
+            contact_sheet: settings.contact_sheet,
+            image_profile: settings.get_image_profile(),
+            keep_frames,
+            policy: settings.failure_policy,
+            videos_number,
+            journal,
+            cache: Mutex::new(cache),
+            failures: Mutex::new(Vec::new()),
+            stop: AtomicBool::new(false),
+        }
+    }
+
+    /// Stores the result of the analysis of a video in the cache.
+    ///
+    /// ### Parameters
+    /// - `video`: The analysed video.
+    fn store(&self, video: &Video) {
+        if let Err(error) = self.cache.lock().unwrap().store(video) {
+            log::error!("Error while storing the analysis in the cache: {}", error);
+        }
+    }
+
+    /// Removes the frames of a video once the models don't need them anymore: the frames held in memory are freed, and the thumbnails are removed unless they must be kept.
+    ///
+    /// ### Parameters
+    /// - `video`: The video.
+    fn remove_frames(&self, video: &mut Video) {
+        video.frames = Vec::new();
+        if !self.keep_frames {
+            file_management::remove_files(&video.thumbnails);
+        }
+    }
+
+    /// Skips a video that failed a stage, and stops the other videos if the run must be stopped.
+    ///
+    /// ### Parameters
+    /// - `video`: The video.
+    /// - `stage`: The stage the video failed.
+    /// - `error`: The error of the video.
+    fn fail(&self, video: &mut Video, stage: Stage, error: AspargusError) {
+        if self.policy.must_stop(&error) {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+        Aspargus::skip_video(video, stage, error, &self.failures);
+    }
+
+    /// Gets the failures of the stage, once it is over.
+    ///
+    /// ### Returns
+    /// The failures of the stage.
+    fn into_failures(self) -> Vec<Failure> {
+        self.failures.into_inner().unwrap()
+    }
+}
+
+impl Default for Aspargus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Aspargus {
+    fn drop(&mut self) {
+        self.remove_run_temp_folder();
+    }
 }
 
 impl Aspargus {
@@ -58,18 +278,54 @@ impl Aspargus {
     /// ### Returns
     /// A new Aspargus instance.
     pub fn new() -> Self {
-        let settings = settings::load_settings();
-        let computer_vision_server = settings.computer_vision_server.clone();
-        let computer_vision_server_port = settings.computer_vision_server_port.clone();
-        let text_server = settings.text_server.clone();
-        let text_server_port = settings.text_server_port.clone();
+        Self::with_settings(settings::load_settings())
+    }
+
+    /// Creates a new Aspargus instance with some settings, loading the cache, prompts and face library of their work folder.
+    /// ### Parameters
+    /// - `settings`: The Aspargus settings.
+    ///
+    /// ### Returns
+    /// A new Aspargus instance.
+    fn with_settings(settings: AspargusSettings) -> Self {
+        let (cv_servers, text_servers) = Self::make_server_pools(&settings);
         log::debug!("Temp folder: {}", settings.temp_folder);
+        let cache = AnalysisCache::load(settings.work_folder.as_str());
+        let prompts = match PromptSet::load(settings.work_folder.as_str(), settings.prompt_set.as_str()) {
+            Ok(prompts) => prompts,
+            Err(error) => {
+                log::error!("{}, using the built-in prompts", error);
+                PromptSet::default()
+            }
+        };
+        let languages = settings.languages.clone();
+        let custom_fields = match custom_fields::check_fields(&settings.custom_fields) {
+            Ok(_) => settings.custom_fields.clone(),
+            Err(error) => {
+                log::error!("{}, the custom fields are ignored", error);
+                Vec::new()
+            }
+        };
+        let faces = FaceLibrary::load(settings.work_folder.as_str());
+        let run_temp_folder = settings.temp_folder.clone();
         Self {
             videos: Vec::new(),
             settings,
-            cv_ollama: Ollama::new(computer_vision_server, computer_vision_server_port),
-            text_ollama: Ollama::new(text_server, text_server_port),
+            cv_servers,
+            text_servers,
             videos_number: 0,
+            cache,
+            force: false,
+            keep_frames: false,
+            run_temp_folder,
+            run_lock: None,
+            journal: Journal::default(),
+            undo_manifest: UndoManifest::new(),
+            failures: Vec::new(),
+            prompts,
+            languages,
+            custom_fields,
+            faces,
         }
     }
 
@@ -105,6 +361,7 @@ impl Aspargus {
     pub fn set_computer_vision_server(&mut self, server: String) {
         if self.settings.computer_vision_server != server {
             self.settings.computer_vision_server = server;
+            self.update_server_pools();
             match settings::save_settings(&self.settings) {
                 Ok(_) => (),
                 Err(error) => log::error!("{}", error),
@@ -118,6 +375,7 @@ impl Aspargus {
     pub fn set_computer_vision_server_port(&mut self, port: u16) {
         if self.settings.computer_vision_server_port != port {
             self.settings.computer_vision_server_port = port;
+            self.update_server_pools();
             match settings::save_settings(&self.settings) {
                 Ok(_) => (),
                 Err(error) => log::error!("{}", error),
@@ -131,6 +389,7 @@ impl Aspargus {
     pub fn set_text_server(&mut self, server: String) {
         if self.settings.text_server != server {
             self.settings.text_server = server;
+            self.update_server_pools();
             match settings::save_settings(&self.settings) {
                 Ok(_) => (),
                 Err(error) => log::error!("{}", error),
@@ -144,6 +403,7 @@ impl Aspargus {
     pub fn set_text_server_port(&mut self, port: u16) {
         if self.settings.text_server_port != port {
             self.settings.text_server_port = port;
+            self.update_server_pools();
             match settings::save_settings(&self.settings) {
                 Ok(_) => (),
                 Err(error) => log::error!("{}", error),
@@ -151,300 +411,1418 @@ impl Aspargus {
         }
     }
 
-    /// Sets the two steps flag.
+    /// Sets the computer vision servers between which the requests are balanced.
     /// ### Parameters
-    /// - `two_steps`: The two steps flag.
-    pub fn set_two_steps(&mut self, two_steps: bool) {
-        if self.settings.two_steps != two_steps {
-            self.settings.two_steps = two_steps;
+    /// - `servers`: The servers as `URL:PORT`, an empty list to use the computer vision server and port.
+    ///
+    /// ### Errors
+    /// Returns an error if a server is not a valid URL.
+    pub fn set_computer_vision_servers(&mut self, servers: Vec<String>) -> Result<(), AspargusError> {
+        for server in &servers {
+            server_pool::parse_endpoint(server).map_err(AspargusError::ParseError)?;
+        }
+        if self.settings.computer_vision_servers != servers {
+            self.settings.computer_vision_servers = servers;
+            self.update_server_pools();
             match settings::save_settings(&self.settings) {
                 Ok(_) => (),
                 Err(error) => log::error!("{}", error),
             }
         }
+        Ok(())
     }
 
-    pub fn is_two_steps(&mut self) -> bool {
-        self.settings.two_steps
+    /// Sets the text servers between which the requests are balanced.
+    /// ### Parameters
+    /// - `servers`: The servers as `URL:PORT`, an empty list to use the text server and port.
+    ///
+    /// ### Errors
+    /// Returns an error if a server is not a valid URL.
+    pub fn set_text_servers(&mut self, servers: Vec<String>) -> Result<(), AspargusError> {
+        for server in &servers {
+            server_pool::parse_endpoint(server).map_err(AspargusError::ParseError)?;
+        }
+        if self.settings.text_servers != servers {
+            self.settings.text_servers = servers;
+            self.update_server_pools();
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
+            }
+        }
+        Ok(())
     }
 
-    /// Add a whole list of videos to be analysed to Aspargus.
+    /// Sets how the requests are dispatched between the servers.
     /// ### Parameters
-    /// - `paths`: The paths of the videos to analyse.
-    pub fn add_videos(&mut self, paths: Vec<String>) -> Result<(), AspargusError> { 
-        for path in paths {
-            match self.add_video(path) {
-                Ok(_) => self.videos_number += 1,
-                Err(error) => {
-                    log::error!("Error while adding video: {}", error);
-                    return Err(error);
-                }
+    /// - `dispatch`: The dispatch strategy, as `round_robin` or `least_busy`.
+    ///
+    /// ### Errors
+    /// Returns an error if the dispatch strategy can't be parsed.
+    pub fn set_dispatch(&mut self, dispatch: &str) -> Result<(), AspargusError> {
+        let dispatch = dispatch
+            .parse::<DispatchStrategy>()
+            .map_err(AspargusError::ParseError)?;
+        if self.settings.dispatch != dispatch {
+            self.settings.dispatch = dispatch;
+            self.update_server_pools();
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
             }
         }
         Ok(())
     }
 
-    /// Gets a new numeric ID for a video.
-    /// ### Returns
-    /// A new numeric ID for a video.
-    fn get_new_video_numeric_id(&mut self) -> i32 {
-        if self.videos.len() >= 1 {
-            self.videos.last().unwrap().numeric_id + 1
-        } else {
-            1
+    /// Sets the time after which a request is sent to another server, when there are several servers.
+    /// ### Parameters
+    /// - `server_timeout`: The time in seconds, 0 to wait for the answer.
+    pub fn set_server_timeout(&mut self, server_timeout: u64) {
+        if self.settings.server_timeout != server_timeout {
+            self.settings.server_timeout = server_timeout;
+            self.update_server_pools();
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
+            }
         }
     }
 
-    /// Gets the name of the currently set computer vision model.
+    /// Creates the pools of computer vision and text servers from the settings.
+    ///
+    /// ### Parameters
+    /// - `settings`: The Aspargus settings.
+    ///
     /// ### Returns
-    /// The name of the currently set computer vision model.
-    pub fn get_computer_vision_model(&self) -> String {
-        self.settings.computer_vision_model.clone()
+    /// A tuple with the computer vision servers and the text servers.
+    fn make_server_pools(settings: &AspargusSettings) -> (ServerPool, ServerPool) {
+        let timeout = Some(settings.server_timeout)
+            .filter(|server_timeout| *server_timeout > 0)
+            .map(Duration::from_secs);
+        (
+            ServerPool::new(
+                "computer vision",
+                &settings.get_computer_vision_endpoints(),
+                settings.dispatch,
+                timeout,
+            ),
+            ServerPool::new("text", &settings.get_text_endpoints(), settings.dispatch, timeout),
+        )
     }
 
-    /// Gets the list of computer vision models available on the server.
-    /// ### Returns
-    /// A list of computer vision models available on the server.
-    pub async fn get_computer_vision_models_list(&self) -> Result<Vec<String>, AspargusError> { 
-        match aspargus_helper::get_models_for_server(&self.cv_ollama).await {
-            Ok(models) => Ok(models),
-            Err(error) => {
-                log::error!("Error while getting computer vision models list: {}", error);
-                Err(AspargusError::Io(format!(
-                    "Error while getting computer vision models list: {}",
-                    error
-                )))
+    /// Creates again the pools of servers after their settings changed.
+    fn update_server_pools(&mut self) {
+        (self.cv_servers, self.text_servers) = Self::make_server_pools(&self.settings);
+    }
+
+    /// Checks which servers answer and have the models, before sending them the videos that still have to be analysed.
+    ///
+    /// ### Errors
+    /// Returns an error if no server answers with the model for one of the roles.
+    pub async fn check_servers(&self) -> Result<(), AspargusError> {
+        let pending = |stage: Stage| {
+            self.videos
+                .iter()
+                .any(|video| !video.skip && video.stage < stage)
+        };
+        let to_error = |error: anyhow::Error| AspargusError::GenericError(error.to_string());
+        if self.settings.two_steps {
+            if pending(Stage::Story) {
+                self.cv_servers
+                    .check_health(&self.settings.computer_vision_model)
+                    .await
+                    .map_err(to_error)?;
+            }
+            if pending(Stage::Resume) {
+                self.text_servers
+                    .check_health(&self.settings.text_model)
+                    .await
+                    .map_err(to_error)?;
             }
+        } else if pending(Stage::Resume) {
+            self.cv_servers
+                .check_health(&self.settings.computer_vision_model)
+                .await
+                .map_err(to_error)?;
         }
+        Ok(())
     }
 
-    /// Gets the name of the currently set text model.
-    /// ### Returns
-    /// The name of the currently set text model.
-    pub fn get_text_model(&self) -> String {
-        self.settings.text_model.clone()
+    /// Sets the two steps flag.
+    /// ### Parameters
+    /// - `two_steps`: The two steps flag.
+    pub fn set_two_steps(&mut self, two_steps: bool) {
+        if self.settings.two_steps != two_steps {
+            self.settings.two_steps = two_steps;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
+            }
+        }
     }
 
-    /// Gets the list of text models available on the server.
-    /// ### Returns
-    /// A list of text models available on the server.
-    pub async fn get_text_models_list(&self) -> Result<Vec<String>, AspargusError> { 
-        match aspargus_helper::get_models_for_server(&self.text_ollama).await {
-            Ok(models) => Ok(models),
-            Err(error) => {
-                log::error!("Error while getting text models list: {}", error);
-                Err(AspargusError::Io(format!(
-                    "Error while getting text models list: {}",
-                    error
-                )))
+    /// Sets the frame sampling strategy.
+    /// ### Parameters
+    /// - `sampling`: The sampling strategy, as `count:<N>`, `interval:<SECONDS>` or `scene:<THRESHOLD>`.
+    ///
+    /// ### Errors
+    /// Returns an error if the sampling strategy can't be parsed.
+    pub fn set_sampling(&mut self, sampling: &str) -> Result<(), AspargusError> {
+        let sampling = sampling
+            .parse::<SamplingStrategy>()
+            .map_err(AspargusError::ParseError)?;
+        if self.settings.sampling != sampling {
+            self.settings.sampling = sampling;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
             }
         }
+        Ok(())
     }
 
-    /// Add a video to be analysed to Aspargus.
+    /// Sets the minimum number of frames to extract with the interval and scene detection strategies.
     /// ### Parameters
-    /// - `path`: The path of the video to analyse.
-    pub fn add_video(&mut self, path: String) -> Result<(), AspargusError> {
-        let the_path = Path::new(path.as_str());
-        if the_path.is_file() {
-            match Video::new(path.clone(), self.get_new_video_numeric_id()) {
-                Ok(video) => self.videos.push(video),
-                Err(error) => {
-                    if let Some(metadata_extraction_error) = error.downcast_ref::<VideoDataError>()
-                    {
-                        match metadata_extraction_error {
-                        VideoDataError::FFProbeNotFoundError(_) => return Err(AspargusError::GenericError("FFProbe is not found, we're quitting for now. Please install FFMpeg and FFProbe and put them in the path.".to_string())),
-                            VideoDataError::FrameExtractionError(_) => log::error!("Error while extracting metadata for: {}, it won't be processed further on.", path),
-                            _ => (), // Other cases are not for frame extraction
-                        }
-                    } else {
-                        log::error!("Error while extracting metadata for: {}, it won't be processed further on.", &path);
-                        return Err(AspargusError::ProcessingError(format!("Error while extracting metadata for: {}", &path)))
-                    }
-                }
+    /// - `min_frames`: The minimum number of frames.
+    pub fn set_min_frames(&mut self, min_frames: u32) {
+        if self.settings.min_frames != min_frames {
+            self.settings.min_frames = min_frames;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
             }
-        } else {
-            log::error!(
-                "File {} doesn't exist or is not a file, and therefore will be ignored.",
-                path
-            );
-            return Err(AspargusError::ProcessingError(format!(
-                "File {} doesn't exist or is not a file, and therefore will be ignored.",
-                path
-            )));
         }
-        Ok(())
     }
 
-    /// Extract frames for all the videos in the list in the Aspargus struct.
-    pub fn extract_frames(&mut self) -> Result<(), AspargusError> { 
-        let error_holder = Arc::new(Mutex::new(None));
-        self.videos.par_iter_mut().for_each(|video| {
-            log::info!(
-                "{}/{} - Extracting frames for {}",
-                video.numeric_id,
-                self.videos_number,
-                video.path
-            );
-            match aspargus_helper::extract_frames_for_video(self.settings.temp_folder.as_str(), video) {
-                Ok(thumbnails) => {
-                    video.thumbnails = thumbnails;
-                    //extract_faces_from_thumbnails(thumbnails);
-                }
-                Err(error) =>  {
-                    if let Some(extraction_error) = error.downcast_ref::<VideoDataError>() {
-                        match extraction_error {
-                            VideoDataError::FFMpegNotFoundError(_) => {
-                                let mut holder = error_holder.lock().unwrap();
-                                if holder.is_none() { // Only capture the first error
-                                    *holder = Some(anyhow::anyhow!("FFMpeg is not found, we're quitting for now. Please install FFMpeg and FFProbe and put them in the path."));
-                                }
-                            },
-                            VideoDataError::FrameExtractionError(_) => {
-                                video.skip = true;
-                                log::error!("{}/{} - Error while extracting frames for: {}, it won't be processed further on.", video.numeric_id, self.videos_number, error)
-                            },
-                            _ => (), // Other cases are not for frame extraction
-                        }
-                    } else {
-                        log::error!("{}/{} - Error while extracting frames for: {}, it won't be processed further on.", video.numeric_id, self.videos_number, error)
-                    }
-                },
+    /// Sets the sanitisation profile applied to the new file names.
+    /// ### Parameters
+    /// - `sanitisation`: The sanitisation profile, as `posix`, `windows` or `slug`.
+    ///
+    /// ### Errors
+    /// Returns an error if the sanitisation profile can't be parsed.
+    pub fn set_sanitisation(&mut self, sanitisation: &str) -> Result<(), AspargusError> {
+        let sanitisation = sanitisation
+            .parse::<SanitisationProfile>()
+            .map_err(AspargusError::ParseError)?;
+        if self.settings.sanitisation != sanitisation {
+            self.settings.sanitisation = sanitisation;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
             }
-        });
-        let mut locked_error: std::sync::MutexGuard<Option<anyhow::Error>> =
-            error_holder.lock().unwrap();
-        if let Some(err) = locked_error.take() {
-            Err(AspargusError::ProcessingError(format!("Error while extracting frames: {}", err.to_string())))
-        } else {
-            Ok(())
         }
+        Ok(())
     }
 
-    /// Runs the computer vision model for all the videos files. Note that this method must be run before the '''run_resume_model''' method.
-    pub async fn run_computer_vision_model(&mut self) {
-        for video in &mut self.videos {
-            if video.skip {
-                log::info!(
-                    "{}/{} - Skipping {}",
-                    video.numeric_id,
-                    self.videos_number,
-                    video.path
-                );
-            } else {
-                log::info!(
-                    "{}/{} - Running computer vision model for {}",
-                    video.numeric_id,
-                    self.videos_number,
-                    video.path
-                );
-                match aspargus_helper::run_computer_vision_model_for_video(
-                    &self.cv_ollama,
-                    &self.settings.computer_vision_model,
-                    video,
-                )
-                .await
-                {
-                    Ok(story) => video.story = story,
-                    Err(error) => log::error!(
-                        "{}/{} - Error while running computer vision model: {}",
-                        video.numeric_id,
-                        self.videos_number,
-                        error
-                    ),
-                }
+    /// Sets the flag to transliterate the new file names to ASCII.
+    /// ### Parameters
+    /// - `transliterate`: The flag to transliterate the new file names.
+    pub fn set_transliterate(&mut self, transliterate: bool) {
+        if self.settings.transliterate != transliterate {
+            self.settings.transliterate = transliterate;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
             }
         }
     }
 
-    /// Runs the computer vision model for all the videos files that is able to provide a full result without running the second step with the resume model.
-    pub async fn run_only_computer_vision_model(&mut self) {
-        for video in &mut self.videos {
-            if video.skip {
-                log::info!(
-                    "{}/{} - Skipping {}",
-                    video.numeric_id,
-                    self.videos_number,
-                    video.path
-                );
-            } else {
-                log::info!(
-                    "{}/{} - Running computer vision model for {}",
-                    video.numeric_id,
-                    self.videos_number,
-                    video.path
-                );
-                match aspargus_helper::run_only_computer_vision_model_for_video(
-                    &self.cv_ollama,
-                    &self.settings.computer_vision_model,
-                    video,
-                )
-                .await
-                {
-                    Ok(resume) => video.resume = resume,
-                    Err(error) => log::error!(
-                        "{}/{} - Error while running computer vision model: {}",
-                        video.numeric_id,
-                        self.videos_number,
-                        error
-                    ),
-                }
+    /// Sets the maximum length of the new file names.
+    /// ### Parameters
+    /// - `max_name_length`: The maximum length in bytes, extension included.
+    pub fn set_max_name_length(&mut self, max_name_length: usize) {
+        if self.settings.max_name_length != max_name_length {
+            self.settings.max_name_length = max_name_length;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
             }
         }
     }
 
-    /// Runs the text model for all the videos files based on the computer vision model's output.
-    pub async fn run_resume_model(&mut self) {
-        for video in &mut self.videos {
-            if video.skip {
-                log::info!(
-                    "{}/{} - Skipping {}",
-                    video.numeric_id,
-                    self.videos_number,
+    /// Sets what to do when a video fails a stage of the analysis.
+    /// ### Parameters
+    /// - `failure_policy`: The failure policy, as `fail_fast`, `skip` or `retry:<N>`.
+    ///
+    /// ### Errors
+    /// Returns an error if the failure policy can't be parsed.
+    pub fn set_failure_policy(&mut self, failure_policy: &str) -> Result<(), AspargusError> {
+        let failure_policy = failure_policy
+            .parse::<FailurePolicy>()
+            .map_err(AspargusError::ParseError)?;
+        if self.settings.failure_policy != failure_policy {
+            self.settings.failure_policy = failure_policy;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the number of videos sent to the models at the same time.
+    /// ### Parameters
+    /// - `concurrency`: The number of videos, at least 1.
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        let concurrency = concurrency.max(1);
+        if self.settings.concurrency != concurrency {
+            self.settings.concurrency = concurrency;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
+            }
+        }
+    }
+
+    /// Sets the maximum number of frames to extract with the interval and scene detection strategies.
+    /// ### Parameters
+    /// - `max_frames`: The maximum number of frames.
+    pub fn set_max_frames(&mut self, max_frames: u32) {
+        if self.settings.max_frames != max_frames {
+            self.settings.max_frames = max_frames;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
+            }
+        }
+    }
+
+    /// Sets where the extracted frames are held until they are sent to the models.
+    /// ### Parameters
+    /// - `frame_storage`: The frame storage, as `disk` or `memory`.
+    ///
+    /// ### Errors
+    /// Returns an error if the frame storage can't be parsed.
+    pub fn set_frame_storage(&mut self, frame_storage: &str) -> Result<(), AspargusError> {
+        let frame_storage = frame_storage
+            .parse::<FrameStorage>()
+            .map_err(AspargusError::ParseError)?;
+        if self.settings.frame_storage != frame_storage {
+            self.settings.frame_storage = frame_storage;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets whether the frames are captioned one by one before the story is told.
+    /// ### Parameters
+    /// - `captions`: The caption mode, as `off`, `timeline` or `story`.
+    ///
+    /// ### Errors
+    /// Returns an error if the caption mode can't be parsed.
+    pub fn set_captions(&mut self, captions: &str) -> Result<(), AspargusError> {
+        let captions = captions
+            .parse::<CaptionMode>()
+            .map_err(AspargusError::ParseError)?;
+        if self.settings.captions != captions {
+            self.settings.captions = captions;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets whether the frames are sent to the computer vision model as a single contact sheet.
+    /// ### Parameters
+    /// - `contact_sheet`: The contact sheet mode, as `off`, `on` or `preview`.
+    ///
+    /// ### Errors
+    /// Returns an error if the contact sheet mode can't be parsed.
+    pub fn set_contact_sheet(&mut self, contact_sheet: &str) -> Result<(), AspargusError> {
+        let contact_sheet = contact_sheet
+            .parse::<ContactSheetMode>()
+            .map_err(AspargusError::ParseError)?;
+        if self.settings.contact_sheet != contact_sheet {
+            self.settings.contact_sheet = contact_sheet;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the speech-to-text backend used to transcribe the audio of the videos.
+    /// ### Parameters
+    /// - `transcription`: The backend, as `whisper:<MODEL_PATH>` or `command:<COMMAND>`, or `none` to disable the transcription.
+    ///
+    /// ### Errors
+    /// Returns an error if the backend can't be parsed.
+    pub fn set_transcription(&mut self, transcription: &str) -> Result<(), AspargusError> {
+        let transcription = if transcription == "none" {
+            None
+        } else {
+            Some(
+                transcription
+                    .parse::<TranscriptionBackend>()
+                    .map_err(AspargusError::ParseError)?,
+            )
+        };
+        if self.settings.transcription != transcription {
+            self.settings.transcription = transcription;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the face detection backend used to recognise the recurring people of the videos. The similarity threshold follows the backend, unless it was changed in the settings.
+    /// ### Parameters
+    /// - `faces`: The backend, as `builtin` or `plugin:<COMMAND>`, or `none` to disable the face detection.
+    ///
+    /// ### Errors
+    /// Returns an error if the backend can't be parsed.
+    pub fn set_faces(&mut self, faces: &str) -> Result<(), AspargusError> {
+        let faces = if faces == "none" {
+            None
+        } else {
+            Some(faces.parse::<FaceBackend>().map_err(AspargusError::ParseError)?)
+        };
+        if self.settings.faces != faces {
+            let get_default_threshold = |faces: &Option<FaceBackend>| {
+                faces.as_ref().map_or(faces::DEFAULT_FACE_THRESHOLD, FaceBackend::get_default_threshold)
+            };
+            if self.settings.face_threshold == get_default_threshold(&self.settings.faces) {
+                self.settings.face_threshold = get_default_threshold(&faces);
+            }
+            self.settings.faces = faces;
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the set of prompt templates, loaded from the `prompts` folder of the work folder.
+    /// ### Parameters
+    /// - `prompt_set`: The name of the prompt set.
+    ///
+    /// ### Errors
+    /// Returns an error if the prompt set doesn't exist or one of its templates is invalid.
+    pub fn set_prompt_set(&mut self, prompt_set: &str) -> Result<(), AspargusError> {
+        self.prompts = PromptSet::load(self.settings.work_folder.as_str(), prompt_set)?;
+        if self.settings.prompt_set != prompt_set {
+            self.settings.prompt_set = prompt_set.to_string();
+            match settings::save_settings(&self.settings) {
+                Ok(_) => (),
+                Err(error) => log::error!("{}", error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the languages of the resumes for the current run, overriding the languages of the settings without saving them.
+    /// ### Parameters
+    /// - `languages`: The comma separated codes of the languages, e.g. `fr,de`, the first one being the main language and the others translations.
+    ///
+    /// ### Errors
+    /// Returns an error if a language is unknown or if no language is given.
+    pub fn set_languages(&mut self, languages: &str) -> Result<(), AspargusError> {
+        self.languages = language::parse_languages(languages).map_err(AspargusError::ParseError)?;
+        Ok(())
+    }
+
+    pub fn is_two_steps(&mut self) -> bool {
+        self.settings.two_steps
+    }
+
+    /// Sets the force flag, to analyse the videos again even if their result is in the cache. This flag is not saved in the settings.
+    /// ### Parameters
+    /// - `force`: The force flag.
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    /// Sets the keep frames flag, to keep the extracted frames in the temp folder of the run for debugging. This flag is not saved in the settings.
+    /// ### Parameters
+    /// - `keep_frames`: The keep frames flag.
+    pub fn set_keep_frames(&mut self, keep_frames: bool) {
+        self.keep_frames = keep_frames;
+    }
+
+    /// Creates the temp folder of the current run, after removing the temp files left by the interrupted runs.
+    ///
+    /// ### Parameters
+    /// - `run_id`: The ID of the run, used as the name of its temp folder.
+    ///
+    /// ### Errors
+    /// Returns an error if the temp folder of the run can't be created.
+    fn make_run_temp_folder(&mut self, run_id: &str) -> Result<(), AspargusError> {
+        file_management::remove_orphaned_temp_files(
+            self.settings.temp_folder.as_str(),
+            run_id,
+            ORPHANED_TEMP_FILES_AGE,
+        );
+        match file_management::make_run_temp_folder(self.settings.temp_folder.as_str(), run_id) {
+            Ok((folder, lock)) => {
+                log::debug!("Temp folder of the run: {}", folder);
+                self.run_temp_folder = folder;
+                self.run_lock = Some(lock);
+                Ok(())
+            }
+            Err(error) => Err(AspargusError::Io(format!(
+                "Error while creating the temp folder of the run: {}",
+                error
+            ))),
+        }
+    }
+
+    /// Removes the temp folder of the current run with the frames left in it, unless the frames must be kept. It is also called when Aspargus is dropped, so the folder is removed on every exit path.
+    pub fn remove_run_temp_folder(&mut self) {
+        if self.run_temp_folder == self.settings.temp_folder {
+            return;
+        }
+        self.run_lock = None;
+        let run_temp_folder = std::mem::replace(&mut self.run_temp_folder, self.settings.temp_folder.clone());
+        if self.keep_frames {
+            log::info!("The frames of the run are kept in {}", run_temp_folder);
+        } else {
+            file_management::remove_folder(run_temp_folder.as_str());
+        }
+    }
+
+    /// Starts a new run, with its own journal recording the progress of each video.
+    /// ### Returns
+    /// A Result containing the ID of the new run.
+    pub fn start_run(&mut self) -> Result<String, AspargusError> {
+        match Journal::create(self.settings.work_folder.as_str()) {
+            Ok(journal) => {
+                let run_id = journal.run_id.clone();
+                self.journal = journal;
+                log::info!(
+                    "Run ID: {} (use --resume {} to continue it if it is interrupted)",
+                    self.journal.run_id,
+                    self.journal.run_id
+                );
+                self.make_run_temp_folder(run_id.as_str())?;
+                Ok(run_id)
+            }
+            Err(error) => Err(AspargusError::Io(format!(
+                "Error while creating the journal of the run: {}",
+                error
+            ))),
+        }
+    }
+
+    /// Continues a previous run from its journal: the videos of that run are added again, and each of them continues from the last stage it completed. A video that can't be added again, e.g. deleted since, is handled according to the failure policy.
+    /// ### Parameters
+    /// - `run_id`: The ID of the run to continue.
+    ///
+    /// ### Errors
+    /// Returns an error if the journal can't be loaded, or if a video can't be added and the run must be stopped.
+    pub fn continue_run(&mut self, run_id: &str) -> Result<(), AspargusError> {
+        let (journal, states) = match Journal::load(self.settings.work_folder.as_str(), run_id) {
+            Ok(loaded) => loaded,
+            Err(error) => return Err(AspargusError::Io(error.to_string())),
+        };
+        self.journal = journal;
+        self.make_run_temp_folder(run_id)?;
+        log::info!("Continuing run {} with {} videos", run_id, states.len());
+        for state in states {
+            let path = state.new_path.clone().unwrap_or(state.path.clone());
+            if !self.add_video_with_policy(path)? {
+                continue;
+            }
+            let video = self.videos.last_mut().unwrap();
+            if state.stage <= video.stage {
+                continue;
+            }
+            video.stage = state.stage;
+            video.transcript = state.transcript;
+            video.timeline = state.timeline;
+            video.face_clusters = state.faces;
+            video.people = self.faces.get_names(&video.face_clusters);
+            video.story = state.story;
+            if let Some(resume) = state.resume {
+                video.resume = resume;
+            }
+            if video.stage >= Stage::Frames && video.stage <= Stage::Captions {
+                video.thumbnails = file_management::list_matching_files(
+                    self.run_temp_folder.as_str(),
+                    video.id.as_str(),
+                );
+                if video.thumbnails.is_empty() {
+                    video.stage = Stage::Metadata;
+                } else if state.timestamps.len() == video.thumbnails.len() {
+                    video.timestamps = state.timestamps;
+                }
+            }
+            log::debug!("{} continues after stage {:?}", video.path, video.stage);
+        }
+        Ok(())
+    }
+
+    /// Add a whole list of videos to be analysed to Aspargus. A video that can't be added is handled according to the failure policy.
+    /// ### Parameters
+    /// - `paths`: The paths of the videos to analyse.
+    ///
+    /// ### Errors
+    /// Returns an error if a video can't be added and the run must be stopped.
+    pub fn add_videos(&mut self, paths: Vec<String>) -> Result<(), AspargusError> { 
+        for path in &paths {
+            self.journal.record_queued(path);
+        }
+        for path in paths {
+            self.add_video_with_policy(path)?;
+        }
+        Ok(())
+    }
+
+    /// Adds a video to be analysed to Aspargus, handling the errors according to the failure policy: the video is tried again, or skipped and recorded in the failures of the run.
+    /// ### Parameters
+    /// - `path`: The path of the video to analyse.
+    ///
+    /// ### Returns
+    /// A Result containing true if the video was added, false if it was skipped.
+    ///
+    /// ### Errors
+    /// Returns an error if the video can't be added and the run must be stopped.
+    fn add_video_with_policy(&mut self, path: String) -> Result<bool, AspargusError> {
+        let policy = self.settings.failure_policy;
+        let label = format!("Error while adding video {}", path);
+        match failure::run_with_retries(&policy, &label, || self.add_video(path.clone())) {
+            Ok(_) => {
+                self.videos_number += 1;
+                Ok(true)
+            }
+            Err(error) => {
+                log::error!("Error while adding video: {}", error);
+                if policy.must_stop(&error) {
+                    return Err(error);
+                }
+                self.failures.push(Failure {
+                    path,
+                    stage: Stage::Metadata,
+                    error,
+                });
+                Ok(false)
+            }
+        }
+    }
+
+    /// Gets a new numeric ID for a video.
+    /// ### Returns
+    /// A new numeric ID for a video.
+    fn get_new_video_numeric_id(&mut self) -> i32 {
+        if !self.videos.is_empty() {
+            self.videos.last().unwrap().numeric_id + 1
+        } else {
+            1
+        }
+    }
+
+    /// Gets the name of the currently set computer vision model.
+    /// ### Returns
+    /// The name of the currently set computer vision model.
+    pub fn get_computer_vision_model(&self) -> String {
+        self.settings.computer_vision_model.clone()
+    }
+
+    /// Gets the list of computer vision models available on the servers.
+    /// ### Returns
+    /// A list of computer vision models available on the servers.
+    pub async fn get_computer_vision_models_list(&self) -> Result<Vec<String>, AspargusError> { 
+        match self.cv_servers.list_models().await {
+            Ok(models) => Ok(models),
+            Err(error) => {
+                log::error!("Error while getting computer vision models list: {}", error);
+                Err(AspargusError::Io(format!(
+                    "Error while getting computer vision models list: {}",
+                    error
+                )))
+            }
+        }
+    }
+
+    /// Gets the name of the currently set text model.
+    /// ### Returns
+    /// The name of the currently set text model.
+    pub fn get_text_model(&self) -> String {
+        self.settings.text_model.clone()
+    }
+
+    /// Gets the list of text models available on the servers.
+    /// ### Returns
+    /// A list of text models available on the servers.
+    pub async fn get_text_models_list(&self) -> Result<Vec<String>, AspargusError> { 
+        match self.text_servers.list_models().await {
+            Ok(models) => Ok(models),
+            Err(error) => {
+                log::error!("Error while getting text models list: {}", error);
+                Err(AspargusError::Io(format!(
+                    "Error while getting text models list: {}",
+                    error
+                )))
+            }
+        }
+    }
+
+    /// Sets the models, prompts, languages, custom fields and the other settings of the run changing the analysis in the analysis cache, so that the results made with other ones are not picked up.
+    fn update_cache_settings(&mut self) {
+        let settings = AnalysisSettings {
+            computer_vision_model: self.settings.computer_vision_model.as_str(),
+            text_model: if self.settings.two_steps {
+                Some(self.settings.text_model.as_str())
+            } else {
+                None
+            },
+            prompts: &self.prompts,
+            languages: &self.languages,
+            custom_fields: &self.custom_fields,
+            sampling: &self.settings.sampling,
+            min_frames: self.settings.min_frames,
+            max_frames: self.settings.max_frames,
+            image_profile: image_resizer::select_profile(&self.settings.image_profiles, self.settings.computer_vision_model.as_str()),
+            captions: self.settings.captions,
+            contact_sheet: self.settings.contact_sheet != ContactSheetMode::Off,
+            transcription: self.settings.transcription.as_ref(),
+            faces: self.settings.faces.as_ref(),
+            face_threshold: self.settings.face_threshold,
+        };
+        self.cache.set_settings(&settings);
+    }
+
+    /// Add a video to be analysed to Aspargus.
+    /// ### Parameters
+    /// - `path`: The path of the video to analyse.
+    pub fn add_video(&mut self, path: String) -> Result<(), AspargusError> {
+        let the_path = Path::new(path.as_str());
+        if the_path.is_file() {
+            let numeric_id = self.get_new_video_numeric_id();
+            self.update_cache_settings();
+            let cache = if self.force { None } else { Some(&self.cache) };
+            match Video::new(path.clone(), numeric_id, cache) {
+                Ok(mut video) => {
+                    if video.cached {
+                        log::info!("Found a cached analysis for {}", path);
+                    }
+                    video.people = self.faces.get_names(&video.face_clusters);
+                    if video.cached {
+                        video.resume.add_people(&video.people);
+                    }
+                    self.journal.record(&video);
+                    self.videos.push(video)
+                }
+                Err(error) => {
+                    return Err(failure::classify_error(error)
+                        .with_context(format!("Error while extracting metadata for: {}", path).as_str()));
+                }
+            }
+        } else {
+            return Err(AspargusError::Io(format!(
+                "File {} doesn't exist or is not a file, and therefore will be ignored.",
+                path
+            )));
+        }
+        Ok(())
+    }
+
+    /// Extract frames for all the videos in the list in the Aspargus struct. A video whose frames can't be extracted is handled according to the failure policy.
+    ///
+    /// ### Errors
+    /// Returns an error if the frames of a video can't be extracted and the run must be stopped.
+    pub fn extract_frames(&mut self) -> Result<(), AspargusError> { 
+        let policy = self.settings.failure_policy;
+        let profile = self.settings.get_image_profile();
+        let failures = Mutex::new(Vec::new());
+        self.videos.par_iter_mut().filter(|video| !video.skip && video.stage < Stage::Frames).for_each(|video| {
+            log::info!(
+                "{}/{} - Extracting frames for {}",
+                video.numeric_id,
+                self.videos_number,
+                video.path
+            );
+            let label = format!("{}/{} - Error while extracting frames", video.numeric_id, self.videos_number);
+            let result = failure::run_with_retries(&policy, &label, || {
+                match self.settings.frame_storage {
+                    FrameStorage::Disk => aspargus_helper::extract_frames_for_video(
+                        self.run_temp_folder.as_str(),
+                        video,
+                        &self.settings.sampling,
+                        self.settings.min_frames,
+                        self.settings.max_frames,
+                    )
+                    .map(|(thumbnails, timestamps, sampling)| (thumbnails, Vec::new(), timestamps, sampling)),
+                    FrameStorage::Memory => aspargus_helper::extract_frames_in_memory(
+                        video,
+                        &self.settings.sampling,
+                        self.settings.min_frames,
+                        self.settings.max_frames,
+                        profile.max_edge,
+                    )
+                    .map(|(frames, timestamps, sampling)| (Vec::new(), frames, timestamps, sampling)),
+                }
+                .map_err(failure::classify_error)
+            });
+            match result {
+                Ok((thumbnails, frames, timestamps, sampling)) => {
+                    video.thumbnails = thumbnails;
+                    video.frames = frames;
+                    log::debug!(
+                        "{}/{} - Extracted {} frames with {}",
+                        video.numeric_id,
+                        self.videos_number,
+                        video.get_frame_count(),
+                        sampling
+                    );
+                    if self.keep_frames && !video.frames.is_empty() {
+                        frames::write_frames(self.run_temp_folder.as_str(), video);
+                    }
+                    video.timestamps = timestamps;
+                    video.sampling = Some(sampling);
+                    if self.settings.contact_sheet != ContactSheetMode::Off {
+                        match aspargus_helper::make_contact_sheet(video, &profile) {
+                            Ok(sheet) => video.contact_sheet = sheet,
+                            Err(error) => log::warn!("{}/{} - Couldn't make the contact sheet: {}", video.numeric_id, self.videos_number, error),
+                        }
+                    }
+                    video.stage = Stage::Frames;
+                    self.journal.record(video);
+                }
+                Err(error) =>  {
+                    log::error!("{}/{} - Error while extracting frames: {}, it won't be processed further on.", video.numeric_id, self.videos_number, error);
+                    Self::skip_video(video, Stage::Frames, error, &failures);
+                },
+            }
+        });
+        self.handle_failures(failures.into_inner().unwrap())
+    }
+
+    /// Records the videos that failed a stage run in parallel, and checks if the run must be stopped.
+    ///
+    /// ### Parameters
+    /// - `failures`: The failures of the stage.
+    ///
+    /// ### Errors
+    /// Returns the first error that must stop the run, if any.
+    fn handle_failures(&mut self, failures: Vec<Failure>) -> Result<(), AspargusError> {
+        let mut stop_error = None;
+        for failure in failures {
+            if stop_error.is_none() && self.settings.failure_policy.must_stop(&failure.error) {
+                stop_error = Some(failure.error);
+            } else {
+                self.failures.push(failure);
+            }
+        }
+        match stop_error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Marks a video that failed a stage as skipped, and records its failure.
+    ///
+    /// ### Parameters
+    /// - `video`: The video.
+    /// - `stage`: The stage the video failed.
+    /// - `error`: The error of the video.
+    /// - `failures`: The failures of the stage.
+    fn skip_video(video: &mut Video, stage: Stage, error: AspargusError, failures: &Mutex<Vec<Failure>>) {
+        video.skip = true;
+        failures.lock().unwrap().push(Failure {
+            path: video.path.clone(),
+            stage,
+            error,
+        });
+    }
+
+    /// Detects the faces in the frames of all the videos, if a face detection backend is set. The faces of the same person in a video are merged, each person is then added to the cluster of the same person in the face library, and the names given to the clusters are given to the models and added to the keywords.
+    /// The frames are analysed in parallel on the CPU, and a frame the backend fails for is ignored.
+    pub fn detect_faces(&mut self) {
+        let Some(detector) = &self.settings.faces else {
+            return;
+        };
+        let detected: Vec<(usize, Vec<Vec<f32>>)> = self
+            .videos
+            .par_iter()
+            .enumerate()
+            .filter(|(_, video)| !video.skip && video.stage < Stage::Faces)
+            .map(|(index, video)| {
+                log::info!(
+                    "{}/{} - Detecting faces for {}",
+                    video.numeric_id,
+                    self.videos_number,
                     video.path
                 );
-            } else {
+                if video.frames.is_empty() {
+                    return (index, faces::extract_faces_from_thumbnails(detector, &video.thumbnails));
+                }
+                // The backend reads files, so the frames held in memory are written for it
+                let thumbnails = frames::write_frames(self.run_temp_folder.as_str(), video);
+                let embeddings = faces::extract_faces_from_thumbnails(detector, &thumbnails);
+                if !self.keep_frames {
+                    file_management::remove_files(&thumbnails);
+                }
+                (index, embeddings)
+            })
+            .collect();
+        if detected.is_empty() {
+            return;
+        }
+        for (index, embeddings) in detected {
+            let video = &mut self.videos[index];
+            let mut clusters = Vec::new();
+            for embedding in faces::merge_faces(&embeddings, self.settings.face_threshold) {
+                let id = self.faces.assign(&embedding, self.settings.face_threshold, &video.path);
+                if !clusters.contains(&id) {
+                    clusters.push(id);
+                }
+            }
+            video.people = self.faces.get_names(&clusters);
+            log::debug!(
+                "{}/{} - Found {} faces of {} people, recognised: {}",
+                video.numeric_id,
+                self.videos_number,
+                embeddings.len(),
+                clusters.len(),
+                video.people.join(", ")
+            );
+            video.face_clusters = clusters;
+            video.stage = Stage::Faces;
+            self.journal.record(video);
+        }
+        if let Err(error) = self.faces.save() {
+            log::error!("Error while saving the face library: {}", error);
+        }
+    }
+
+    /// Prints the clusters of faces of the face library, with their ID, name, number of videos and the video they were first seen in, so that they can be named.
+    pub fn print_faces(&self) {
+        if self.faces.clusters.is_empty() {
+            println!("No faces found yet, set a face detection backend with --faces and analyse some videos first.");
+            return;
+        }
+        for cluster in &self.faces.clusters {
+            let name = if cluster.name.is_empty() {
+                "<unnamed>"
+            } else {
+                cluster.name.as_str()
+            };
+            println!(
+                "{}: {} (seen in {} videos, first in {})",
+                cluster.id, name, cluster.count, cluster.sample
+            );
+        }
+    }
+
+    /// Names the person of a cluster of the face library, the name being used for all the videos analysed from then on.
+    ///
+    /// ### Parameters
+    /// - `id`: The ID of the cluster.
+    /// - `name`: The name of the person, empty to forget it.
+    ///
+    /// ### Errors
+    /// Returns an error if the cluster doesn't exist or if the face library can't be saved.
+    pub fn name_face(&mut self, id: u32, name: &str) -> Result<(), AspargusError> {
+        self.faces.name_cluster(id, name).map_err(AspargusError::ParseError)?;
+        self.faces
+            .save()
+            .map_err(|error| AspargusError::Io(format!("Error while saving the face library: {}", error)))?;
+        log::info!("Face cluster {} named {}", id, name);
+        Ok(())
+    }
+
+    /// Transcribes the audio of all the videos, if a speech-to-text backend is set. The transcripts are then given to the models together with the frames.
+    /// A video whose audio can't be transcribed is still analysed, only with its frames.
+    pub fn transcribe_audio(&mut self) -> Result<(), AspargusError> {
+        let Some(transcriber) = &self.settings.transcription else {
+            return Ok(());
+        };
+        for video in &mut self.videos {
+            if video.skip || video.stage >= Stage::Transcript {
+                continue;
+            }
+            log::info!(
+                "{}/{} - Transcribing audio for {}",
+                video.numeric_id,
+                self.videos_number,
+                video.path
+            );
+            match transcription::transcribe_video(
+                self.run_temp_folder.as_str(),
+                transcriber,
+                video,
+            ) {
+                Ok(transcript) => {
+                    log::debug!("Transcript: {}", transcript);
+                    video.transcript = transcript;
+                    video.stage = Stage::Transcript;
+                    self.journal.record(video);
+                }
+                Err(error) => {
+                    if let Some(VideoDataError::FFMpegNotFoundError(_)) =
+                        error.downcast_ref::<VideoDataError>()
+                    {
+                        return Err(AspargusError::GenericError("FFMpeg is not found, we're quitting for now. Please install FFMpeg and FFProbe and put them in the path.".to_string()));
+                    }
+                    log::warn!(
+                        "{}/{} - Error while transcribing audio: {}, the video will be analysed without it.",
+                        video.numeric_id,
+                        self.videos_number,
+                        error
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the computer vision model for all the videos files. Note that this method must be run before the '''run_resume_model''' method.
+    /// Up to `concurrency` videos are sent to the model at the same time, and a video the model fails for is handled according to the failure policy.
+    ///
+    /// ### Errors
+    /// Returns an error if the model fails for a video and the run must be stopped.
+    pub async fn run_computer_vision_model(&mut self) -> Result<(), AspargusError> {
+        let context = ModelContext::new(
+            &self.settings,
+            &self.prompts,
+            &self.languages,
+            &self.custom_fields,
+            self.keep_frames,
+            self.videos_number,
+            &self.journal,
+            &mut self.cache,
+        );
+        stream::iter(self.videos.iter_mut())
+            .for_each_concurrent(self.settings.concurrency, |video| async {
+                Self::run_computer_vision_model_on(video, &self.cv_servers, &context).await;
+            })
+            .await;
+        let failures = context.into_failures();
+        self.handle_failures(failures)
+    }
+
+    /// Runs the computer vision model for all the videos files that is able to provide a full result without running the second step with the resume model.
+    /// Up to `concurrency` videos are sent to the model at the same time, and a video the model fails for is handled according to the failure policy.
+    ///
+    /// ### Errors
+    /// Returns an error if the model fails for a video and the run must be stopped.
+    pub async fn run_only_computer_vision_model(&mut self) -> Result<(), AspargusError> {
+        let context = ModelContext::new(
+            &self.settings,
+            &self.prompts,
+            &self.languages,
+            &self.custom_fields,
+            self.keep_frames,
+            self.videos_number,
+            &self.journal,
+            &mut self.cache,
+        );
+        stream::iter(self.videos.iter_mut())
+            .for_each_concurrent(self.settings.concurrency, |video| async {
+                Self::run_only_computer_vision_model_on(video, &self.cv_servers, &context).await;
+            })
+            .await;
+        let failures = context.into_failures();
+        self.handle_failures(failures)
+    }
+
+    /// Runs the text model for all the videos files based on the computer vision model's output.
+    /// Up to `concurrency` videos are sent to the model at the same time, and a video the model fails for is handled according to the failure policy.
+    ///
+    /// ### Errors
+    /// Returns an error if the model fails for a video and the run must be stopped.
+    pub async fn run_resume_model(&mut self) -> Result<(), AspargusError> {
+        let context = ModelContext::new(
+            &self.settings,
+            &self.prompts,
+            &self.languages,
+            &self.custom_fields,
+            self.keep_frames,
+            self.videos_number,
+            &self.journal,
+            &mut self.cache,
+        );
+        stream::iter(self.videos.iter_mut())
+            .for_each_concurrent(self.settings.concurrency, |video| async {
+                Self::run_resume_model_on(video, &self.text_servers, &context).await;
+            })
+            .await;
+        let failures = context.into_failures();
+        self.handle_failures(failures)
+    }
+
+    /// Runs the two steps of the analysis as a pipeline: each video is handed to the text model as soon as the computer vision model has told its story, so that both models work at the same time and the results come one video after the other.
+    /// Up to `concurrency` videos are sent to each model at the same time, and a video a model fails for is handled according to the failure policy.
+    ///
+    /// ### Errors
+    /// Returns an error if a model fails for a video and the run must be stopped.
+    pub async fn run_pipelined_models(&mut self) -> Result<(), AspargusError> {
+        let context = ModelContext::new(
+            &self.settings,
+            &self.prompts,
+            &self.languages,
+            &self.custom_fields,
+            self.keep_frames,
+            self.videos_number,
+            &self.journal,
+            &mut self.cache,
+        );
+        let concurrency = self.settings.concurrency;
+        let (sender, mut receiver) = mpsc::unbounded_channel::<&mut Video>();
+        let story_stage = async {
+            let sender = sender;
+            stream::iter(self.videos.iter_mut())
+                .for_each_concurrent(concurrency, |video| async {
+                    if Self::run_computer_vision_model_on(video, &self.cv_servers, &context).await {
+                        // The receiver only stops once all the stories are sent
+                        let _ = sender.send(video);
+                    }
+                })
+                .await;
+        };
+        let resume_stage = stream::poll_fn(|cx| receiver.poll_recv(cx)).for_each_concurrent(
+            concurrency,
+            |video| async {
+                Self::run_resume_model_on(video, &self.text_servers, &context).await;
+            },
+        );
+        tokio::join!(story_stage, resume_stage);
+        drop(receiver);
+        let failures = context.into_failures();
+        self.handle_failures(failures)
+    }
+
+    /// Runs the computer vision model to caption the frames of a video one by one, unless the frames are not captioned or their captions are already available.
+    ///
+    /// ### Parameters
+    /// - `video`: The video to analyse.
+    /// - `servers`: The computer vision servers.
+    /// - `context`: The context shared by the videos of the stage.
+    ///
+    /// ### Returns
+    /// True if the video can go on with its story, false if its frames couldn't be captioned.
+    async fn run_caption_model_on(video: &mut Video, servers: &ServerPool, context: &ModelContext<'_>) -> bool {
+        if context.captions == CaptionMode::Off || video.stage >= Stage::Captions {
+            return true;
+        }
+        log::info!(
+            "{}/{} - Captioning {} frames for {}",
+            video.numeric_id,
+            context.videos_number,
+            video.get_frame_count(),
+            video.path
+        );
+        let label = format!("{}/{} - Error while captioning frames", video.numeric_id, context.videos_number);
+        let the_video = &*video;
+        let result = failure::run_with_retries_async(&context.policy, &label, || async {
+            servers
+                .run(|ollama| async move {
+                    aspargus_helper::run_caption_model_for_video(&ollama, context.computer_vision_model, context.prompts, &context.image_profile, the_video).await
+                })
+                .await
+                .map_err(failure::classify_error)
+        })
+        .await;
+        match result {
+            Ok(timeline) => {
+                video.timeline = timeline;
+                video.stage = Stage::Captions;
+                context.journal.record(video);
+                true
+            }
+            Err(error) => {
+                log::error!(
+                    "{}/{} - Error while captioning frames: {}",
+                    video.numeric_id,
+                    context.videos_number,
+                    error
+                );
+                context.fail(video, Stage::Captions, error);
+                false
+            }
+        }
+    }
+
+    /// Runs the computer vision model for a video, unless it is skipped or its story is already available.
+    ///
+    /// ### Parameters
+    /// - `video`: The video to analyse.
+    /// - `servers`: The computer vision servers.
+    /// - `context`: The context shared by the videos of the stage.
+    ///
+    /// ### Returns
+    /// True if the story of the video is available.
+    async fn run_computer_vision_model_on(
+        video: &mut Video,
+        servers: &ServerPool,
+        context: &ModelContext<'_>,
+    ) -> bool {
+        if context.stop.load(Ordering::Relaxed) {
+            return false;
+        }
+        if video.skip {
+            log::info!(
+                "{}/{} - Skipping {}",
+                video.numeric_id,
+                context.videos_number,
+                video.path
+            );
+            return false;
+        }
+        if video.stage >= Stage::Story {
+            log::info!(
+                "{}/{} - Story already available for {}",
+                video.numeric_id,
+                context.videos_number,
+                video.path
+            );
+            return true;
+        }
+        if !Self::run_caption_model_on(video, servers, context).await {
+            return false;
+        }
+        log::info!(
+            "{}/{} - Running computer vision model for {}",
+            video.numeric_id,
+            context.videos_number,
+            video.path
+        );
+        let label = format!("{}/{} - Error while running computer vision model", video.numeric_id, context.videos_number);
+        let the_video = &*video;
+        let result = failure::run_with_retries_async(&context.policy, &label, || async {
+            servers
+                .run(|ollama| async move {
+                    let language = &context.languages[0];
+                    aspargus_helper::run_computer_vision_model_for_video(&ollama, context.computer_vision_model, context.prompts, language, context.captions, &context.image_profile, context.contact_sheet != ContactSheetMode::Off, the_video).await
+                })
+                .await
+                .map_err(failure::classify_error)
+        })
+        .await;
+        match result {
+            Ok(story) => {
+                video.story = story;
+                video.stage = Stage::Story;
+                context.journal.record(video);
+                context.remove_frames(video);
+                true
+            }
+            Err(error) => {
+                log::error!(
+                    "{}/{} - Error while running computer vision model: {}",
+                    video.numeric_id,
+                    context.videos_number,
+                    error
+                );
+                context.remove_frames(video);
+                context.fail(video, Stage::Story, error);
+                false
+            }
+        }
+    }
+
+    /// Runs the computer vision model for a video to get its full result at once, unless it is skipped or its resume is already available.
+    ///
+    /// ### Parameters
+    /// - `video`: The video to analyse.
+    /// - `servers`: The computer vision servers.
+    /// - `context`: The context shared by the videos of the stage.
+    async fn run_only_computer_vision_model_on(
+        video: &mut Video,
+        servers: &ServerPool,
+        context: &ModelContext<'_>,
+    ) {
+        if context.stop.load(Ordering::Relaxed) {
+            return;
+        }
+        if video.skip {
+            log::info!(
+                "{}/{} - Skipping {}",
+                video.numeric_id,
+                context.videos_number,
+                video.path
+            );
+            return;
+        }
+        if video.stage >= Stage::Resume {
+            log::info!(
+                "{}/{} - Resume already available for {}",
+                video.numeric_id,
+                context.videos_number,
+                video.path
+            );
+            return;
+        }
+        if !Self::run_caption_model_on(video, servers, context).await {
+            return;
+        }
+        log::info!(
+            "{}/{} - Running computer vision model for {}",
+            video.numeric_id,
+            context.videos_number,
+            video.path
+        );
+        let label = format!("{}/{} - Error while running computer vision model", video.numeric_id, context.videos_number);
+        let the_video = &*video;
+        let result = failure::run_with_retries_async(&context.policy, &label, || async {
+            servers
+                .run(|ollama| async move {
+                    let model = context.computer_vision_model;
+                    aspargus_helper::run_only_computer_vision_model_for_video(&ollama, model, context.prompts, &context.languages[0], context.fields, context.captions, &context.image_profile, context.contact_sheet != ContactSheetMode::Off, the_video).await
+                })
+                .await
+                .map_err(failure::classify_error)
+        })
+        .await;
+        let result = match result {
+            Ok(resume) => Self::add_translations(resume, video, servers, context.computer_vision_model, context).await,
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok(mut resume) => {
+                resume.add_people(&video.people);
+                video.resume = resume;
+                video.stage = Stage::Resume;
+                context.journal.record(video);
+                context.store(video);
+                context.remove_frames(video);
+            }
+            Err(error) => {
+                log::error!(
+                    "{}/{} - Error while running computer vision model: {}",
+                    video.numeric_id,
+                    context.videos_number,
+                    error
+                );
+                context.remove_frames(video);
+                context.fail(video, Stage::Resume, error);
+            }
+        }
+    }
+
+    /// Translates the resume of a video into the other languages of the run. Each translation is its own request, tried again according to the failure policy, so that the server timeout applies to each of them rather than to the whole resume.
+    ///
+    /// ### Parameters
+    /// - `resume`: The resume of the video, in the first language.
+    /// - `video`: The video.
+    /// - `servers`: The servers of the model.
+    /// - `model`: The name of the model.
+    /// - `context`: The context shared by the videos of the stage.
+    ///
+    /// ### Returns
+    /// A Result containing the resume with its language and translations.
+    async fn add_translations(
+        mut resume: Resume,
+        video: &Video,
+        servers: &ServerPool,
+        model: &str,
+        context: &ModelContext<'_>,
+    ) -> Result<Resume, AspargusError> {
+        resume.language = context.languages[0].code.clone();
+        resume.translations.clear();
+        for language in context.languages.iter().skip(1) {
+            let label = format!(
+                "{}/{} - Error while translating the resume into {}",
+                video.numeric_id, context.videos_number, language.name
+            );
+            let source = &resume;
+            let translation = failure::run_with_retries_async(&context.policy, &label, || async {
+                servers
+                    .run(|ollama| async move {
+                        aspargus_helper::translate_resume(&ollama, model, source, language, &video.path).await
+                    })
+                    .await
+                    .map_err(failure::classify_error)
+            })
+            .await?;
+            resume.translations.insert(language.code.clone(), translation);
+        }
+        Ok(resume)
+    }
+
+    /// Runs the text model for a video based on its story, unless it is skipped or its resume is already available.
+    ///
+    /// ### Parameters
+    /// - `video`: The video to analyse.
+    /// - `servers`: The text servers.
+    /// - `context`: The context shared by the videos of the stage.
+    async fn run_resume_model_on(video: &mut Video, servers: &ServerPool, context: &ModelContext<'_>) {
+        if context.stop.load(Ordering::Relaxed) {
+            return;
+        }
+        if video.skip {
+            log::info!(
+                "{}/{} - Skipping {}",
+                video.numeric_id,
+                context.videos_number,
+                video.path
+            );
+            return;
+        }
+        if video.stage >= Stage::Resume {
+            log::info!(
+                "{}/{} - Resume already available for {}",
+                video.numeric_id,
+                context.videos_number,
+                video.path
+            );
+            return;
+        }
+        log::info!(
+            "{}/{} - Running resume model for {}",
+            video.numeric_id,
+            context.videos_number,
+            video.path
+        );
+        let label = format!("{}/{} - Error while running resume model", video.numeric_id, context.videos_number);
+        let the_video = &*video;
+        let result = failure::run_with_retries_async(&context.policy, &label, || async {
+            servers
+                .run(|ollama| async move {
+                    let model = context.text_model;
+                    aspargus_helper::run_resume_model_for_video(&ollama, model, context.prompts, &context.languages[0], context.fields, the_video).await
+                })
+                .await
+                .map_err(failure::classify_error)
+        })
+        .await;
+        let result = match result {
+            Ok(resume) => Self::add_translations(resume, video, servers, context.text_model, context).await,
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok(mut resume) => {
+                resume.add_people(&video.people);
                 log::info!(
-                    "{}/{} - Running resume model for {}",
+                    "{}/{} - Title: {}",
                     video.numeric_id,
-                    self.videos_number,
-                    video.path
+                    context.videos_number,
+                    resume.title
                 );
-                match aspargus_helper::run_resume_model_for_video(
-                    &self.text_ollama,
-                    &self.settings.text_model,
-                    video,
-                )
-                .await
-                {
-                    Ok(resume) => {
-                        log::info!(
-                            "{}/{} - Title: {}",
-                            video.numeric_id,
-                            self.videos_number,
-                            resume.title
-                        );
-                        log::info!(
-                            "{}/{} - Description: {}",
-                            video.numeric_id,
-                            self.videos_number,
-                            resume.description
-                        );
-                        log::info!(
-                            "{}/{} - Keywords: {}",
-                            video.numeric_id,
-                            self.videos_number,
-                            resume.keywords.join(", ")
-                        );
-                        video.resume = resume;
-                    }
-                    Err(error) => log::error!(
-                        "{}/{} - Error while running resume model: {}",
-                        video.numeric_id,
-                        self.videos_number,
-                        error
-                    ),
-                }
+                log::info!(
+                    "{}/{} - Description: {}",
+                    video.numeric_id,
+                    context.videos_number,
+                    resume.description
+                );
+                log::info!(
+                    "{}/{} - Keywords: {}",
+                    video.numeric_id,
+                    context.videos_number,
+                    resume.keywords.join(", ")
+                );
+                video.resume = resume;
+                video.stage = Stage::Resume;
+                context.journal.record(video);
+                context.store(video);
+            }
+            Err(error) => {
+                log::error!(
+                    "{}/{} - Error while running resume model: {}",
+                    video.numeric_id,
+                    context.videos_number,
+                    error
+                );
+                context.fail(video, Stage::Resume, error);
             }
         }
     }
@@ -453,14 +1831,19 @@ impl Aspargus {
     ///
     /// ### Parameters
     /// - `path`: The path of the file to write.  
+    /// - `language`: The code of the language of the exported resumes, None to export the resumes with all their translations.
     ///   
     /// ### Returns
     /// An empty Result in case of success.
     ///
     /// ### Errors
     /// Returns an error if the export fails.
-    pub fn export_to_json(&self, path: &str) -> Result<(), AspargusError> { 
-        let contents = match serde_json::to_string_pretty(&self.videos) {
+    pub fn export_to_json(&self, path: &str, language: Option<&str>) -> Result<(), AspargusError> { 
+        let videos = match language {
+            Some(language) => self.get_videos_in_language(language),
+            None => serde_json::to_value(&self.videos),
+        };
+        let contents = match videos.and_then(|videos| serde_json::to_string_pretty(&videos)) {
             Ok(json) => json,
             Err(_) => {
                 return Err(AspargusError::GenericError(
@@ -483,22 +1866,223 @@ impl Aspargus {
         Ok(())
     }
 
-    /// Renames the videos based on the results of the analysis.
+    /// Gets the videos with their resume in one language only, the resumes not available in that language being left empty.
     ///
     /// ### Parameters
-    /// - `template`: The template for the new file name.
-    pub fn rename_videos(&mut self, template: &str) {
-        self.videos.par_iter_mut().for_each(|video| {
-            let new_name = file_management::create_new_file_name(video, template);
-            let new_path =
-                &file_management::create_new_path(video.path.as_str(), new_name.as_str());
-            match file_management::rename_file(&video.path, new_path) {
-                Ok(_) => log::info!(
-                    "{}/{} - Renamed to: {}",
+    /// - `language`: The code of the language.
+    ///
+    /// ### Returns
+    /// A Result containing the JSON value of the videos.
+    ///
+    /// ### Errors
+    /// Returns an error if the videos can't be serialized.
+    fn get_videos_in_language(&self, language: &str) -> serde_json::Result<serde_json::Value> {
+        let mut videos = Vec::new();
+        for video in &self.videos {
+            let mut value = serde_json::to_value(video)?;
+            let translation = video.resume.get_translation(language);
+            if translation.is_none() && video.stage >= Stage::Resume {
+                log::warn!("No resume in {} for {}", language, video.path);
+            }
+            let mut resume = serde_json::to_value(translation.unwrap_or_default())?;
+            resume["language"] = serde_json::Value::from(language);
+            for (name, field) in &video.resume.fields {
+                resume[name] = field.clone();
+            }
+            value["resume"] = resume;
+            videos.push(value);
+        }
+        Ok(serde_json::Value::Array(videos))
+    }
+
+    /// Writes the results of the analysis into the metadata of the video files (title, description and keywords).
+    /// As it changes the content of the files, the analysis cache is updated so that they are still recognised on the next runs. The videos whose metadata already holds their resume are left untouched.
+    ///
+    /// ### Returns
+    /// An empty Result in case of success, even if some videos couldn't be updated.
+    ///
+    /// ### Errors
+    /// Returns an error if FFmpeg can't be found.
+    pub fn write_metadata(&mut self) -> Result<(), AspargusError> {
+        for video in &mut self.videos {
+            if video.skip || video.stage < Stage::Resume {
+                continue;
+            }
+            if container_metadata::has_metadata(video) {
+                log::debug!(
+                    "{}/{} - Metadata already written to: {}",
+                    video.numeric_id,
+                    self.videos_number,
+                    video.path
+                );
+                continue;
+            }
+            match container_metadata::write_metadata_to_video(video) {
+                Ok(_) => {
+                    log::info!(
+                        "{}/{} - Metadata written to: {}",
+                        video.numeric_id,
+                        self.videos_number,
+                        video.path
+                    );
+                    // The content changed with its metadata, so the cached analysis is stored again under its new content key
+                    match file_management::get_content_key(video.path.as_str()) {
+                        Ok(content_hash) => {
+                            video.content_hash = content_hash;
+                            if let Err(error) = self.cache.store(video) {
+                                log::error!("Error while storing the analysis in the cache: {}", error);
+                            }
+                        }
+                        Err(error) => log::error!(
+                            "{}/{} - Error while hashing the updated file: {}",
+                            video.numeric_id,
+                            self.videos_number,
+                            error
+                        ),
+                    }
+                }
+                Err(error) => {
+                    if let Some(VideoDataError::FFMpegNotFoundError(_)) =
+                        error.downcast_ref::<VideoDataError>()
+                    {
+                        return Err(AspargusError::GenericError("FFMpeg is not found, we're quitting for now. Please install FFMpeg and FFProbe and put them in the path.".to_string()));
+                    }
+                    log::error!(
+                        "{}/{} - Error while writing metadata: {}",
+                        video.numeric_id,
+                        self.videos_number,
+                        error
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Exports the results of the analysis in an XMP sidecar next to each video, merged with the existing sidecar if any.
+    pub fn export_to_xmp(&self) {
+        for video in &self.videos {
+            if video.skip || video.stage < Stage::Resume {
+                continue;
+            }
+            match xmp::export_xmp_sidecar(video) {
+                Ok(path) => log::info!(
+                    "{}/{} - Exported XMP sidecar to {}",
+                    video.numeric_id,
+                    self.videos_number,
+                    path
+                ),
+                Err(error) => log::error!(
+                    "{}/{} - Error while exporting the XMP sidecar: {}",
+                    video.numeric_id,
+                    self.videos_number,
+                    error
+                ),
+            }
+        }
+    }
+
+    /// Saves the contact sheet of each analysed video next to it as a preview, in the `preview` contact sheet mode.
+    pub fn export_contact_sheets(&self) {
+        if self.settings.contact_sheet != ContactSheetMode::Preview {
+            return;
+        }
+        for video in &self.videos {
+            if video.skip || video.stage < Stage::Resume {
+                continue;
+            }
+            if video.contact_sheet.is_empty() {
+                log::debug!(
+                    "{}/{} - No contact sheet for {}, its frames were not extracted in this run",
                     video.numeric_id,
                     self.videos_number,
-                    new_name
+                    video.path
+                );
+                continue;
+            }
+            match contact_sheet::save_preview(video) {
+                Ok(path) => log::info!(
+                    "{}/{} - Saved the contact sheet to {}",
+                    video.numeric_id,
+                    self.videos_number,
+                    path
+                ),
+                Err(error) => log::error!(
+                    "{}/{} - Error while saving the contact sheet: {}",
+                    video.numeric_id,
+                    self.videos_number,
+                    error
                 ),
+            }
+        }
+    }
+
+    /// Renames the videos based on the results of the analysis. When several videos get the same name, or when a name is already taken, a `_2`, `_3`... suffix is added.
+    /// The renames are recorded in an undo manifest in the work folder, so that they can be reverted with the `undo` command.
+    ///
+    /// ### Parameters
+    /// - `template`: The template for the new file name.
+    /// - `dry_run`: Flag to only log the new names, without renaming the files.
+    ///
+    /// ### Errors
+    /// Returns an error if the template can't be parsed.
+    pub fn rename_videos(&mut self, template: &str, dry_run: bool) -> Result<(), AspargusError> {
+        let template = template
+            .parse::<RenameTemplate>()
+            .map_err(AspargusError::ParseError)?;
+        let rules = self.get_file_name_rules();
+        let mut taken = HashSet::new();
+        let mut new_paths: Vec<Option<String>> = Vec::new();
+        for video in &self.videos {
+            if video.skip || video.stage >= Stage::Renamed {
+                new_paths.push(None);
+                continue;
+            }
+            let new_name = file_management::create_new_file_name(video, &template, &rules);
+            let new_path =
+                file_management::create_new_path(video.path.as_str(), new_name.as_str());
+            let new_path = file_management::make_unique_path(&video.path, &new_path, &taken, rules.max_length);
+            taken.insert(new_path.clone());
+            new_paths.push(Some(new_path));
+        }
+
+        if dry_run {
+            for (video, new_path) in self.videos.iter().zip(new_paths) {
+                if let Some(new_path) = new_path {
+                    log::info!(
+                        "{}/{} - {} -> {}",
+                        video.numeric_id,
+                        self.videos_number,
+                        video.path,
+                        new_path
+                    );
+                }
+            }
+            return Ok(());
+        }
+
+        let operations = Mutex::new(Vec::new());
+        self.videos.par_iter_mut().zip(new_paths).for_each(|(video, new_path)| {
+            let Some(new_path) = new_path else {
+                return;
+            };
+            match file_management::rename_file(&video.path, &new_path) {
+                Ok(_) => {
+                    log::info!(
+                        "{}/{} - Renamed to: {}",
+                        video.numeric_id,
+                        self.videos_number,
+                        new_path
+                    );
+                    self.journal.record_moved(&video.path, &new_path, Stage::Renamed);
+                    operations.lock().unwrap().push(UndoOperation {
+                        kind: OperationKind::Rename,
+                        original_path: video.path.clone(),
+                        new_path: new_path.clone(),
+                    });
+                    video.path = new_path;
+                    video.stage = Stage::Renamed;
+                }
                 Err(error) => log::error!(
                     "{}/{} - Error while renaming file: {}",
                     video.numeric_id,
@@ -507,6 +2091,317 @@ impl Aspargus {
                 ),
             }
         });
+
+        self.undo_manifest
+            .operations
+            .extend(operations.into_inner().unwrap());
+        self.save_undo_manifest();
+        Ok(())
+    }
+
+    /// Moves or copies the videos into a folder tree based on the results of the analysis, creating the folders as needed. The videos keep their file name, with a `_2`, `_3`... suffix if it is already taken.
+    /// The operations are recorded in an undo manifest in the work folder, so that they can be reverted with the `undo` command.
+    ///
+    /// ### Parameters
+    /// - `template`: The template for the destination folder, e.g. `Library/%Y/%Y-%M %T`.
+    /// - `copy`: Flag to copy the videos instead of moving them.
+    /// - `dry_run`: Flag to only log the new paths, without moving or copying the files.
+    ///
+    /// ### Errors
+    /// Returns an error if the template can't be parsed.
+    pub fn organise_videos(
+        &mut self,
+        template: &str,
+        copy: bool,
+        dry_run: bool,
+    ) -> Result<(), AspargusError> {
+        let template = template
+            .parse::<FolderTemplate>()
+            .map_err(AspargusError::ParseError)?;
+        let rules = self.get_file_name_rules();
+        let mut taken = HashSet::new();
+        let mut new_paths: Vec<Option<String>> = Vec::new();
+        for video in &self.videos {
+            if video.skip || video.stage >= Stage::Organised {
+                new_paths.push(None);
+                continue;
+            }
+            let mut new_path = template.render(video, &rules);
+            new_path.push(Path::new(&video.path).file_name().unwrap_or_default());
+            let new_path = new_path.to_str().unwrap_or_default().to_string();
+            let new_path = file_management::make_unique_path(&video.path, &new_path, &taken, rules.max_length);
+            taken.insert(new_path.clone());
+            new_paths.push(Some(new_path).filter(|new_path| *new_path != video.path));
+        }
+
+        if dry_run {
+            for (video, new_path) in self.videos.iter().zip(new_paths) {
+                if let Some(new_path) = new_path {
+                    log::info!(
+                        "{}/{} - {} -> {}",
+                        video.numeric_id,
+                        self.videos_number,
+                        video.path,
+                        new_path
+                    );
+                }
+            }
+            return Ok(());
+        }
+
+        // The videos are organised one by one, as they can be copied between file systems
+        for (video, new_path) in self.videos.iter_mut().zip(new_paths) {
+            let Some(new_path) = new_path else {
+                continue;
+            };
+            let folder = Path::new(&new_path).parent().unwrap_or(Path::new(""));
+            match file_management::create_folders(folder) {
+                Ok(created_folders) => self.undo_manifest.created_folders.extend(created_folders),
+                Err(error) => {
+                    log::error!(
+                        "{}/{} - Error while creating folder {}: {}",
+                        video.numeric_id,
+                        self.videos_number,
+                        folder.display(),
+                        error
+                    );
+                    continue;
+                }
+            }
+            let (kind, result) = if copy {
+                (OperationKind::Copy, file_management::copy_file(&video.path, &new_path))
+            } else {
+                (OperationKind::Move, file_management::move_file(&video.path, &new_path))
+            };
+            match result {
+                Ok(_) => {
+                    log::info!(
+                        "{}/{} - {} to: {}",
+                        video.numeric_id,
+                        self.videos_number,
+                        if copy { "Copied" } else { "Moved" },
+                        new_path
+                    );
+                    self.journal
+                        .record_moved(&video.path, &new_path, Stage::Organised);
+                    self.undo_manifest.operations.push(UndoOperation {
+                        kind,
+                        original_path: video.path.clone(),
+                        new_path: new_path.clone(),
+                    });
+                    video.path = new_path;
+                    video.stage = Stage::Organised;
+                }
+                Err(error) => log::error!(
+                    "{}/{} - Error while organising file: {}",
+                    video.numeric_id,
+                    self.videos_number,
+                    error
+                ),
+            }
+        }
+        self.save_undo_manifest();
+        Ok(())
+    }
+
+    /// Gets the rules applied to the new file and folder names.
+    ///
+    /// ### Returns
+    /// The rules from the settings.
+    fn get_file_name_rules(&self) -> FileNameRules {
+        FileNameRules {
+            profile: self.settings.sanitisation,
+            transliterate: self.settings.transliterate,
+            max_length: self.settings.max_name_length,
+        }
+    }
+
+    /// Saves the undo manifest of the run, if any file operation was done.
+    fn save_undo_manifest(&self) {
+        if self.undo_manifest.operations.is_empty() {
+            return;
+        }
+        match self.undo_manifest.save(self.settings.work_folder.as_str()) {
+            Ok(path) => log::info!(
+                "Undo manifest saved, use `aspargus undo {}` to restore the original files",
+                path
+            ),
+            Err(error) => log::error!("Error while saving the undo manifest: {}", error),
+        }
+    }
+
+    /// Logs a summary of the run: the number of analysed videos, and each skipped video with the stage it failed and the kind of its error.
+    pub fn log_summary(&self) {
+        let analysed = self
+            .videos
+            .iter()
+            .filter(|video| !video.skip && video.stage >= Stage::Resume)
+            .count();
+        // The videos whose metadata couldn't be extracted were never added to the queue
+        let not_added = self
+            .failures
+            .iter()
+            .filter(|failure| failure.stage == Stage::Metadata)
+            .count();
+        log::info!(
+            "Summary: {} video(s) analysed out of {}, {} skipped",
+            analysed,
+            self.videos.len() + not_added,
+            self.failures.len()
+        );
+        for failure in &self.failures {
+            log::warn!(
+                "Skipped {} at stage {:?}: {}",
+                failure.path,
+                failure.stage,
+                failure.error
+            );
+        }
+    }
+
+    /// Checks via FFprobe if a file is a video, for files without a known video extension.
+    ///
+    /// ### Parameters
+    /// - `path`: The path of the file.
+    ///
+    /// ### Returns
+    /// True if the file contains a video.
+    pub fn is_video_file(path: &str) -> bool {
+        aspargus_helper::is_video_content(path)
+    }
+
+    /// Checks that a rename template can be parsed, so that a mistake is reported before running the analysis.
+    ///
+    /// ### Parameters
+    /// - `template`: The template for the new file name.
+    ///
+    /// ### Errors
+    /// Returns an error describing the problem in the template.
+    pub fn check_rename_template(template: &str) -> Result<(), AspargusError> {
+        template
+            .parse::<RenameTemplate>()
+            .map(|_| ())
+            .map_err(AspargusError::ParseError)
+    }
+
+    /// Checks that a folder template can be parsed, so that a mistake is reported before running the analysis.
+    ///
+    /// ### Parameters
+    /// - `template`: The template for the destination folder.
+    ///
+    /// ### Errors
+    /// Returns an error describing the problem in the template.
+    pub fn check_organise_template(template: &str) -> Result<(), AspargusError> {
+        template
+            .parse::<FolderTemplate>()
+            .map(|_| ())
+            .map_err(AspargusError::ParseError)
+    }
+
+    /// Prints the prompts that would be sent to the models for a video, without running the models. The frames are extracted in a temp folder of their own to know their number, then removed, and the story, transcript and recognised people are taken from the cache when the video was already analysed.
+    ///
+    /// ### Parameters
+    /// - `path`: The path of the video.
+    ///
+    /// ### Errors
+    /// Returns an error if the metadata or the frames of the video can't be read.
+    pub fn print_prompts(&mut self, path: &str) -> Result<(), AspargusError> {
+        self.update_cache_settings();
+        let mut video = Video::new(path.to_string(), 1, Some(&self.cache)).map_err(failure::classify_error)?;
+        video.people = self.faces.get_names(&video.face_clusters);
+        self.make_run_temp_folder(format!("prompt-{}", std::process::id()).as_str())?;
+        let (thumbnails, timestamps, _) = aspargus_helper::extract_frames_for_video(
+            self.run_temp_folder.as_str(),
+            &video,
+            &self.settings.sampling,
+            self.settings.min_frames,
+            self.settings.max_frames,
+        )
+        .map_err(failure::classify_error)?;
+        video.thumbnails = thumbnails;
+        video.timestamps = timestamps;
+        let mut stages = if self.settings.two_steps {
+            vec![PromptStage::Story, PromptStage::Resume]
+        } else {
+            vec![PromptStage::SinglePass]
+        };
+        if self.settings.captions != CaptionMode::Off {
+            stages.insert(0, PromptStage::Caption);
+        }
+        for stage in stages {
+            println!("--- {} prompt ({} prompt set) ---", stage, self.prompts.name);
+            let mut prompt = self.prompts.render(stage, &video, &self.languages[0], &self.custom_fields, self.settings.captions);
+            if self.settings.contact_sheet != ContactSheetMode::Off && matches!(stage, PromptStage::Story | PromptStage::SinglePass) {
+                prompt += contact_sheet::get_prompt(video.get_frame_count()).as_str();
+            }
+            println!("{}", prompt);
+            println!();
+        }
+        self.remove_run_temp_folder();
+        Ok(())
     }
 
+    /// Reverts the file operations recorded in an undo manifest.
+    ///
+    /// ### Parameters
+    /// - `manifest`: The path of the undo manifest, or its name in the work folder.
+    ///
+    /// ### Errors
+    /// Returns an error if the manifest can't be loaded.
+    pub fn undo(&self, manifest: &str) -> Result<(), AspargusError> {
+        let manifest = UndoManifest::load(self.settings.work_folder.as_str(), manifest)
+            .map_err(|error| AspargusError::Io(error.to_string()))?;
+        let reverted = manifest.undo();
+        log::info!(
+            "Restored {} of {} files",
+            reverted,
+            manifest.operations.len()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an Aspargus instance working in an empty folder, with a failure policy.
+    fn make_aspargus(name: &str, failure_policy: FailurePolicy) -> Aspargus {
+        let folder = std::env::temp_dir().join(format!("aspargus-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(folder.join("tmp")).unwrap();
+        Aspargus::with_settings(AspargusSettings {
+            work_folder: folder.to_str().unwrap().to_string(),
+            temp_folder: folder.join("tmp").to_str().unwrap().to_string(),
+            failure_policy,
+            languages: vec![Language::default()],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn continue_run_skips_a_video_deleted_since_the_run_was_interrupted() {
+        let mut aspargus = make_aspargus("continue-skip", FailurePolicy::Skip);
+        let journal = Journal::create(aspargus.settings.work_folder.as_str()).unwrap();
+        journal.record_queued("/videos/deleted.mp4");
+
+        aspargus.continue_run(journal.run_id.as_str()).unwrap();
+        assert!(aspargus.videos.is_empty());
+        assert_eq!(aspargus.videos_number, 0);
+        assert_eq!(aspargus.failures.len(), 1);
+        assert_eq!(aspargus.failures[0].path, "/videos/deleted.mp4");
+        assert_eq!(aspargus.failures[0].stage, Stage::Metadata);
+        let _ = fs::remove_dir_all(aspargus.settings.work_folder.as_str());
+    }
+
+    #[test]
+    fn continue_run_stops_on_a_deleted_video_when_failing_fast() {
+        let mut aspargus = make_aspargus("continue-fail-fast", FailurePolicy::FailFast);
+        let journal = Journal::create(aspargus.settings.work_folder.as_str()).unwrap();
+        journal.record_queued("/videos/deleted.mp4");
+
+        assert!(aspargus.continue_run(journal.run_id.as_str()).is_err());
+        assert!(aspargus.failures.is_empty());
+        let _ = fs::remove_dir_all(aspargus.settings.work_folder.as_str());
+    }
 }
@@ -0,0 +1,372 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use super::captions::{self, CaptionMode};
+use super::custom_fields::{self, CustomField};
+use super::language::Language;
+use super::rename_template;
+use super::video::Video;
+use super::{file_management, AspargusError};
+
+/// The name of the prompt set used when none is chosen.
+pub const DEFAULT_PROMPT_SET: &str = "default";
+
+/// The variables that can be used in the prompt templates, as `{{variable}}`.
+const VARIABLES: &[&str] = &[
+    "story",
+    "transcript",
+    "duration",
+    "date",
+    "filename",
+    "frame_count",
+    "language",
+    "custom_fields",
+    "captions",
+    "people",
+];
+
+/// The value of the `story` variable when the story of the video is not known yet.
+const UNKNOWN_STORY: &str = "<the story told by the computer vision model>";
+
+const DEFAULT_CAPTION_PROMPT: &str = "This image is a frame of a video. Please describe what it shows in one sentence, focusing on the persons and their action and less on their environment, with no other text at all.";
+
+const DEFAULT_STORY_PROMPT: &str = "The following images are part of a video, they tell a story. Please describe that story focusing on the persons and their action and less on their environment.{{transcript}}{{captions}}";
+
+const DEFAULT_RESUME_PROMPT: &str = "You are a helpful assistant and expert in concise storytelling. The following text tells the story of a video. Please resume that story in 20 words focusing on the person and their action and less on their environment, from that resume please generate a title of maximum 8 words, and make a list of up to 5 keywords that resumes the story, the keywords will include the person on the video if any (e.g. woman, child...). Please format the answer in a json format: {\"title\": <<title>>, \"description\": <<description>>, \"keywords\": <<array of keywords>>}, with no other text at all, only the json result.{{custom_fields}} Please write the title, the description and the keywords in {{language}}. The story is:{{story}}{{transcript}}";
+
+const DEFAULT_SINGLE_PASS_PROMPT: &str = "The following images are part of a video, they tell a story. Please describe that story focusing on the persons and their action and less on their environment.{{transcript}}{{captions}} Please resume that story in 20 words focusing on the person and their action and less on their environment, from that resume please generate a title of maximum 8 words, and make a list of up to 5 keywords that resumes the story, the keywords will include the person on the video if any (e.g. woman, child...). Please format the answer in a valid json format: {\"title\": <<title>>, \"description\": <<description>>, \"keywords\": <<array of keywords>>}, with no other text at all, only the json result.{{custom_fields}} Please write the title, the description and the keywords in {{language}}.";
+
+/// Represents a stage of the analysis sending a prompt to a model.
+/// ### Variants
+/// - `Caption`: The computer vision model captions a frame of the video, when the frames are captioned.
+/// - `Story`: The computer vision model tells the story of the video, in two steps mode.
+/// - `Resume`: The text model resumes the story, in two steps mode.
+/// - `SinglePass`: The computer vision model resumes the video at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptStage {
+    Caption,
+    Story,
+    Resume,
+    SinglePass,
+}
+
+impl PromptStage {
+    /// Gets the name of the template file of the stage in a prompt set folder.
+    ///
+    /// ### Returns
+    /// The file name of the template.
+    fn file_name(&self) -> &'static str {
+        match self {
+            PromptStage::Caption => "caption.txt",
+            PromptStage::Story => "story.txt",
+            PromptStage::Resume => "resume.txt",
+            PromptStage::SinglePass => "single_pass.txt",
+        }
+    }
+
+    /// Gets the built-in template of the stage.
+    ///
+    /// ### Returns
+    /// The built-in template.
+    fn default_template(&self) -> &'static str {
+        match self {
+            PromptStage::Caption => DEFAULT_CAPTION_PROMPT,
+            PromptStage::Story => DEFAULT_STORY_PROMPT,
+            PromptStage::Resume => DEFAULT_RESUME_PROMPT,
+            PromptStage::SinglePass => DEFAULT_SINGLE_PASS_PROMPT,
+        }
+    }
+}
+
+/// The stages of the analysis, in the order they are run.
+const STAGES: [PromptStage; 4] = [
+    PromptStage::Caption,
+    PromptStage::Story,
+    PromptStage::Resume,
+    PromptStage::SinglePass,
+];
+
+impl fmt::Display for PromptStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PromptStage::Caption => write!(f, "caption"),
+            PromptStage::Story => write!(f, "story"),
+            PromptStage::Resume => write!(f, "resume"),
+            PromptStage::SinglePass => write!(f, "single pass"),
+        }
+    }
+}
+
+/// Represents a set of prompt templates, one per stage, loaded from `prompts/<name>/` in the work folder.
+/// ### Fields
+/// - `name`: The name of the set.
+/// - `caption`: The template of the caption stage.
+/// - `story`: The template of the story stage.
+/// - `resume`: The template of the resume stage.
+/// - `single_pass`: The template of the single pass stage.
+#[derive(Clone, Debug, Serialize)]
+pub struct PromptSet {
+    pub name: String,
+    caption: String,
+    story: String,
+    resume: String,
+    single_pass: String,
+}
+
+impl Default for PromptSet {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_PROMPT_SET.to_string(),
+            caption: DEFAULT_CAPTION_PROMPT.to_string(),
+            story: DEFAULT_STORY_PROMPT.to_string(),
+            resume: DEFAULT_RESUME_PROMPT.to_string(),
+            single_pass: DEFAULT_SINGLE_PASS_PROMPT.to_string(),
+        }
+    }
+}
+
+impl PromptSet {
+    /// Loads a prompt set from the work folder. The stages without template file use the built-in templates, and the default set is written in the work folder the first time, so that it can be edited.
+    ///
+    /// ### Parameters
+    /// - `work_folder`: The path of the work folder.
+    /// - `name`: The name of the prompt set.
+    ///
+    /// ### Returns
+    /// A Result containing the prompt set.
+    ///
+    /// ### Errors
+    /// Returns an error if the set doesn't exist, if a template can't be read or if it uses an unknown variable.
+    pub fn load(work_folder: &str, name: &str) -> Result<Self, AspargusError> {
+        let folder = get_prompt_set_folder(work_folder, name);
+        if !folder.is_dir() {
+            if name != DEFAULT_PROMPT_SET {
+                return Err(AspargusError::Io(format!(
+                    "The prompt set {} doesn't exist, its templates must be in {}",
+                    name,
+                    folder.display()
+                )));
+            }
+            write_default_templates(&folder);
+        }
+        let mut prompt_set = PromptSet {
+            name: name.to_string(),
+            ..PromptSet::default()
+        };
+        for stage in STAGES {
+            let path = folder.join(stage.file_name());
+            if !path.exists() {
+                log::debug!("No {} prompt in the prompt set {}, using the built-in one", stage, name);
+                continue;
+            }
+            let template = fs::read_to_string(&path)
+                .map_err(|error| AspargusError::Io(format!("Can't read {}: {}", path.display(), error)))?;
+            let template = template.trim().to_string();
+            check_template(&template).map_err(|error| {
+                AspargusError::ParseError(format!("{} in {}", error, path.display()))
+            })?;
+            match stage {
+                PromptStage::Caption => prompt_set.caption = template,
+                PromptStage::Story => prompt_set.story = template,
+                PromptStage::Resume => prompt_set.resume = template,
+                PromptStage::SinglePass => prompt_set.single_pass = template,
+            }
+        }
+        Ok(prompt_set)
+    }
+
+    /// Renders the prompt of a stage for a video. When the template of a stage generating the resume doesn't mention the custom fields or the language, they are asked for at the end of the prompt, unless there are no custom fields and the language is English.
+    /// In the story caption mode, the captions of the frames are also added at the end of the story and single pass prompts that don't mention them, and the names of the recognised people are added at the end of the prompts that don't mention them.
+    ///
+    /// ### Parameters
+    /// - `stage`: The stage of the analysis.
+    /// - `video`: The video.
+    /// - `language`: The language of the resume.
+    /// - `fields`: The custom fields of the resume.
+    /// - `captions`: The caption mode.
+    ///
+    /// ### Returns
+    /// The prompt, its variables being replaced with the values of the video.
+    pub fn render(
+        &self,
+        stage: PromptStage,
+        video: &Video,
+        language: &Language,
+        fields: &[CustomField],
+        captions: CaptionMode,
+    ) -> String {
+        let template = match stage {
+            PromptStage::Caption => &self.caption,
+            PromptStage::Story => &self.story,
+            PromptStage::Resume => &self.resume,
+            PromptStage::SinglePass => &self.single_pass,
+        };
+        let mut uses_language = false;
+        let mut uses_fields = false;
+        let mut uses_captions = false;
+        let mut uses_people = false;
+        let mut prompt = render_template(template, |variable| {
+            uses_language |= variable == "language";
+            uses_fields |= variable == "custom_fields";
+            uses_captions |= variable == "captions";
+            uses_people |= variable == "people";
+            get_variable(variable, video, language, fields, captions)
+        });
+        if stage == PromptStage::Caption {
+            return prompt;
+        }
+        if !uses_people {
+            prompt += get_variable("people", video, language, fields, captions).as_str();
+        }
+        if !uses_captions && stage != PromptStage::Resume {
+            prompt += get_variable("captions", video, language, fields, captions).as_str();
+        }
+        if stage == PromptStage::Story {
+            return prompt;
+        }
+        if !uses_fields {
+            prompt += custom_fields::get_prompt(fields).as_str();
+        }
+        if !uses_language && *language != Language::default() {
+            prompt += format!(
+                " Please write the title, the description and the keywords in {}.",
+                language.name
+            )
+            .as_str();
+        }
+        prompt
+    }
+}
+
+/// Gets the folder of a prompt set.
+///
+/// ### Parameters
+/// - `work_folder`: The path of the work folder.
+/// - `name`: The name of the prompt set.
+///
+/// ### Returns
+/// The path of the folder of the prompt set.
+fn get_prompt_set_folder(work_folder: &str, name: &str) -> PathBuf {
+    let mut path = PathBuf::from(work_folder);
+    path.push("prompts");
+    path.push(name);
+    path
+}
+
+/// Writes the built-in templates in the folder of the default prompt set. Errors are only logged, as the built-in templates are used anyway.
+///
+/// ### Parameters
+/// - `folder`: The folder of the default prompt set.
+fn write_default_templates(folder: &Path) {
+    if let Err(error) = fs::create_dir_all(folder) {
+        log::warn!("Can't create the default prompt set in {}: {}", folder.display(), error);
+        return;
+    }
+    for stage in STAGES {
+        let path = folder.join(stage.file_name());
+        if let Err(error) = fs::write(&path, stage.default_template()) {
+            log::warn!("Can't write the default {} prompt in {}: {}", stage, path.display(), error);
+        }
+    }
+    log::info!("Default prompts written in {}", folder.display());
+}
+
+/// Checks that a template only uses known variables.
+///
+/// ### Parameters
+/// - `template`: The template.
+///
+/// ### Errors
+/// Returns an error with the first unknown variable.
+fn check_template(template: &str) -> Result<(), String> {
+    let mut unknown = None;
+    render_template(template, |variable| {
+        if unknown.is_none() && !VARIABLES.contains(&variable) {
+            unknown = Some(variable.to_string());
+        }
+        String::default()
+    });
+    match unknown {
+        Some(variable) => Err(format!(
+            "Unknown prompt variable {{{{{}}}}}, expected one of {}",
+            variable,
+            VARIABLES.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Replaces the `{{variable}}` of a template, the spaces around the name of a variable being ignored.
+///
+/// ### Parameters
+/// - `template`: The template.
+/// - `value`: Gives the value of a variable from its name.
+///
+/// ### Returns
+/// The rendered template.
+fn render_template(template: &str, mut value: impl FnMut(&str) -> String) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&value(rest[start + 2..start + 2 + length].trim()));
+        rest = &rest[start + 2 + length + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Gets the value of a variable for a video.
+///
+/// ### Parameters
+/// - `variable`: The name of the variable.
+/// - `video`: The video.
+/// - `language`: The language of the resume.
+/// - `fields`: The custom fields of the resume.
+/// - `captions`: The caption mode, the captions of the frames being only given in the story mode.
+///
+/// ### Returns
+/// The value of the variable, empty if unknown.
+fn get_variable(
+    variable: &str,
+    video: &Video,
+    language: &Language,
+    fields: &[CustomField],
+    captions: CaptionMode,
+) -> String {
+    match variable {
+        "story" if video.story.is_empty() => UNKNOWN_STORY.to_string(),
+        "story" => video.story.clone(),
+        "transcript" if video.transcript.is_empty() => String::default(),
+        "transcript" => format!(
+            " Here is the transcript of what is said in the video, use it to better understand the story: \"{}\".",
+            video.transcript
+        ),
+        "duration" => rename_template::format_duration(video.duration),
+        // An unknown creation date is stored as the epoch
+        "date" if video.creation_date.timestamp() == 0 => String::default(),
+        "date" => video.creation_date.format("%Y-%m-%d %H:%M").to_string(),
+        "filename" => file_management::get_file_name(&video.path),
+        "frame_count" => video.get_frame_count().to_string(),
+        "language" => language.name.clone(),
+        "custom_fields" => custom_fields::get_prompt(fields),
+        "captions" if captions != CaptionMode::Story || video.timeline.is_empty() => String::default(),
+        "captions" => format!(
+            " Here is what each image shows, in the order of the video:\n{}",
+            captions::format_timeline(&video.timeline)
+        ),
+        "people" if video.people.is_empty() => String::default(),
+        "people" => format!(
+            " The following people appear in the video, please call them by their name: {}.",
+            video.people.join(", ")
+        ),
+        _ => String::default(),
+    }
+}
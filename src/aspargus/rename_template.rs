@@ -0,0 +1,771 @@
+use std::{
+    path::{self, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Utc};
+
+use super::custom_fields;
+use super::file_management;
+use super::language::Language;
+use super::sanitiser::{self, FileNameRules};
+use super::video::Video;
+
+/// Represents a parsed rename template.
+///
+/// A template is made of text and placeholders written as `%[FLAGS][NUMBER]LETTER`, e.g. `%T`, `%3N` or `%^2K`:
+/// - The letter selects the value (see `Field`).
+/// - The number pads the sequence number with zeros, or limits the number of keywords or people.
+/// - The flags change the case of the value: `^` for uppercase, `,` for lowercase and `~` for a slug. A language code between braces picks the title or keywords in that language, e.g. `%{fr}T`, and the name of a custom field between braces is required by `%V`, e.g. `%{season}V`.
+///
+/// The parts between square brackets are conditional, and disappear when one of their values is empty, e.g. `[_%C]`.
+/// `%%`, `%[` and `%]` stand for the literal characters.
+/// ### Fields
+/// - `segments`: The parsed segments of the template.
+pub struct RenameTemplate {
+    segments: Vec<Segment>,
+}
+
+/// Represents a parsed folder template, such as `Library/%Y/%Y-%M %T`.
+/// The leading folders without placeholders form the base folder, used as is, and each of the following folders is a rename template.
+/// ### Fields
+/// - `base`: The base folder.
+/// - `folders`: The templates of the folders inside the base folder.
+pub struct FolderTemplate {
+    base: PathBuf,
+    folders: Vec<RenameTemplate>,
+}
+
+/// Represents a segment of a rename template.
+/// ### Variants
+/// - `Text`: Literal text.
+/// - `Placeholder`: A value of the video.
+/// - `Conditional`: Segments that disappear when one of their values is empty.
+enum Segment {
+    Text(String),
+    Placeholder(Placeholder),
+    Conditional(Vec<Segment>),
+}
+
+/// Represents a placeholder of a rename template.
+/// ### Fields
+/// - `field`: The value of the video.
+/// - `number`: The padding of the sequence number, or the number of keywords or people.
+/// - `case`: The case applied to the value.
+/// - `parameter`: The code of the language of the title or keywords, None for the main language, or the name of the custom field.
+struct Placeholder {
+    field: Field,
+    number: Option<usize>,
+    case: Case,
+    parameter: Option<String>,
+}
+
+/// Represents the values of a video that can be used in a rename template.
+/// ### Variants
+/// - `Year` (`%Y`), `Month` (`%M`), `Day` (`%D`): The creation date of the video.
+/// - `Hour` (`%h`), `Minute` (`%m`), `Second` (`%s`): The creation time of the video.
+/// - `Title` (`%T`): The generated title.
+/// - `Keywords` (`%K`), `KeywordsList` (`%J`): The generated keywords, separated by dashes or by commas.
+/// - `FileName` (`%F`): The original file name.
+/// - `Duration` (`%L`): The length of the video, e.g. `1m05s`.
+/// - `Resolution` (`%R`): The resolution of the video, e.g. `1920x1080`.
+/// - `CameraMake` (`%B`), `CameraModel` (`%C`): The brand and the model of the camera.
+/// - `Number` (`%N`): The number of the video in the queue.
+/// - `People` (`%P`): The names of the people recognised in the video, separated by dashes.
+/// - `Custom` (`%{NAME}V`): The value of a custom field.
+#[derive(Clone, Copy)]
+enum Field {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Title,
+    Keywords,
+    KeywordsList,
+    FileName,
+    Duration,
+    Resolution,
+    CameraMake,
+    CameraModel,
+    Number,
+    People,
+    Custom,
+}
+
+/// Represents the case applied to a value of a rename template.
+#[derive(Clone, Copy)]
+enum Case {
+    Unchanged,
+    Upper,
+    Lower,
+    Slug,
+}
+
+impl Field {
+    /// Gets the field matching a placeholder letter.
+    ///
+    /// ### Parameters
+    /// - `letter`: The letter of the placeholder.
+    ///
+    /// ### Returns
+    /// The field, None if the letter is unknown.
+    fn from_letter(letter: char) -> Option<Self> {
+        match letter {
+            'Y' => Some(Field::Year),
+            'M' => Some(Field::Month),
+            'D' => Some(Field::Day),
+            'h' => Some(Field::Hour),
+            'm' => Some(Field::Minute),
+            's' => Some(Field::Second),
+            'T' => Some(Field::Title),
+            'K' => Some(Field::Keywords),
+            'J' => Some(Field::KeywordsList),
+            'F' => Some(Field::FileName),
+            'L' => Some(Field::Duration),
+            'R' => Some(Field::Resolution),
+            'B' => Some(Field::CameraMake),
+            'C' => Some(Field::CameraModel),
+            'N' => Some(Field::Number),
+            'P' => Some(Field::People),
+            'V' => Some(Field::Custom),
+            _ => None,
+        }
+    }
+
+    /// Checks if the field accepts a number.
+    ///
+    /// ### Returns
+    /// True if the field accepts a number.
+    fn accepts_number(&self) -> bool {
+        matches!(self, Field::Keywords | Field::KeywordsList | Field::Number | Field::People)
+    }
+
+    /// Checks if the field accepts a language.
+    ///
+    /// ### Returns
+    /// True if the field accepts a language.
+    fn accepts_language(&self) -> bool {
+        matches!(self, Field::Title | Field::Keywords | Field::KeywordsList)
+    }
+}
+
+impl FromStr for RenameTemplate {
+    type Err = String;
+
+    /// Parses a rename template.
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let characters: Vec<char> = template.chars().collect();
+        let mut position = 0;
+        let segments = parse_segments(&characters, &mut position, None)?;
+        Ok(Self { segments })
+    }
+}
+
+impl RenameTemplate {
+    /// Renders the template for a video.
+    ///
+    /// ### Parameters
+    /// - `video`: The video to rename.
+    ///
+    /// ### Returns
+    /// The rendered file name, not sanitised.
+    pub fn render(&self, video: &Video) -> String {
+        render_segments(&self.segments, video).0
+    }
+}
+
+impl FromStr for FolderTemplate {
+    type Err = String;
+
+    /// Parses a folder template.
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = template.split(path::is_separator).collect();
+        let first_template = parts
+            .iter()
+            .position(|part| part.contains(['%', '[', ']']))
+            .unwrap_or(parts.len());
+        let base = parts[..first_template].join(path::MAIN_SEPARATOR_STR);
+        let folders = parts[first_template..]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                part.parse::<RenameTemplate>()
+                    .map_err(|error| format!("{} (folder {})", error, part))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            base: PathBuf::from(base),
+            folders,
+        })
+    }
+}
+
+impl FolderTemplate {
+    /// Renders the template for a video. Each folder name is sanitised, and the folders left empty are skipped.
+    ///
+    /// ### Parameters
+    /// - `video`: The video to organise.
+    /// - `rules`: The rules applied to sanitise and limit the length of the folder names.
+    ///
+    /// ### Returns
+    /// The rendered folder.
+    pub fn render(&self, video: &Video, rules: &FileNameRules) -> PathBuf {
+        let mut folder = self.base.clone();
+        for template in &self.folders {
+            let name = sanitiser::sanitise_file_name(&template.render(video), rules);
+            let name = sanitiser::truncate_file_name(&name, rules.max_length);
+            if !name.is_empty() {
+                folder.push(name);
+            }
+        }
+        folder
+    }
+}
+
+/// Parses the segments of a template until its end, or until the end of the current conditional segment.
+///
+/// ### Parameters
+/// - `characters`: The characters of the template.
+/// - `position`: The position of the next character to parse, moved forward while parsing.
+/// - `opening`: The position of the opening bracket of the current conditional segment, None at the root of the template.
+///
+/// ### Returns
+/// A Result containing the parsed segments.
+///
+/// ### Errors
+/// Returns an error describing the problem and its position in the template.
+fn parse_segments(
+    characters: &[char],
+    position: &mut usize,
+    opening: Option<usize>,
+) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    while *position < characters.len() {
+        let current = *position;
+        *position += 1;
+        match characters[current] {
+            '[' => segments.push(Segment::Conditional(parse_segments(
+                characters,
+                position,
+                Some(current),
+            )?)),
+            ']' => {
+                if opening.is_some() {
+                    return Ok(segments);
+                }
+                return Err(format!(
+                    "Unexpected ] at position {} of the template, use %] for a literal bracket",
+                    current + 1
+                ));
+            }
+            '%' => match parse_placeholder(characters, position, current)? {
+                Some(placeholder) => segments.push(Segment::Placeholder(placeholder)),
+                None => push_text(&mut segments, characters[*position - 1]),
+            },
+            character => push_text(&mut segments, character),
+        }
+    }
+    match opening {
+        Some(opening) => Err(format!(
+            "Unclosed [ at position {} of the template, use %[ for a literal bracket",
+            opening + 1
+        )),
+        None => Ok(segments),
+    }
+}
+
+/// Parses a placeholder, the `%` being already consumed.
+///
+/// ### Parameters
+/// - `characters`: The characters of the template.
+/// - `position`: The position of the character following the `%`, moved after the placeholder.
+/// - `start`: The position of the `%`.
+///
+/// ### Returns
+/// A Result containing the placeholder, or None if it is an escaped character (the last consumed character).
+///
+/// ### Errors
+/// Returns an error if the placeholder is unknown or incomplete.
+fn parse_placeholder(
+    characters: &[char],
+    position: &mut usize,
+    start: usize,
+) -> Result<Option<Placeholder>, String> {
+    let mut case = Case::Unchanged;
+    let mut number = String::new();
+    let mut parameter = None;
+    while let Some(&character) = characters.get(*position) {
+        *position += 1;
+        match character {
+            '%' | '[' | ']' if *position == start + 2 => return Ok(None),
+            '^' if number.is_empty() => case = Case::Upper,
+            ',' if number.is_empty() => case = Case::Lower,
+            '~' if number.is_empty() => case = Case::Slug,
+            '{' if number.is_empty() && parameter.is_none() => {
+                let Some(length) = characters[*position..].iter().position(|&c| c == '}') else {
+                    return Err(format!(
+                        "Unclosed {{ at position {} of the template",
+                        start + 1
+                    ));
+                };
+                parameter = Some(characters[*position..*position + length].iter().collect::<String>());
+                *position += length + 1;
+            }
+            '0'..='9' => number.push(character),
+            letter => {
+                let placeholder: String = characters[start..*position].iter().collect();
+                let Some(field) = Field::from_letter(letter) else {
+                    return Err(format!(
+                        "Unknown placeholder {} at position {} of the template",
+                        placeholder,
+                        start + 1
+                    ));
+                };
+                if !number.is_empty() && !field.accepts_number() {
+                    return Err(format!(
+                        "The placeholder {} at position {} of the template doesn't accept a number",
+                        placeholder,
+                        start + 1
+                    ));
+                }
+                let parameter = match (field, parameter) {
+                    (Field::Custom, Some(name)) => Some(name.trim().to_string()),
+                    (Field::Custom, None) => {
+                        return Err(format!(
+                            "The placeholder {} at position {} of the template needs the name of a custom field, e.g. %{{season}}V",
+                            placeholder,
+                            start + 1
+                        ));
+                    }
+                    (field, Some(code)) if field.accepts_language() => {
+                        let language = code.parse::<Language>().map_err(|error| {
+                            format!("{} at position {} of the template", error, start + 1)
+                        })?;
+                        Some(language.code)
+                    }
+                    (_, Some(_)) => {
+                        return Err(format!(
+                            "The placeholder {} at position {} of the template doesn't accept a language",
+                            placeholder,
+                            start + 1
+                        ));
+                    }
+                    (_, None) => None,
+                };
+                return Ok(Some(Placeholder {
+                    field,
+                    number: number.parse().ok(),
+                    case,
+                    parameter,
+                }));
+            }
+        }
+    }
+    Err(format!(
+        "Incomplete placeholder at position {} of the template, use %% for a literal %",
+        start + 1
+    ))
+}
+
+/// Appends a character to the text segment at the end of the segments, or to a new one.
+///
+/// ### Parameters
+/// - `segments`: The parsed segments.
+/// - `character`: The character to append.
+fn push_text(segments: &mut Vec<Segment>, character: char) {
+    if let Some(Segment::Text(text)) = segments.last_mut() {
+        text.push(character);
+    } else {
+        segments.push(Segment::Text(character.to_string()));
+    }
+}
+
+/// Renders segments for a video.
+///
+/// ### Parameters
+/// - `segments`: The segments to render.
+/// - `video`: The video to rename.
+///
+/// ### Returns
+/// The rendered segments, and a flag set to false if one of their values was empty.
+fn render_segments(segments: &[Segment], video: &Video) -> (String, bool) {
+    let mut rendered = String::new();
+    let mut complete = true;
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => rendered.push_str(text),
+            Segment::Placeholder(placeholder) => {
+                let value = render_placeholder(placeholder, video);
+                complete &= !value.is_empty();
+                rendered.push_str(&value);
+            }
+            Segment::Conditional(segments) => {
+                let (conditional, conditional_complete) = render_segments(segments, video);
+                if conditional_complete {
+                    rendered.push_str(&conditional);
+                }
+            }
+        }
+    }
+    (rendered, complete)
+}
+
+/// Renders a placeholder for a video.
+///
+/// ### Parameters
+/// - `placeholder`: The placeholder to render.
+/// - `video`: The video to rename.
+///
+/// ### Returns
+/// The value of the placeholder, empty if unknown.
+fn render_placeholder(placeholder: &Placeholder, video: &Video) -> String {
+    // An unknown creation date is stored as the epoch
+    let date = Some(video.creation_date).filter(|date| date.timestamp() != 0);
+    let format_date = |date: Option<DateTime<Utc>>, format: &str| {
+        date.map(|date| date.format(format).to_string())
+            .unwrap_or_default()
+    };
+    let translation = match (placeholder.field, &placeholder.parameter) {
+        (Field::Title | Field::Keywords | Field::KeywordsList, Some(language)) => {
+            video.resume.get_translation(language).unwrap_or_default()
+        }
+        _ => video.resume.get_translation(video.resume.get_language()).unwrap_or_default(),
+    };
+    let keywords = || {
+        let count = placeholder.number.unwrap_or(usize::MAX);
+        translation.keywords.iter().take(count).cloned().collect::<Vec<_>>()
+    };
+    let value = match placeholder.field {
+        Field::Year => format_date(date, "%Y"),
+        Field::Month => format_date(date, "%m"),
+        Field::Day => format_date(date, "%d"),
+        Field::Hour => format_date(date, "%H"),
+        Field::Minute => format_date(date, "%M"),
+        Field::Second => format_date(date, "%S"),
+        Field::Title => translation.title.clone(),
+        Field::Keywords => keywords().join("-"),
+        Field::KeywordsList => keywords().join(", "),
+        Field::FileName => file_management::get_file_name(&video.path),
+        Field::Duration => format_duration(video.duration),
+        Field::Resolution if video.width > 0 && video.height > 0 => {
+            format!("{}x{}", video.width, video.height)
+        }
+        Field::Resolution => String::default(),
+        Field::CameraMake => video.camera_make.clone(),
+        Field::CameraModel => video.camera_model.clone(),
+        Field::Number => format!(
+            "{:0width$}",
+            video.numeric_id,
+            width = placeholder.number.unwrap_or_default()
+        ),
+        Field::People => video
+            .people
+            .iter()
+            .take(placeholder.number.unwrap_or(usize::MAX))
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("-"),
+        Field::Custom => {
+            let name = placeholder.parameter.as_deref().unwrap_or_default();
+            video
+                .resume
+                .fields
+                .get(name)
+                .map(|value| custom_fields::format_value(name, value))
+                .unwrap_or_default()
+        }
+    };
+    match placeholder.case {
+        Case::Unchanged => value,
+        Case::Upper => value.to_uppercase(),
+        Case::Lower => value.to_lowercase(),
+        Case::Slug => sanitiser::slugify(&value),
+    }
+}
+
+/// Formats a duration for a file name, e.g. `45s`, `1m05s` or `1h02m05s`.
+///
+/// ### Parameters
+/// - `duration`: The duration in seconds, 0 if unknown.
+///
+/// ### Returns
+/// The formatted duration, empty if unknown.
+pub fn format_duration(duration: f32) -> String {
+    let seconds = duration.round() as u64;
+    match seconds {
+        0 => String::default(),
+        1..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!(
+            "{}h{:02}m{:02}s",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::aspargus::sanitiser::SanitisationProfile;
+    use crate::aspargus::video::Translation;
+
+    /// Creates a video with all the values known.
+    fn make_video() -> Video {
+        let mut video = Video {
+            path: "/videos/IMG_0042.MOV".to_string(),
+            creation_date: Utc.with_ymd_and_hms(2024, 4, 16, 18, 30, 5).unwrap(),
+            duration: 65.0,
+            width: 1920,
+            height: 1080,
+            camera_make: "Apple".to_string(),
+            camera_model: "iPhone 15".to_string(),
+            numeric_id: 7,
+            people: vec!["Alice".to_string(), "Bob".to_string(), "Carol".to_string()],
+            ..Default::default()
+        };
+        video.resume.title = "A Day at the Café".to_string();
+        video.resume.keywords = vec!["beach".to_string(), "sun".to_string(), "sand".to_string()];
+        video.resume.language = "en".to_string();
+        video.resume.translations.insert(
+            "fr".to_string(),
+            Translation {
+                title: "Une journée à la plage".to_string(),
+                description: String::default(),
+                keywords: vec!["plage".to_string(), "soleil".to_string()],
+            },
+        );
+        video.resume.fields.insert("season".to_string(), Value::from("summer"));
+        video.resume.fields.insert("indoor".to_string(), Value::from(true));
+        video.resume.fields.insert("night".to_string(), Value::from(false));
+        video.resume.fields.insert("people_count".to_string(), Value::from(3));
+        video
+    }
+
+    /// Parses a template and renders it for a video.
+    fn render(template: &str, video: &Video) -> String {
+        template.parse::<RenameTemplate>().unwrap().render(video)
+    }
+
+    /// Parses an invalid template.
+    fn parse_error(template: &str) -> String {
+        match template.parse::<RenameTemplate>() {
+            Ok(_) => panic!("{} should not parse", template),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn renders_the_date_and_time_placeholders() {
+        let video = make_video();
+        assert_eq!(render("%Y-%M-%D %h.%m.%s", &video), "2024-04-16 18.30.05");
+    }
+
+    #[test]
+    fn renders_the_resume_placeholders() {
+        let video = make_video();
+        assert_eq!(render("%T", &video), "A Day at the Café");
+        assert_eq!(render("%K", &video), "beach-sun-sand");
+        assert_eq!(render("%J", &video), "beach, sun, sand");
+        assert_eq!(render("%2K", &video), "beach-sun");
+        assert_eq!(render("%1J", &video), "beach");
+    }
+
+    #[test]
+    fn renders_the_file_placeholders() {
+        let video = make_video();
+        assert_eq!(render("%F", &video), "IMG_0042");
+        assert_eq!(render("%L", &video), "1m05s");
+        assert_eq!(render("%R", &video), "1920x1080");
+        assert_eq!(render("%B %C", &video), "Apple iPhone 15");
+    }
+
+    #[test]
+    fn renders_the_number_with_padding() {
+        let video = make_video();
+        assert_eq!(render("%N", &video), "7");
+        assert_eq!(render("%3N", &video), "007");
+    }
+
+    #[test]
+    fn renders_the_people() {
+        let video = make_video();
+        assert_eq!(render("%P", &video), "Alice-Bob-Carol");
+        assert_eq!(render("%2P", &video), "Alice-Bob");
+    }
+
+    #[test]
+    fn renders_the_custom_fields() {
+        let video = make_video();
+        assert_eq!(render("%{season}V", &video), "summer");
+        assert_eq!(render("%{ season }V", &video), "summer");
+        assert_eq!(render("%{people_count}V", &video), "3");
+        assert_eq!(render("%{indoor}V", &video), "indoor");
+        assert_eq!(render("%{night}V", &video), "");
+        assert_eq!(render("%{unknown}V", &video), "");
+    }
+
+    #[test]
+    fn renders_the_translations() {
+        let video = make_video();
+        assert_eq!(render("%{fr}T", &video), "Une journée à la plage");
+        assert_eq!(render("%{French}K", &video), "plage-soleil");
+        assert_eq!(render("%{en}T", &video), "A Day at the Café");
+        assert_eq!(render("%{de}T", &video), "");
+    }
+
+    #[test]
+    fn applies_the_case_flags() {
+        let video = make_video();
+        assert_eq!(render("%^T", &video), "A DAY AT THE CAFÉ");
+        assert_eq!(render("%,T", &video), "a day at the café");
+        assert_eq!(render("%~T", &video), "a-day-at-the-cafe");
+        assert_eq!(render("%^2K", &video), "BEACH-SUN");
+        assert_eq!(render("%~{fr}T", &video), "une-journee-a-la-plage");
+    }
+
+    #[test]
+    fn renders_nothing_for_unknown_values() {
+        let video = Video::default();
+        assert_eq!(render("%Y%M%D%h%m%s%T%K%L%R%B%C%P", &video), "");
+    }
+
+    #[test]
+    fn drops_the_conditional_parts_with_an_unknown_value() {
+        let mut video = make_video();
+        assert_eq!(render("%Y[_%C]_%T", &video), "2024_iPhone 15_A Day at the Café");
+        video.camera_model = String::default();
+        assert_eq!(render("%Y[_%C]_%T", &video), "2024_A Day at the Café");
+        assert_eq!(render("[%C]", &video), "");
+        assert_eq!(render("[text]", &video), "text");
+    }
+
+    #[test]
+    fn renders_the_nested_conditional_parts_independently() {
+        let mut video = make_video();
+        assert_eq!(render("%Y[-%B[-%C]]", &video), "2024-Apple-iPhone 15");
+        video.camera_model = String::default();
+        assert_eq!(render("%Y[-%B[-%C]]", &video), "2024-Apple");
+        video.camera_model = "iPhone 15".to_string();
+        video.camera_make = String::default();
+        // The outer part is dropped for its own value, whatever the inner part gives
+        assert_eq!(render("%Y[-%B[-%C]]", &video), "2024");
+        assert_eq!(render("%Y[-%R[-%B]]", &video), "2024-1920x1080");
+    }
+
+    #[test]
+    fn renders_the_escaped_characters() {
+        let video = make_video();
+        assert_eq!(render("100%% %[%N%]", &video), "100% [7]");
+        assert_eq!(render("[%[%T%]]", &video), "[A Day at the Café]");
+        assert_eq!(render("[%%%C]", &video), "%iPhone 15");
+    }
+
+    #[test]
+    fn rejects_unbalanced_brackets() {
+        assert_eq!(
+            parse_error("%T]"),
+            "Unexpected ] at position 3 of the template, use %] for a literal bracket"
+        );
+        assert_eq!(
+            parse_error("%Y[_%C"),
+            "Unclosed [ at position 3 of the template, use %[ for a literal bracket"
+        );
+        assert_eq!(
+            parse_error("[[%C]"),
+            "Unclosed [ at position 1 of the template, use %[ for a literal bracket"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_placeholders() {
+        assert_eq!(
+            parse_error("%Y-%Q"),
+            "Unknown placeholder %Q at position 4 of the template"
+        );
+        assert_eq!(
+            parse_error("%T%"),
+            "Incomplete placeholder at position 3 of the template, use %% for a literal %"
+        );
+        assert_eq!(
+            parse_error("%3T"),
+            "The placeholder %3T at position 1 of the template doesn't accept a number"
+        );
+        assert_eq!(
+            parse_error("%V"),
+            "The placeholder %V at position 1 of the template needs the name of a custom field, e.g. %{season}V"
+        );
+        assert_eq!(
+            parse_error("%{fr}Y"),
+            "The placeholder %{fr}Y at position 1 of the template doesn't accept a language"
+        );
+        assert_eq!(parse_error("%{fr"), "Unclosed { at position 1 of the template");
+        assert!(parse_error("%{xx}T").starts_with("Unknown language: xx, expected one of "));
+        assert!(parse_error("%{xx}T").ends_with(" at position 1 of the template"));
+    }
+
+    #[test]
+    fn renders_the_folder_templates() {
+        let mut video = make_video();
+        let rules = FileNameRules {
+            profile: SanitisationProfile::Windows,
+            transliterate: false,
+            max_length: 255,
+        };
+        let template = "Library/Videos/%Y/[%C]/%Y-%M %T".parse::<FolderTemplate>().unwrap();
+        assert_eq!(
+            template.render(&video, &rules),
+            ["Library", "Videos", "2024", "iPhone 15", "2024-04 A Day at the Café"]
+                .iter()
+                .collect::<PathBuf>()
+        );
+        // The folders left empty are skipped
+        video.camera_model = String::default();
+        assert_eq!(
+            template.render(&video, &rules),
+            ["Library", "Videos", "2024", "2024-04 A Day at the Café"]
+                .iter()
+                .collect::<PathBuf>()
+        );
+    }
+
+    #[test]
+    fn sanitises_the_folder_names() {
+        let mut video = make_video();
+        video.resume.title = "What? A/B test".to_string();
+        let rules = FileNameRules {
+            profile: SanitisationProfile::Windows,
+            transliterate: false,
+            max_length: 255,
+        };
+        let template = "%T".parse::<FolderTemplate>().unwrap();
+        let folder = template.render(&video, &rules);
+        assert_eq!(folder.components().count(), 1);
+        assert!(!folder.to_str().unwrap().contains(['?', '/']));
+    }
+
+    #[test]
+    fn rejects_invalid_folder_templates() {
+        match "Library/%Y/%Q".parse::<FolderTemplate>() {
+            Ok(_) => panic!("the folder template should not parse"),
+            Err(error) => assert_eq!(
+                error,
+                "Unknown placeholder %Q at position 1 of the template (folder %Q)"
+            ),
+        }
+    }
+
+    #[test]
+    fn formats_the_durations() {
+        assert_eq!(format_duration(0.0), "");
+        assert_eq!(format_duration(45.4), "45s");
+        assert_eq!(format_duration(65.0), "1m05s");
+        assert_eq!(format_duration(3725.0), "1h02m05s");
+    }
+}
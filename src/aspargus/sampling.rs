@@ -0,0 +1,64 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Represents the strategy used to choose which frames of a video are sent to the models.
+/// ### Variants
+/// - `FixedCount`: A fixed number of frames, evenly spread over the video.
+/// - `FixedInterval`: A frame every `interval` seconds.
+/// - `SceneDetection`: A frame at each scene change, detected by FFmpeg when the scene score is above `threshold` (between 0 and 1).
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum SamplingStrategy {
+    FixedCount { count: u32 },
+    FixedInterval { interval: f32 },
+    SceneDetection { threshold: f32 },
+}
+
+impl Default for SamplingStrategy {
+    fn default() -> Self {
+        SamplingStrategy::FixedCount { count: 3 }
+    }
+}
+
+impl fmt::Display for SamplingStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SamplingStrategy::FixedCount { count } => write!(f, "count:{}", count),
+            SamplingStrategy::FixedInterval { interval } => write!(f, "interval:{}", interval),
+            SamplingStrategy::SceneDetection { threshold } => write!(f, "scene:{}", threshold),
+        }
+    }
+}
+
+impl FromStr for SamplingStrategy {
+    type Err = String;
+
+    /// Parses a sampling strategy written as `count:<N>`, `interval:<SECONDS>` or `scene:<THRESHOLD>`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (mode, parameter) = value.split_once(':').unwrap_or((value, ""));
+        match mode.trim() {
+            "count" => match parameter.trim().parse::<u32>() {
+                Ok(count) if count > 0 => Ok(SamplingStrategy::FixedCount { count }),
+                _ => Err(format!("Invalid frame count in sampling strategy: {}", value)),
+            },
+            "interval" => match parameter.trim().parse::<f32>() {
+                Ok(interval) if interval > 0.0 => Ok(SamplingStrategy::FixedInterval { interval }),
+                _ => Err(format!("Invalid interval in sampling strategy: {}", value)),
+            },
+            "scene" => match parameter.trim().parse::<f32>() {
+                Ok(threshold) if threshold > 0.0 && threshold < 1.0 => {
+                    Ok(SamplingStrategy::SceneDetection { threshold })
+                }
+                _ => Err(format!(
+                    "Invalid scene threshold in sampling strategy, it must be between 0 and 1: {}",
+                    value
+                )),
+            },
+            _ => Err(format!(
+                "Unknown sampling strategy: {}, expected count:<N>, interval:<SECONDS> or scene:<THRESHOLD>",
+                value
+            )),
+        }
+    }
+}
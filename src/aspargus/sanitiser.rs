@@ -0,0 +1,297 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Represents the set of rules applied to the generated file names.
+/// ### Variants
+/// - `Posix`: Only removes what POSIX file systems don't accept, i.e. slashes and control characters.
+/// - `Windows`: Also removes the characters reserved by Windows and by FAT/exFAT memory cards (`<>:"/\|?*`), emoji, trailing dots and reserved names such as `CON`.
+/// - `Slug`: Keeps only lowercase ASCII letters, digits, dashes and underscores.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SanitisationProfile {
+    Posix,
+    #[default]
+    Windows,
+    Slug,
+}
+
+impl fmt::Display for SanitisationProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanitisationProfile::Posix => write!(f, "posix"),
+            SanitisationProfile::Windows => write!(f, "windows"),
+            SanitisationProfile::Slug => write!(f, "slug"),
+        }
+    }
+}
+
+impl FromStr for SanitisationProfile {
+    type Err = String;
+
+    /// Parses a sanitisation profile written as `posix`, `windows` or `slug`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "posix" => Ok(SanitisationProfile::Posix),
+            "windows" => Ok(SanitisationProfile::Windows),
+            "slug" => Ok(SanitisationProfile::Slug),
+            _ => Err(format!(
+                "Unknown sanitisation profile: {}, expected posix, windows or slug",
+                value
+            )),
+        }
+    }
+}
+
+/// Represents the rules applied to the generated file names.
+/// ### Fields
+/// - `profile`: The sanitisation profile.
+/// - `transliterate`: Flag to transliterate the accented and non-Latin characters to ASCII (always done with the slug profile).
+/// - `max_length`: The maximum length of the file name in bytes, extension included.
+pub struct FileNameRules {
+    pub profile: SanitisationProfile,
+    pub transliterate: bool,
+    pub max_length: usize,
+}
+
+/// The characters reserved by Windows and by FAT/exFAT file systems.
+const WINDOWS_RESERVED_CHARACTERS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// The device names reserved by Windows, which can't be used as file names even with an extension.
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Sanitises a file name (without its extension) according to the given rules.
+///
+/// ### Parameters
+/// - `name`: The file name to sanitise.
+/// - `rules`: The rules to apply.
+///
+/// ### Returns
+/// The sanitised file name, which can be empty if nothing usable remains.
+pub fn sanitise_file_name(name: &str, rules: &FileNameRules) -> String {
+    let name = if rules.transliterate || rules.profile == SanitisationProfile::Slug {
+        // The emoji are removed rather than transliterated to words
+        let name: String = name.chars().filter(|c| !is_emoji_component(*c)).collect();
+        deunicode::deunicode(&name)
+    } else {
+        name.to_string()
+    };
+    let sanitised = match rules.profile {
+        SanitisationProfile::Posix => sanitise_posix(&name),
+        SanitisationProfile::Windows => sanitise_windows(&name),
+        SanitisationProfile::Slug => sanitise_slug(&name),
+    };
+    // A leading dot hides the file and a leading dash makes it look like a command option
+    sanitised.trim_start_matches(['.', '-', ' ']).to_string()
+}
+
+/// Removes the slashes and control characters, and collapses the whitespaces.
+///
+/// ### Parameters
+/// - `name`: The file name to sanitise.
+///
+/// ### Returns
+/// The sanitised file name.
+fn sanitise_posix(name: &str) -> String {
+    let replaced: String = name
+        .chars()
+        .map(|c| if c == '/' || c.is_control() { ' ' } else { c })
+        .collect();
+    collapse_whitespaces(&replaced)
+}
+
+/// Removes the characters reserved by Windows, the control characters and the emoji, the trailing dots, and renames the reserved device names.
+///
+/// ### Parameters
+/// - `name`: The file name to sanitise.
+///
+/// ### Returns
+/// The sanitised file name.
+fn sanitise_windows(name: &str) -> String {
+    let replaced: String = name
+        .chars()
+        .filter(|c| !is_emoji_component(*c))
+        .map(|c| {
+            if WINDOWS_RESERVED_CHARACTERS.contains(&c) || c.is_control() {
+                ' '
+            } else {
+                c
+            }
+        })
+        .collect();
+    let mut sanitised = collapse_whitespaces(&replaced)
+        .trim_end_matches(['.', ' '])
+        .to_string();
+    // The reserved names can't be used whatever follows their first dot, e.g. `NUL.mp4`
+    let base = sanitised.split('.').next().unwrap_or_default().trim_end();
+    if WINDOWS_RESERVED_NAMES.contains(&base.to_uppercase().as_str()) {
+        sanitised.insert(base.len(), '_');
+    }
+    sanitised
+}
+
+/// Turns a file name into a lowercase ASCII slug, any run of other characters becoming a single dash.
+///
+/// ### Parameters
+/// - `name`: The transliterated file name.
+///
+/// ### Returns
+/// The slug.
+fn sanitise_slug(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') && !slug.ends_with('_') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches(['-', '_']).to_string()
+}
+
+/// Turns a text into a lowercase ASCII slug, e.g. `Café du Port` becomes `cafe-du-port`.
+///
+/// ### Parameters
+/// - `text`: The text to turn into a slug.
+///
+/// ### Returns
+/// The slug.
+pub fn slugify(text: &str) -> String {
+    let text: String = text.chars().filter(|c| !is_emoji_component(*c)).collect();
+    sanitise_slug(&deunicode::deunicode(&text))
+}
+
+/// Checks if a character is part of an emoji: a pictograph outside of the basic multilingual plane, a variation selector or a zero width joiner.
+///
+/// ### Parameters
+/// - `c`: The character to check.
+///
+/// ### Returns
+/// True if the character is part of an emoji.
+fn is_emoji_component(c: char) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0xFE00..=0xFE0F | 0x200D)
+}
+
+/// Replaces any run of whitespaces by a single space and trims the text.
+///
+/// ### Parameters
+/// - `text`: The text to clean.
+///
+/// ### Returns
+/// The cleaned text.
+fn collapse_whitespaces(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Truncates a file name (without its extension) so that it fits in the given number of bytes, cutting on a word boundary when possible.
+///
+/// ### Parameters
+/// - `name`: The file name to truncate.
+/// - `max_length`: The maximum length in bytes.
+///
+/// ### Returns
+/// The truncated file name.
+pub fn truncate_file_name(name: &str, max_length: usize) -> String {
+    if name.len() <= max_length {
+        return name.to_string();
+    }
+    let mut end = max_length;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    let is_separator = |c: char| c.is_whitespace() || matches!(c, '-' | '_' | ',' | '.');
+    // Cuts on the last word boundary, unless it would drop more than half of the name
+    let cut = if name[end..].starts_with(is_separator) {
+        end
+    } else {
+        match name[..end].rfind(is_separator) {
+            Some(boundary) if boundary > end / 2 => boundary,
+            _ => end,
+        }
+    };
+    name[..cut].trim_end_matches(is_separator).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aspargus::file_management;
+    use crate::aspargus::rename_template::RenameTemplate;
+    use crate::aspargus::video::Video;
+
+    /// Creates the rules of a profile, without transliteration nor length limit.
+    fn make_rules(profile: SanitisationProfile) -> FileNameRules {
+        FileNameRules {
+            profile,
+            transliterate: false,
+            max_length: 255,
+        }
+    }
+
+    #[test]
+    fn truncates_on_utf8_character_boundaries() {
+        assert_eq!(truncate_file_name("Crème brûlée", 3), "Cr");
+        assert_eq!(truncate_file_name("Crème brûlée", 4), "Crè");
+        assert_eq!(truncate_file_name("日本の夏", 7), "日本");
+        assert_eq!(truncate_file_name("🏖️🌊", 5), "🏖");
+        let name = "Été à la plage, 日本の夏 🌊";
+        for max_length in 0..=name.len() {
+            let truncated = truncate_file_name(name, max_length);
+            assert!(truncated.len() <= max_length);
+            assert!(name.starts_with(truncated.as_str()));
+        }
+    }
+
+    #[test]
+    fn truncates_on_a_word_boundary_when_possible() {
+        assert_eq!(truncate_file_name("Sunset at the beach", 12), "Sunset at");
+        assert_eq!(truncate_file_name("Sunset_at_the_beach", 9), "Sunset_at");
+        assert_eq!(truncate_file_name("Sunset", 10), "Sunset");
+        // A boundary dropping more than half of the name is not used
+        assert_eq!(truncate_file_name("A verylongword", 10), "A verylong");
+    }
+
+    #[test]
+    fn renames_the_reserved_windows_names() {
+        let rules = make_rules(SanitisationProfile::Windows);
+        assert_eq!(sanitise_file_name("CON", &rules), "CON_");
+        assert_eq!(sanitise_file_name("nul", &rules), "nul_");
+        assert_eq!(sanitise_file_name("NUL.mp4", &rules), "NUL_.mp4");
+        assert_eq!(sanitise_file_name("com1 .backup", &rules), "com1_ .backup");
+        assert_eq!(sanitise_file_name("Console", &rules), "Console");
+        assert_eq!(sanitise_file_name("CON", &make_rules(SanitisationProfile::Posix)), "CON");
+    }
+
+    #[test]
+    fn removes_the_trailing_dots_and_spaces_for_windows() {
+        let rules = make_rules(SanitisationProfile::Windows);
+        assert_eq!(sanitise_file_name("The end... ", &rules), "The end");
+        assert_eq!(sanitise_file_name("What? A day: at <the> beach", &rules), "What A day at the beach");
+        assert_eq!(sanitise_file_name("..hidden", &rules), "hidden");
+        assert_eq!(sanitise_file_name(". . .", &rules), "");
+    }
+
+    #[test]
+    fn keeps_the_extension_within_the_length_limit() {
+        let template = "%T".parse::<RenameTemplate>().unwrap();
+        let mut video = Video {
+            path: "/videos/VID_0001.mp4".to_string(),
+            ..Default::default()
+        };
+        video.resume.title = "A very long day at the beach with the whole family".to_string();
+        let rules = FileNameRules {
+            max_length: 20,
+            ..make_rules(SanitisationProfile::Windows)
+        };
+        let new_name = file_management::create_new_file_name(&video, &template, &rules);
+        assert_eq!(new_name, "A very long day");
+        let new_path = file_management::create_new_path(&video.path, &new_name);
+        assert!(std::path::Path::new(&new_path).file_name().unwrap().len() <= 20);
+        // A file without extension has the whole length for its name
+        video.path = "/videos/VID_0001".to_string();
+        assert_eq!(file_management::create_new_file_name(&video, &template, &rules), "A very long day at");
+    }
+}
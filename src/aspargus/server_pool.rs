@@ -0,0 +1,431 @@
+use std::{
+    fmt,
+    future::Future,
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use futures::future;
+use ollama_rs::Ollama;
+use serde::{Deserialize, Serialize};
+
+use super::aspargus_helper::{self, VideoDataError};
+
+/// Represents how the requests are dispatched between the servers of a pool.
+/// ### Variants
+/// - `RoundRobin`: Each server gets a request in turn.
+/// - `LeastBusy`: The server with the fewest requests in progress gets the request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DispatchStrategy {
+    #[default]
+    RoundRobin,
+    LeastBusy,
+}
+
+impl fmt::Display for DispatchStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DispatchStrategy::RoundRobin => write!(f, "round_robin"),
+            DispatchStrategy::LeastBusy => write!(f, "least_busy"),
+        }
+    }
+}
+
+impl FromStr for DispatchStrategy {
+    type Err = String;
+
+    /// Parses a dispatch strategy written as `round_robin` or `least_busy`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().replace('-', "_").as_str() {
+            "round_robin" => Ok(DispatchStrategy::RoundRobin),
+            "least_busy" => Ok(DispatchStrategy::LeastBusy),
+            _ => Err(format!(
+                "Unknown dispatch strategy: {}, expected round_robin or least_busy",
+                value
+            )),
+        }
+    }
+}
+
+/// Represents an error of a server pool.
+/// ### Variants
+/// - `NoServerAvailable`: None of the servers of the pool answers.
+#[derive(Debug)]
+pub enum ServerPoolError {
+    NoServerAvailable(String),
+}
+
+impl std::error::Error for ServerPoolError {}
+
+impl fmt::Display for ServerPoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerPoolError::NoServerAvailable(role) => {
+                write!(f, "No {} server available", role)
+            }
+        }
+    }
+}
+
+/// Represents an Ollama server of a pool.
+/// ### Fields
+/// - `address`: The URL of the server, for the logs.
+/// - `ollama`: The model prompter of the server.
+/// - `busy`: The number of requests in progress on the server.
+/// - `healthy`: Flag if the server answers.
+/// - `missing_model`: Flag if the server doesn't have the model, in which case it is never used.
+struct Server {
+    address: String,
+    ollama: Ollama,
+    busy: AtomicUsize,
+    healthy: AtomicBool,
+    missing_model: AtomicBool,
+}
+
+/// Represents a pool of Ollama servers running the same role, between which the requests are balanced.
+/// ### Fields
+/// - `role`: The role of the servers, for the logs (e.g. `computer vision`).
+/// - `servers`: The servers of the pool.
+/// - `strategy`: How the requests are dispatched between the servers.
+/// - `timeout`: The time after which a request is considered lost and sent to another server, None to wait for the answer.
+/// - `next`: The index of the next server for the round robin strategy.
+pub struct ServerPool {
+    role: String,
+    servers: Vec<Server>,
+    strategy: DispatchStrategy,
+    timeout: Option<Duration>,
+    next: AtomicUsize,
+}
+
+/// Parses a server endpoint written as `URL:PORT`, e.g. `http://gpu1:11434`. The scheme defaults to `http` and the port to 11434.
+///
+/// ### Parameters
+/// - `endpoint`: The endpoint.
+///
+/// ### Returns
+/// A Result containing the model prompter of the server.
+///
+/// ### Errors
+/// Returns an error if the endpoint is not a valid URL.
+pub fn parse_endpoint(endpoint: &str) -> Result<Ollama, String> {
+    let endpoint = endpoint.trim().trim_end_matches('/');
+    let endpoint = if endpoint.contains("://") {
+        endpoint.to_string()
+    } else {
+        format!("http://{}", endpoint)
+    };
+    let ollama = Ollama::try_new(endpoint.as_str())
+        .map_err(|error| format!("Invalid server: {}, {}", endpoint, error))?;
+    if ollama.url().host().is_none() {
+        return Err(format!("Invalid server: {}, the host is missing", endpoint));
+    }
+    if ollama.url().port().is_some() {
+        Ok(ollama)
+    } else {
+        Ollama::try_new(format!("{}:11434", endpoint).as_str())
+            .map_err(|error| format!("Invalid server: {}, {}", endpoint, error))
+    }
+}
+
+impl ServerPool {
+    /// Creates a pool of servers. The invalid endpoints are logged and left out.
+    ///
+    /// ### Parameters
+    /// - `role`: The role of the servers, for the logs.
+    /// - `endpoints`: The endpoints of the servers, as `URL:PORT`.
+    /// - `strategy`: How the requests are dispatched between the servers.
+    /// - `timeout`: The time after which a request is considered lost, None to wait for the answer. It is ignored when the pool has a single server, as there is no other server to send the request to.
+    ///
+    /// ### Returns
+    /// A new pool, all its servers being considered healthy until checked.
+    pub fn new(role: &str, endpoints: &[String], strategy: DispatchStrategy, timeout: Option<Duration>) -> Self {
+        let servers: Vec<Server> = endpoints
+            .iter()
+            .filter_map(|endpoint| match parse_endpoint(endpoint) {
+                Ok(ollama) => Some(Server {
+                    address: ollama.url_str().trim_end_matches('/').to_string(),
+                    ollama,
+                    busy: AtomicUsize::new(0),
+                    healthy: AtomicBool::new(true),
+                    missing_model: AtomicBool::new(false),
+                }),
+                Err(error) => {
+                    log::error!("{}", error);
+                    None
+                }
+            })
+            .collect();
+        let timeout = timeout.filter(|_| servers.len() > 1);
+        Self {
+            role: role.to_string(),
+            servers,
+            strategy,
+            timeout,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Checks which servers of the pool answer and have a model, the others being left aside until they answer again.
+    ///
+    /// ### Parameters
+    /// - `model`: The name of the model the servers must have.
+    ///
+    /// ### Returns
+    /// A Result containing the number of healthy servers.
+    ///
+    /// ### Errors
+    /// Returns an error if no server of the pool is healthy.
+    pub async fn check_health(&self, model: &str) -> anyhow::Result<usize> {
+        let probe_timeout = self.get_probe_timeout();
+        let checks = self.servers.iter().map(|server| async move {
+            let models = tokio::time::timeout(
+                probe_timeout,
+                aspargus_helper::get_models_for_server(&server.ollama),
+            )
+            .await;
+            let missing_model = matches!(&models, Ok(Ok(models)) if !has_model(models, model));
+            let healthy = match models {
+                Ok(Ok(models)) if has_model(&models, model) => {
+                    log::info!("The {} server {} is available", self.role, server.address);
+                    true
+                }
+                Ok(Ok(_)) => {
+                    log::warn!(
+                        "The {} server {} doesn't have the model {}, it won't be used",
+                        self.role,
+                        server.address,
+                        model
+                    );
+                    false
+                }
+                Ok(Err(error)) => {
+                    log::warn!(
+                        "The {} server {} doesn't answer, it won't be used: {}",
+                        self.role,
+                        server.address,
+                        error
+                    );
+                    false
+                }
+                Err(_) => {
+                    log::warn!(
+                        "The {} server {} doesn't answer within {} seconds, it won't be used",
+                        self.role,
+                        server.address,
+                        probe_timeout.as_secs()
+                    );
+                    false
+                }
+            };
+            server.healthy.store(healthy, Ordering::Relaxed);
+            server.missing_model.store(missing_model, Ordering::Relaxed);
+            healthy
+        });
+        let healthy = future::join_all(checks)
+            .await
+            .into_iter()
+            .filter(|healthy| *healthy)
+            .count();
+        if healthy == 0 {
+            return Err(ServerPoolError::NoServerAvailable(self.role.clone()).into());
+        }
+        Ok(healthy)
+    }
+
+    /// Gets the names of the models available on the servers of the pool.
+    ///
+    /// ### Returns
+    /// A Result containing the names of the models, without duplicates.
+    ///
+    /// ### Errors
+    /// Returns an error if no server of the pool answers.
+    pub async fn list_models(&self) -> anyhow::Result<Vec<String>> {
+        let mut last_error = anyhow::Error::new(ServerPoolError::NoServerAvailable(self.role.clone()));
+        let mut all_models: Vec<String> = Vec::new();
+        let mut answered = false;
+        for server in &self.servers {
+            match aspargus_helper::get_models_for_server(&server.ollama).await {
+                Ok(models) => {
+                    answered = true;
+                    for model in models {
+                        if !all_models.contains(&model) {
+                            all_models.push(model);
+                        }
+                    }
+                }
+                Err(error) => last_error = error,
+            }
+        }
+        if answered {
+            Ok(all_models)
+        } else {
+            Err(last_error)
+        }
+    }
+
+    /// Sends a request to a server of the pool. When the server can't be reached or doesn't answer in time, it is left aside and the request is sent to another server, each server being tried at most once.
+    ///
+    /// ### Parameters
+    /// - `request`: The request, given the model prompter of the chosen server.
+    ///
+    /// ### Returns
+    /// The result of the request.
+    ///
+    /// ### Errors
+    /// Returns the error of the request, or the error of the last server tried if no server of the pool answers.
+    pub async fn run<T, F, Fut>(&self, request: F) -> anyhow::Result<T>
+    where
+        F: Fn(Ollama) -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let mut last_error = anyhow::Error::new(ServerPoolError::NoServerAvailable(self.role.clone()));
+        for _ in 0..self.servers.len() {
+            let server = match self.pick() {
+                Some(server) => server,
+                None => {
+                    // The servers left aside may have recovered since
+                    self.revive().await;
+                    self.pick()
+                        .ok_or_else(|| ServerPoolError::NoServerAvailable(self.role.clone()))?
+                }
+            };
+            server.busy.fetch_add(1, Ordering::Relaxed);
+            let result = match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, request(server.ollama.clone())).await,
+                None => Ok(request(server.ollama.clone()).await),
+            };
+            server.busy.fetch_sub(1, Ordering::Relaxed);
+            match result {
+                Ok(Err(error)) if is_server_error(&error) => {
+                    server.healthy.store(false, Ordering::Relaxed);
+                    log::warn!(
+                        "The {} server {} can't be reached, it is left aside: {}",
+                        self.role,
+                        server.address,
+                        error
+                    );
+                    last_error = error;
+                }
+                Ok(result) => return result,
+                Err(_) => {
+                    let timeout = self.timeout.unwrap_or_default().as_secs();
+                    server.healthy.store(false, Ordering::Relaxed);
+                    log::warn!(
+                        "The {} server {} didn't answer within {} seconds, it is left aside",
+                        self.role,
+                        server.address,
+                        timeout
+                    );
+                    last_error = anyhow::anyhow!(
+                        "The {} server {} didn't answer within {} seconds",
+                        self.role,
+                        server.address,
+                        timeout
+                    );
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Gets the time after which a server that doesn't answer a health check is left aside, at most 10 seconds.
+    ///
+    /// ### Returns
+    /// The time of a health check.
+    fn get_probe_timeout(&self) -> Duration {
+        let probe_timeout = Duration::from_secs(10);
+        self.timeout.map_or(probe_timeout, |timeout| timeout.min(probe_timeout))
+    }
+
+    /// Picks the server for the next request according to the dispatch strategy.
+    ///
+    /// ### Returns
+    /// The chosen server, None if no server is healthy.
+    fn pick(&self) -> Option<&Server> {
+        let count = self.servers.len();
+        if count == 0 {
+            return None;
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        // Starting from the next server in turn also spreads the requests between equally busy servers
+        let mut healthy = (0..count)
+            .map(|offset| &self.servers[(start + offset) % count])
+            .filter(|server| server.healthy.load(Ordering::Relaxed));
+        match self.strategy {
+            DispatchStrategy::RoundRobin => healthy.next(),
+            DispatchStrategy::LeastBusy => {
+                healthy.min_by_key(|server| server.busy.load(Ordering::Relaxed))
+            }
+        }
+    }
+
+    /// Checks again the servers left aside, and puts back those that answer.
+    async fn revive(&self) {
+        let probe_timeout = self.get_probe_timeout();
+        let left_aside = self
+            .servers
+            .iter()
+            .filter(|server| !server.missing_model.load(Ordering::Relaxed));
+        let probes = left_aside.map(|server| async move {
+            let models = tokio::time::timeout(
+                probe_timeout,
+                aspargus_helper::get_models_for_server(&server.ollama),
+            )
+            .await;
+            if let Ok(Ok(_)) = models {
+                log::info!("The {} server {} is available again", self.role, server.address);
+                server.healthy.store(true, Ordering::Relaxed);
+            }
+        });
+        future::join_all(probes).await;
+    }
+}
+
+/// Checks if a model is in a list of models, the `latest` tag being implied.
+///
+/// ### Parameters
+/// - `models`: The names of the models of a server.
+/// - `model`: The name of the model.
+///
+/// ### Returns
+/// True if the model is in the list.
+fn has_model(models: &[String], model: &str) -> bool {
+    models
+        .iter()
+        .any(|name| name == model || *name == format!("{}:latest", model))
+}
+
+/// Checks if an error comes from a server that can't be reached, rather than from the request itself.
+///
+/// ### Parameters
+/// - `error`: The error of the request.
+///
+/// ### Returns
+/// True if the server can't be reached.
+fn is_server_error(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<VideoDataError>(),
+        Some(VideoDataError::ModelServerError(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_applies_the_timeout_between_several_servers() {
+        let timeout = Some(Duration::from_secs(300));
+        let endpoints = vec!["gpu1".to_string(), "gpu2:11435".to_string()];
+        let pool = ServerPool::new("text", &endpoints, DispatchStrategy::RoundRobin, timeout);
+        assert_eq!(pool.timeout, timeout);
+        assert_eq!(pool.get_probe_timeout(), Duration::from_secs(10));
+        let pool = ServerPool::new("text", &endpoints[..1], DispatchStrategy::RoundRobin, timeout);
+        assert_eq!(pool.timeout, None);
+        let pool = ServerPool::new("text", &endpoints, DispatchStrategy::RoundRobin, None);
+        assert_eq!(pool.timeout, None);
+        assert_eq!(pool.get_probe_timeout(), Duration::from_secs(10));
+    }
+}
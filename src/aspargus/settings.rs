@@ -1,8 +1,21 @@
 use std::{fmt, fs, path::PathBuf};
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
+use super::captions::CaptionMode;
+use super::contact_sheet::ContactSheetMode;
+use super::custom_fields::CustomField;
+use super::faces::{FaceBackend, DEFAULT_FACE_THRESHOLD};
+use super::failure::FailurePolicy;
 use super::file_management;
+use super::frames::FrameStorage;
+use super::image_resizer::{self, ImageProfile};
+use super::language::Language;
+use super::prompts::DEFAULT_PROMPT_SET;
+use super::sampling::SamplingStrategy;
+use super::sanitiser::SanitisationProfile;
+use super::server_pool::DispatchStrategy;
+use super::transcription::TranscriptionBackend;
 
 /// Represents the Aspargus settings.
 ///
@@ -13,10 +26,32 @@ use super::file_management;
 /// - `text_model`: The name of the text model.
 /// - `text_server`: The server URL for the text model.
 /// - `text_server_port`: The port of server URL for the text model.
+/// - `computer_vision_servers`: The servers, as `URL:PORT`, between which the computer vision requests are balanced. Replaces the computer vision server and port when not empty.
+/// - `text_servers`: The servers, as `URL:PORT`, between which the text requests are balanced. Replaces the text server and port when not empty.
+/// - `dispatch`: How the requests are dispatched between the servers.
+/// - `server_timeout`: The time in seconds after which a request is sent to another server, 0 to wait for the answer. Only used when there are several servers.
 /// - `work_folder`: The path to the work folder.
 /// - `temp_folder`: The path to the temp folder.
 /// - `settings_path`: The path to the settings file.
 /// - `two_steps`: Flag if the analysis must be performed in two steps or not.
+/// - `sampling`: The strategy used to extract the frames of the videos.
+/// - `min_frames`: The minimum number of frames to extract with the interval and scene detection strategies.
+/// - `max_frames`: The maximum number of frames to extract with the interval and scene detection strategies.
+/// - `frame_storage`: Where the extracted frames are held until they are sent to the models.
+/// - `image_profiles`: How the frames are prepared for each computer vision model, the models without a profile getting the default one.
+/// - `captions`: Whether the frames are captioned one by one, and if the captions are given to the model telling the story.
+/// - `contact_sheet`: Whether the frames are sent to the computer vision model as a single contact sheet, and if it is saved next to the videos.
+/// - `transcription`: The speech-to-text backend used to transcribe the audio of the videos, None to not transcribe them.
+/// - `faces`: The face detection backend used to recognise the recurring people of the videos, None to not detect the faces.
+/// - `face_threshold`: The minimum cosine similarity between two faces to be the same person.
+/// - `sanitisation`: The sanitisation profile applied to the new file names.
+/// - `transliterate`: Flag to transliterate the accented and non-Latin characters of the new file names to ASCII.
+/// - `max_name_length`: The maximum length of the new file names in bytes, extension included.
+/// - `failure_policy`: What to do when a video fails a stage of the analysis.
+/// - `concurrency`: The number of videos sent to the models at the same time.
+/// - `prompt_set`: The name of the set of prompt templates, in the `prompts` folder of the work folder.
+/// - `languages`: The languages of the titles, descriptions and keywords, the first one being the main language and the others translations.
+/// - `custom_fields`: The fields generated by the models in addition to the title, description and keywords.
 #[derive(Default, Deserialize, Serialize, Debug)]
 pub struct AspargusSettings {
     #[serde(default = "get_default_cv_model")]
@@ -37,8 +72,86 @@ pub struct AspargusSettings {
     pub computer_vision_server_port: u16,
     #[serde(default = "get_default_server_port")]
     pub text_server_port: u16,
+    #[serde(default)]
+    pub computer_vision_servers: Vec<String>,
+    #[serde(default)]
+    pub text_servers: Vec<String>,
+    #[serde(default)]
+    pub dispatch: DispatchStrategy,
+    #[serde(default = "get_default_server_timeout")]
+    pub server_timeout: u64,
     #[serde(default = "get_default_two_steps")]
     pub two_steps: bool,
+    #[serde(default)]
+    pub sampling: SamplingStrategy,
+    #[serde(default = "get_default_min_frames")]
+    pub min_frames: u32,
+    #[serde(default = "get_default_max_frames")]
+    pub max_frames: u32,
+    #[serde(default)]
+    pub frame_storage: FrameStorage,
+    #[serde(default)]
+    pub image_profiles: Vec<ImageProfile>,
+    #[serde(default)]
+    pub captions: CaptionMode,
+    #[serde(default)]
+    pub contact_sheet: ContactSheetMode,
+    #[serde(default)]
+    pub transcription: Option<TranscriptionBackend>,
+    #[serde(default)]
+    pub faces: Option<FaceBackend>,
+    #[serde(default = "get_default_face_threshold")]
+    pub face_threshold: f32,
+    #[serde(default)]
+    pub sanitisation: SanitisationProfile,
+    #[serde(default)]
+    pub transliterate: bool,
+    #[serde(default = "get_default_max_name_length")]
+    pub max_name_length: usize,
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+    #[serde(default = "get_default_concurrency", deserialize_with = "deserialize_concurrency")]
+    pub concurrency: usize,
+    #[serde(default = "get_default_prompt_set")]
+    pub prompt_set: String,
+    #[serde(default = "get_default_languages", deserialize_with = "deserialize_languages")]
+    pub languages: Vec<Language>,
+    #[serde(default)]
+    pub custom_fields: Vec<CustomField>,
+}
+
+impl AspargusSettings {
+    /// Gets the endpoints of the computer vision servers.
+    ///
+    /// ### Returns
+    /// The list of computer vision servers, or the computer vision server and port if the list is empty.
+    pub fn get_computer_vision_endpoints(&self) -> Vec<String> {
+        if self.computer_vision_servers.is_empty() {
+            vec![format!("{}:{}", self.computer_vision_server, self.computer_vision_server_port)]
+        } else {
+            self.computer_vision_servers.clone()
+        }
+    }
+
+    /// Gets the endpoints of the text servers.
+    ///
+    /// ### Returns
+    /// The list of text servers, or the text server and port if the list is empty.
+    pub fn get_text_endpoints(&self) -> Vec<String> {
+        if self.text_servers.is_empty() {
+            vec![format!("{}:{}", self.text_server, self.text_server_port)]
+        } else {
+            self.text_servers.clone()
+        }
+    }
+
+    /// Gets the profile preparing the frames for the computer vision model.
+    ///
+    /// ### Returns
+    /// The profile whose model name is the longest match for the computer vision model, or the default profile.
+    pub fn get_image_profile(&self) -> ImageProfile {
+        image_resizer::select_profile(&self.image_profiles, &self.computer_vision_model)
+    }
 }
 
 // Implement the fmt::Display trait for AspargusSettings
@@ -47,9 +160,33 @@ impl fmt::Display for AspargusSettings {
         writeln!(f, "AspargusSettings:")?;
         writeln!(f, "  Computer Vision Model: {}", self.computer_vision_model)?;
         writeln!(f, "  Text Model: {}", self.text_model)?;
-        writeln!(f, "  Computer Vision Server: {}:{}", self.computer_vision_server, self.computer_vision_server_port)?;
-        writeln!(f, "  Text Server: {}:{}", self.text_server, self.text_server_port)?;
+        writeln!(f, "  Computer Vision Servers: {}", self.get_computer_vision_endpoints().join(", "))?;
+        writeln!(f, "  Text Servers: {}", self.get_text_endpoints().join(", "))?;
+        if self.server_timeout == 0 {
+            writeln!(f, "  Dispatch: {}, no timeout", self.dispatch)?;
+        } else {
+            writeln!(f, "  Dispatch: {}, timeout of {} seconds", self.dispatch, self.server_timeout)?;
+        }
         writeln!(f, "  Two Steps mode: {}", self.two_steps)?;
+        writeln!(f, "  Sampling: {} (from {} to {} frames)", self.sampling, self.min_frames, self.max_frames)?;
+        writeln!(f, "  Frame storage: {}", self.frame_storage)?;
+        writeln!(f, "  Images: {}", self.get_image_profile())?;
+        writeln!(f, "  Frame captions: {}", self.captions)?;
+        writeln!(f, "  Contact sheet: {}", self.contact_sheet)?;
+        match &self.transcription {
+            Some(transcription) => writeln!(f, "  Transcription: {}", transcription)?,
+            None => writeln!(f, "  Transcription: disabled")?,
+        }
+        match &self.faces {
+            Some(faces) => writeln!(f, "  Face detection: {} (similarity threshold of {})", faces, self.face_threshold)?,
+            None => writeln!(f, "  Face detection: disabled")?,
+        }
+        writeln!(f, "  File names: {} profile, transliteration {}, up to {} bytes", self.sanitisation, self.transliterate, self.max_name_length)?;
+        writeln!(f, "  Failure policy: {}", self.failure_policy)?;
+        writeln!(f, "  Concurrency: {}", self.concurrency)?;
+        writeln!(f, "  Prompt set: {}", self.prompt_set)?;
+        writeln!(f, "  Languages: {}", self.languages.iter().map(Language::to_string).collect::<Vec<_>>().join(", "))?;
+        writeln!(f, "  Custom fields: {}", self.custom_fields.iter().map(|field| field.name.as_str()).collect::<Vec<_>>().join(", "))?;
         writeln!(f, "  Work folder: {}", self.work_folder)?;
         writeln!(f, "  Temp folder: {}", self.temp_folder)?;
         writeln!(f, "  Settings path: {}", self.settings_path)?;
@@ -93,6 +230,16 @@ fn get_default_server_port() -> u16 {
     11434
 }
 
+/// Gets the default time after which a request is sent to another server.
+/// The requests have no timeout by default, as the time of a generation depends on the model and the hardware.
+///
+/// ### Returns
+/// The default time in seconds, 0 for no timeout.
+#[doc(hidden)]
+fn get_default_server_timeout() -> u64 {
+    0
+}
+
 /// Gets the default two steps flag value.
 ///
 /// ### Returns
@@ -102,6 +249,109 @@ fn get_default_two_steps() -> bool {
     false
 }
 
+/// Gets the default minimum number of frames to extract.
+///
+/// ### Returns
+/// The default minimum number of frames to extract.
+#[doc(hidden)]
+fn get_default_min_frames() -> u32 {
+    1
+}
+
+/// Gets the default maximum number of frames to extract.
+///
+/// ### Returns
+/// The default maximum number of frames to extract.
+#[doc(hidden)]
+fn get_default_max_frames() -> u32 {
+    10
+}
+
+/// Gets the default minimum similarity between two faces of the same person.
+///
+/// ### Returns
+/// The default minimum cosine similarity.
+#[doc(hidden)]
+fn get_default_face_threshold() -> f32 {
+    DEFAULT_FACE_THRESHOLD
+}
+
+/// Gets the default number of videos sent to the models at the same time.
+///
+/// ### Returns
+/// The default number of videos sent to the models at the same time.
+#[doc(hidden)]
+fn get_default_concurrency() -> usize {
+    1
+}
+
+/// Deserialises the number of videos sent to the models at the same time, clamped to at least 1 as no video would be analysed otherwise.
+///
+/// ### Parameters
+/// - `deserializer`: The deserializer of the settings file.
+///
+/// ### Returns
+/// A Result containing the number of videos.
+///
+/// ### Errors
+/// Returns an error if the value is not a positive integer.
+#[doc(hidden)]
+fn deserialize_concurrency<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    let concurrency = usize::deserialize(deserializer)?;
+    if concurrency == 0 {
+        log::warn!("The concurrency in the settings is 0, using 1");
+        return Ok(1);
+    }
+    Ok(concurrency)
+}
+
+/// Gets the default set of prompt templates.
+///
+/// ### Returns
+/// The name of the default set of prompt templates.
+#[doc(hidden)]
+fn get_default_prompt_set() -> String {
+    DEFAULT_PROMPT_SET.to_string()
+}
+
+/// Gets the default languages of the resumes.
+///
+/// ### Returns
+/// The default languages of the resumes, English only.
+#[doc(hidden)]
+fn get_default_languages() -> Vec<Language> {
+    vec![Language::default()]
+}
+
+/// Deserialises the languages of the resumes, an empty list being replaced by the default languages as at least one language is needed.
+///
+/// ### Parameters
+/// - `deserializer`: The deserializer of the settings file.
+///
+/// ### Returns
+/// A Result containing the languages.
+///
+/// ### Errors
+/// Returns an error if a language is unknown.
+#[doc(hidden)]
+fn deserialize_languages<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Language>, D::Error> {
+    let languages = Vec::<Language>::deserialize(deserializer)?;
+    if languages.is_empty() {
+        log::warn!("No languages in the settings, using the default ones");
+        return Ok(get_default_languages());
+    }
+    Ok(languages)
+}
+
+/// Gets the default maximum length of the new file names.
+///
+/// ### Returns
+/// The default maximum length of the new file names in bytes, the limit of most file systems.
+#[doc(hidden)]
+fn get_default_max_name_length() -> usize {
+    255
+}
+
 /// Loads the Aspargus settings, and creates a new file if it doesn't exist yet.
 ///
 /// ### Returns
@@ -126,14 +376,36 @@ pub fn load_settings() -> AspargusSettings {
             let aspargus_settings = AspargusSettings {
                 computer_vision_model: get_default_cv_model(),
                 text_model: get_default_text_model(),
-                work_folder: work_folder,
-                temp_folder: temp_folder,
+                work_folder,
+                temp_folder,
                 settings_path: settings_path.to_str().unwrap().to_string(),
                 computer_vision_server: get_default_server_url(),
                 text_server: get_default_server_url(),
                 computer_vision_server_port: get_default_server_port(),
                 text_server_port: get_default_server_port(),
+                computer_vision_servers: Vec::new(),
+                text_servers: Vec::new(),
+                dispatch: DispatchStrategy::default(),
+                server_timeout: get_default_server_timeout(),
                 two_steps: get_default_two_steps(),
+                sampling: SamplingStrategy::default(),
+                min_frames: get_default_min_frames(),
+                max_frames: get_default_max_frames(),
+                frame_storage: FrameStorage::default(),
+                image_profiles: Vec::new(),
+                captions: CaptionMode::default(),
+                contact_sheet: ContactSheetMode::default(),
+                transcription: None,
+                faces: None,
+                face_threshold: get_default_face_threshold(),
+                sanitisation: SanitisationProfile::default(),
+                transliterate: false,
+                max_name_length: get_default_max_name_length(),
+                failure_policy: FailurePolicy::default(),
+                concurrency: get_default_concurrency(),
+                prompt_set: get_default_prompt_set(),
+                languages: get_default_languages(),
+                custom_fields: Vec::new(),
             };
             save_settings(&aspargus_settings).expect("Saving settings file");
             aspargus_settings
@@ -161,7 +433,7 @@ pub fn save_settings(aspargus_settings: &AspargusSettings) -> anyhow::Result<()>
         }
     };
     match fs::write(
-        &PathBuf::from(aspargus_settings.settings_path.to_string()),
+        PathBuf::from(aspargus_settings.settings_path.to_string()),
         settings,
     ) {
         Ok(_) => Ok(()),
@@ -171,3 +443,28 @@ pub fn save_settings(aspargus_settings: &AspargusSettings) -> anyhow::Result<()>
         ))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_an_empty_language_list_with_the_default_languages() {
+        let settings: AspargusSettings = serde_json::from_str(r#"{"languages": []}"#).unwrap();
+        assert_eq!(settings.languages, get_default_languages());
+        let settings: AspargusSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(settings.languages, get_default_languages());
+        let settings: AspargusSettings = serde_json::from_str(r#"{"languages": ["fr", "de"]}"#).unwrap();
+        assert_eq!(settings.languages.len(), 2);
+    }
+
+    #[test]
+    fn clamps_the_concurrency_to_at_least_one() {
+        let settings: AspargusSettings = serde_json::from_str(r#"{"concurrency": 0}"#).unwrap();
+        assert_eq!(settings.concurrency, 1);
+        let settings: AspargusSettings = serde_json::from_str(r#"{"concurrency": 4}"#).unwrap();
+        assert_eq!(settings.concurrency, 4);
+        let settings: AspargusSettings = serde_json::from_str("{}").unwrap();
+        assert_eq!(settings.concurrency, get_default_concurrency());
+    }
+}
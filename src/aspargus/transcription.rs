@@ -0,0 +1,202 @@
+use std::{
+    fmt,
+    io::ErrorKind,
+    path::PathBuf,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::aspargus_helper::VideoDataError;
+use super::file_management;
+use super::video::Video;
+
+/// Represents a local speech-to-text backend.
+pub trait Transcriber {
+    /// Transcribes an audio file.
+    ///
+    /// ### Parameters
+    /// - `audio_path`: The path of the audio file, a 16 kHz mono WAV file.
+    ///
+    /// ### Returns
+    /// A Result containing the transcript.
+    ///
+    /// ### Errors
+    /// Returns an error if the backend can't be run or fails.
+    fn transcribe(&self, audio_path: &str) -> anyhow::Result<String>;
+}
+
+/// Represents the configuration of the speech-to-text backend.
+/// ### Variants
+/// - `WhisperCpp`: A whisper.cpp binary, `binary` being its name or path and `model` the path of the GGML model.
+/// - `Command`: Any command printing the transcript on its standard output, where `{audio}` is replaced by the path of the audio file. Its arguments are split like a shell would.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum TranscriptionBackend {
+    WhisperCpp {
+        #[serde(default = "get_default_whisper_binary")]
+        binary: String,
+        model: String,
+    },
+    Command {
+        command: String,
+    },
+}
+
+impl fmt::Display for TranscriptionBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscriptionBackend::WhisperCpp { binary, model } => {
+                write!(f, "whisper:{} (with {})", model, binary)
+            }
+            TranscriptionBackend::Command { command } => write!(f, "command:{}", command),
+        }
+    }
+}
+
+impl FromStr for TranscriptionBackend {
+    type Err = String;
+
+    /// Parses a transcription backend written as `whisper:<MODEL_PATH>` or `command:<COMMAND>`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            Some(("whisper", model)) if !model.trim().is_empty() => {
+                Ok(TranscriptionBackend::WhisperCpp {
+                    binary: get_default_whisper_binary(),
+                    model: model.trim().to_string(),
+                })
+            }
+            Some(("command", command)) if command.contains("{audio}") && shlex::split(command).is_some() => {
+                Ok(TranscriptionBackend::Command {
+                    command: command.trim().to_string(),
+                })
+            }
+            _ => Err(format!(
+                "Invalid transcription backend: {}, expected whisper:<MODEL_PATH>, command:<COMMAND> with an {{audio}} argument and balanced quotes, or none",
+                value
+            )),
+        }
+    }
+}
+
+impl Transcriber for TranscriptionBackend {
+    fn transcribe(&self, audio_path: &str) -> anyhow::Result<String> {
+        let mut command = match self {
+            TranscriptionBackend::WhisperCpp { binary, model } => {
+                let mut command = Command::new(binary);
+                command
+                    .arg("-m")
+                    .arg(model)
+                    .arg("-f")
+                    .arg(audio_path)
+                    .arg("--no-timestamps")
+                    .arg("--no-prints");
+                command
+            }
+            TranscriptionBackend::Command { command } => {
+                // The arguments are split like a shell would, so that quoted paths can contain spaces
+                let Some(parts) = shlex::split(command) else {
+                    return Err(anyhow::anyhow!("Invalid quotes in the transcription command: {}", command));
+                };
+                let mut parts = parts.into_iter();
+                let Some(program) = parts.next() else {
+                    return Err(anyhow::anyhow!("Empty transcription command"));
+                };
+                let mut the_command = Command::new(program);
+                the_command.args(parts.map(|part| part.replace("{audio}", audio_path)));
+                the_command
+            }
+        };
+        let output = command.stderr(Stdio::null()).output()?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "The transcription backend failed for: {}",
+                audio_path
+            ));
+        }
+        let transcript = String::from_utf8_lossy(&output.stdout);
+        Ok(transcript
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+}
+
+/// Gets the default whisper.cpp binary.
+///
+/// ### Returns
+/// The default whisper.cpp binary.
+#[doc(hidden)]
+fn get_default_whisper_binary() -> String {
+    "whisper-cli".to_string()
+}
+
+/// Extracts the audio track of a video as a 16 kHz mono WAV file, the format expected by the speech-to-text backends.
+///
+/// ### Parameters
+/// - `temp_folder`: The path of the temp folder to save the audio file in.
+/// - `video`: The video to extract the audio from.
+///
+/// ### Returns
+/// A Result containing the path of the audio file.
+///
+/// ### Errors
+/// Returns an error if FFmpeg can't be run (e.g. not in the path), or if the video has no audio track.
+pub(crate) fn extract_audio_for_video(temp_folder: &str, video: &Video) -> anyhow::Result<String> {
+    let mut path = PathBuf::from(temp_folder);
+    path.push(format!("{}.wav", video.id));
+    let audio_path = path.to_str().unwrap().to_string();
+    let status = Command::new("ffmpeg")
+        .arg("-y")
+        .arg("-i")
+        .arg(video.path.as_str())
+        .arg("-vn")
+        .arg("-ac")
+        .arg("1")
+        .arg("-ar")
+        .arg("16000")
+        .arg("-c:a")
+        .arg("pcm_s16le")
+        .arg(audio_path.as_str())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => Ok(audio_path),
+        Ok(_) => Err(VideoDataError::AudioExtractionError(video.path.clone()).into()),
+        Err(error) => {
+            if error.kind() == ErrorKind::NotFound {
+                let error_message = "FFMpeg can't be found, we're stopping here. Please install FFMpeg and FFProbe and make sure they're in the path.".to_string();
+                Err(VideoDataError::FFMpegNotFoundError(error_message).into())
+            } else {
+                Err(VideoDataError::AudioExtractionError(video.path.clone()).into())
+            }
+        }
+    }
+}
+
+/// Transcribes what is said in a video.
+///
+/// ### Parameters
+/// - `temp_folder`: The path of the temp folder to save the audio file in.
+/// - `transcriber`: The speech-to-text backend.
+/// - `video`: The video to transcribe.
+///
+/// ### Returns
+/// A Result containing the transcript, empty if nothing is said.
+///
+/// ### Errors
+/// Returns an error if the audio can't be extracted or transcribed.
+pub(crate) fn transcribe_video(
+    temp_folder: &str,
+    transcriber: &dyn Transcriber,
+    video: &Video,
+) -> anyhow::Result<String> {
+    let audio_path = extract_audio_for_video(temp_folder, video)?;
+    let transcript = transcriber.transcribe(audio_path.as_str());
+    file_management::remove_files(&[audio_path]);
+    transcript
+}
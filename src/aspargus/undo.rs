@@ -0,0 +1,242 @@
+use std::{fs, path::Path, path::PathBuf};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use super::file_management;
+
+/// Represents the kind of a file operation.
+/// ### Variants
+/// - `Rename`: The file was renamed in its folder.
+/// - `Move`: The file was moved to another folder.
+/// - `Copy`: The file was copied to another folder, the original file being kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    #[default]
+    Rename,
+    Move,
+    Copy,
+}
+
+/// Represents a file operation that can be undone.
+/// ### Fields
+/// - `kind`: The kind of operation.
+/// - `original_path`: The path of the file before the operation.
+/// - `new_path`: The path of the file after the operation.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct UndoOperation {
+    #[serde(default)]
+    pub kind: OperationKind,
+    pub original_path: String,
+    pub new_path: String,
+}
+
+/// Represents an undo manifest, listing the file operations of a run so that they can be reverted.
+/// ### Fields
+/// - `created`: The date of the operations.
+/// - `operations`: The operations, in the order they were done.
+/// - `created_folders`: The folders created by the operations, in the order they were created.
+/// - `name`: The name of the manifest file, set when the manifest is created.
+#[derive(Default, Deserialize, Serialize)]
+pub struct UndoManifest {
+    pub created: String,
+    pub operations: Vec<UndoOperation>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub created_folders: Vec<String>,
+    #[serde(skip)]
+    name: String,
+}
+
+impl UndoManifest {
+    /// Creates a new empty undo manifest.
+    ///
+    /// ### Returns
+    /// A new undo manifest.
+    pub fn new() -> Self {
+        let now = Local::now();
+        Self {
+            created: now.to_rfc3339(),
+            operations: Vec::new(),
+            created_folders: Vec::new(),
+            // The milliseconds keep apart the manifests of runs started within the same second
+            name: now.format("%Y%m%d-%H%M%S-%3f").to_string(),
+        }
+    }
+
+    /// Saves the undo manifest in the `undo` folder of the work folder, named after its creation date and time.
+    /// Saving it again overwrites the same file, so that all the operations of a run are kept in a single manifest.
+    ///
+    /// ### Parameters
+    /// - `work_folder`: The path of the work folder.
+    ///
+    /// ### Returns
+    /// A Result containing the path of the manifest.
+    ///
+    /// ### Errors
+    /// Returns an error if the manifest can't be written.
+    pub fn save(&self, work_folder: &str) -> anyhow::Result<String> {
+        let mut path = PathBuf::from(work_folder);
+        path.push("undo");
+        fs::create_dir_all(&path)?;
+        path.push(format!("{}.json", self.name));
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path.to_str().unwrap_or_default().to_string())
+    }
+
+    /// Loads an undo manifest, either from its path or from its name in the `undo` folder of the work folder.
+    ///
+    /// ### Parameters
+    /// - `work_folder`: The path of the work folder.
+    /// - `manifest`: The path or the name of the manifest.
+    ///
+    /// ### Returns
+    /// A Result containing the undo manifest.
+    ///
+    /// ### Errors
+    /// Returns an error if the manifest can't be found or parsed.
+    pub fn load(work_folder: &str, manifest: &str) -> anyhow::Result<Self> {
+        let mut path = PathBuf::from(manifest);
+        if !path.is_file() {
+            path = PathBuf::from(work_folder);
+            path.push("undo");
+            path.push(format!("{}.json", manifest.trim_end_matches(".json")));
+        }
+        let contents = fs::read_to_string(&path)
+            .map_err(|_| anyhow::anyhow!("No undo manifest found for: {}", manifest))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Reverts the operations of the manifest, from the last one to the first one, and removes the created folders left empty.
+    /// A renamed or moved file is not restored if it is missing or if its original path is taken again, and a copy is only removed if the original file still exists.
+    ///
+    /// ### Returns
+    /// The number of reverted operations.
+    pub fn undo(&self) -> usize {
+        let mut reverted = 0;
+        for operation in self.operations.iter().rev() {
+            if !Path::new(&operation.new_path).is_file() {
+                log::warn!("{} doesn't exist anymore, it can't be restored", operation.new_path);
+            } else if operation.kind == OperationKind::Copy {
+                if !Path::new(&operation.original_path).is_file() {
+                    log::warn!(
+                        "{} doesn't exist anymore, its copy {} is kept",
+                        operation.original_path,
+                        operation.new_path
+                    );
+                    continue;
+                }
+                match fs::remove_file(&operation.new_path) {
+                    Ok(_) => {
+                        log::info!("Removed the copy {}", operation.new_path);
+                        reverted += 1;
+                    }
+                    Err(error) => log::error!(
+                        "Could not remove the copy {}: {}",
+                        operation.new_path,
+                        error
+                    ),
+                }
+            } else if Path::new(&operation.original_path).exists() {
+                log::warn!(
+                    "{} already exists, {} can't be restored",
+                    operation.original_path,
+                    operation.new_path
+                );
+            } else {
+                match file_management::move_file(&operation.new_path, &operation.original_path) {
+                    Ok(_) => {
+                        log::info!("Restored {} to {}", operation.new_path, operation.original_path);
+                        reverted += 1;
+                    }
+                    Err(error) => log::error!(
+                        "Could not restore {} to {}: {}",
+                        operation.new_path,
+                        operation.original_path,
+                        error
+                    ),
+                }
+            }
+        }
+        for folder in self.created_folders.iter().rev() {
+            // Only empty folders can be removed, so the folders still containing other files are kept
+            if fs::remove_dir(folder).is_ok() {
+                log::debug!("Removed the folder {}", folder);
+            }
+        }
+        reverted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_the_renamed_files_from_a_saved_manifest() {
+        let temp_folder = std::env::temp_dir().join(format!("aspargus-undo-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_folder);
+        fs::create_dir_all(&temp_folder).unwrap();
+        let work_folder = temp_folder.join("work");
+        let moved_folder = temp_folder.join("2024");
+        let get_path = |name: &str| temp_folder.join(name).to_str().unwrap().to_string();
+
+        let mut manifest = UndoManifest::new();
+        let renames = [
+            (OperationKind::Rename, "VID_0001.mp4", "Sunset at the beach.mp4"),
+            (OperationKind::Rename, "VID_0002.mp4", "Birthday party.mp4"),
+            (OperationKind::Move, "VID_0003.mp4", "2024/Snow in the mountains.mp4"),
+        ];
+        fs::create_dir_all(&moved_folder).unwrap();
+        manifest.created_folders.push(moved_folder.to_str().unwrap().to_string());
+        for (kind, original_name, new_name) in renames {
+            fs::write(get_path(original_name), original_name).unwrap();
+            fs::rename(get_path(original_name), get_path(new_name)).unwrap();
+            manifest.operations.push(UndoOperation {
+                kind,
+                original_path: get_path(original_name),
+                new_path: get_path(new_name),
+            });
+        }
+        let manifest_path = manifest.save(work_folder.to_str().unwrap()).unwrap();
+        // The target of the second rename was removed in the meantime
+        fs::remove_file(get_path("Birthday party.mp4")).unwrap();
+
+        let name = Path::new(&manifest_path).file_stem().unwrap().to_str().unwrap();
+        let manifest = UndoManifest::load(work_folder.to_str().unwrap(), name).unwrap();
+        assert_eq!(manifest.undo(), 2);
+        assert_eq!(fs::read_to_string(get_path("VID_0001.mp4")).unwrap(), "VID_0001.mp4");
+        assert_eq!(fs::read_to_string(get_path("VID_0003.mp4")).unwrap(), "VID_0003.mp4");
+        assert!(!Path::new(&get_path("Sunset at the beach.mp4")).exists());
+        assert!(!Path::new(&get_path("VID_0002.mp4")).exists());
+        // The created folder is removed once empty
+        assert!(!moved_folder.exists());
+        // Replaying the manifest again doesn't revert anything more
+        assert_eq!(manifest.undo(), 0);
+
+        fs::remove_dir_all(&temp_folder).unwrap();
+    }
+
+    #[test]
+    fn keeps_a_file_whose_original_path_is_taken_again() {
+        let temp_folder = std::env::temp_dir().join(format!("aspargus-undo-taken-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_folder);
+        fs::create_dir_all(&temp_folder).unwrap();
+        let original_path = temp_folder.join("VID_0001.mp4").to_str().unwrap().to_string();
+        let new_path = temp_folder.join("Sunset.mp4").to_str().unwrap().to_string();
+        fs::write(&new_path, "renamed").unwrap();
+        fs::write(&original_path, "new").unwrap();
+
+        let mut manifest = UndoManifest::new();
+        manifest.operations.push(UndoOperation {
+            kind: OperationKind::Rename,
+            original_path: original_path.clone(),
+            new_path: new_path.clone(),
+        });
+        assert_eq!(manifest.undo(), 0);
+        assert_eq!(fs::read_to_string(&original_path).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&new_path).unwrap(), "renamed");
+
+        fs::remove_dir_all(&temp_folder).unwrap();
+    }
+}
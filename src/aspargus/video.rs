@@ -1,50 +1,179 @@
+use std::collections::BTreeMap;
+
 use chksum_hash_md5 as md5;
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use super::aspargus_helper;
+use super::cache::AnalysisCache;
+use super::captions::FrameCaption;
+use super::journal::Stage;
+use super::sampling::SamplingStrategy;
+use super::{aspargus_helper, file_management};
 /// Represents a video resume, generated by an LLM.
 /// ### Fields
 /// - `title`: The title of the video.
 /// - `description`: The description of the video.
 /// - `keywords`: An array of keywords representing the video.
-#[derive(Default, Deserialize, Serialize, Debug)]
+/// - `language`: The code of the language of the title, description and keywords, empty for the resumes generated before the languages were supported, which are in English.
+/// - `translations`: The title, description and keywords in the other languages, indexed by language code.
+/// - `fields`: The values of the custom fields defined in the settings, indexed by name, null when the model couldn't give a valid value.
+#[derive(Clone, Default, Deserialize, Serialize, Debug, JsonSchema)]
 pub struct Resume {
     pub title: String,
     pub description: String,
     pub keywords: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    #[schemars(skip)]
+    pub language: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[schemars(skip)]
+    pub translations: BTreeMap<String, Translation>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub fields: BTreeMap<String, Value>,
+}
+
+/// Represents the title, description and keywords of a video in another language.
+/// ### Fields
+/// - `title`: The title of the video.
+/// - `description`: The description of the video.
+/// - `keywords`: An array of keywords representing the video.
+#[derive(Clone, Default, Deserialize, Serialize, Debug)]
+pub struct Translation {
+    pub title: String,
+    pub description: String,
+    pub keywords: Vec<String>,
+}
+
+impl Resume {
+    /// Gets the code of the language of the resume.
+    ///
+    /// ### Returns
+    /// The code of the language, `en` for the resumes generated before the languages were supported.
+    pub fn get_language(&self) -> &str {
+        if self.language.is_empty() {
+            "en"
+        } else {
+            self.language.as_str()
+        }
+    }
+
+    /// Gets the title, description and keywords of the resume in a language.
+    ///
+    /// ### Parameters
+    /// - `language`: The code of the language.
+    ///
+    /// ### Returns
+    /// The title, description and keywords, None if the resume is not available in that language.
+    pub fn get_translation(&self, language: &str) -> Option<Translation> {
+        if self.get_language() == language {
+            Some(Translation {
+                title: self.title.clone(),
+                description: self.description.clone(),
+                keywords: self.keywords.clone(),
+            })
+        } else {
+            self.translations.get(language).cloned()
+        }
+    }
+
+    /// Adds the names of the people recognised in the video to the keywords, in every language, unless they are already there.
+    ///
+    /// ### Parameters
+    /// - `people`: The names of the people.
+    pub fn add_people(&mut self, people: &[String]) {
+        let add = |keywords: &mut Vec<String>| {
+            for name in people {
+                if !keywords.iter().any(|keyword| keyword.eq_ignore_ascii_case(name)) {
+                    keywords.push(name.clone());
+                }
+            }
+        };
+        add(&mut self.keywords);
+        for translation in self.translations.values_mut() {
+            add(&mut translation.keywords);
+        }
+    }
 }
 
 /// Represents a video.
 /// ### Fields
 /// - `id`: An idea for this video, internal purpose.
+/// - `content_hash`: The key of the video's content, used as cache key as it survives renames, empty when the cache is not read.
 /// - `path`: The path of the video file.
 /// - `story`: The story of this video generated by the CV model when a 2 steps approach is prefered.
+/// - `transcript`: The transcript of what is said in the video, empty if the audio was not transcribed.
 /// - `resume`: The resume of the video generated by the CV and optionally the text models.
 /// - `thumbnails`: The thumbnails of the video.
+/// - `frames`: The frames of the video as JPEG images, when they are held in memory instead of the thumbnails.
+/// - `contact_sheet`: The frames tiled in a single JPEG image, empty if they are sent as separate images.
+/// - `timestamps`: The time of each thumbnail in the video in seconds, empty if FFmpeg didn't give them.
+/// - `timeline`: The captions of the thumbnails, empty if the frames were not captioned.
+/// - `face_clusters`: The IDs of the clusters of the faces found in the thumbnails, empty if the faces were not detected.
+/// - `people`: The names of the people recognised in the video, from the named face clusters.
 /// - `creation_date`: The creation date of the video.
-/// - `gap`: The gap between thumbnails to be extracted, based on the video's duration.
+/// - `duration`: The duration of the video in seconds, 0 if unknown.
+/// - `width`: The width of the video in pixels, 0 if unknown.
+/// - `height`: The height of the video in pixels, 0 if unknown.
+/// - `camera_make`: The make of the camera that recorded the video, empty if unknown.
+/// - `camera_model`: The model of the camera that recorded the video, empty if unknown.
+/// - `sampling`: The strategy that was used to extract the thumbnails, None if they were not extracted.
 /// - `numeric_id`: The number of the video in the queue.
+/// - `skip`: Flag if the video must not be processed further on.
+/// - `cached`: Flag if the resume of the video was found in the analysis cache.
+/// - `stage`: The last processing stage completed by the video.
 ///
 #[derive(Default, Serialize)]
 
 pub struct Video {
     #[serde(skip_serializing)]
     pub id: String,
+    #[serde(skip_serializing)]
+    pub content_hash: String,
     pub path: String,
     #[serde(skip_serializing)]
     pub story: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub transcript: String,
     pub resume: Resume,
     #[serde(skip_serializing)]
     pub thumbnails: Vec<String>,
     #[serde(skip_serializing)]
+    pub frames: Vec<Vec<u8>>,
+    #[serde(skip_serializing)]
+    pub contact_sheet: Vec<u8>,
+    #[serde(skip_serializing)]
+    pub timestamps: Vec<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub timeline: Vec<FrameCaption>,
+    #[serde(skip_serializing)]
+    pub face_clusters: Vec<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub people: Vec<String>,
+    #[serde(skip_serializing)]
     pub creation_date: DateTime<Utc>,
     #[serde(skip_serializing)]
-    pub gap: i32,
+    pub duration: f32,
+    #[serde(skip_serializing)]
+    pub width: u32,
+    #[serde(skip_serializing)]
+    pub height: u32,
+    #[serde(skip_serializing)]
+    pub camera_make: String,
+    #[serde(skip_serializing)]
+    pub camera_model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<SamplingStrategy>,
     #[serde(skip_serializing)]
     pub numeric_id: i32,
     #[serde(skip_serializing)]
     pub skip: bool,
+    #[serde(skip_serializing)]
+    pub cached: bool,
+    #[serde(skip_serializing)]
+    pub stage: Stage,
 }
 
 impl Video {
@@ -53,23 +182,65 @@ impl Video {
     /// ### Parameters
     /// - `path`: The path of the video file.
     /// - `numeric_id`: The number of the video in the queue.
+    /// - `cache`: The analysis cache to pick up a previous result from, None to force a new analysis.
     ///
     /// ### Returns
     /// A new Video.
-    pub fn new(path: String, numeric_id: i32) -> anyhow::Result<Self> {
+    pub fn new(path: String, numeric_id: i32, cache: Option<&AnalysisCache>) -> anyhow::Result<Self> {
         let id = md5::hash(&path).to_hex_lowercase();
-        let (duration, creation_date) = aspargus_helper::get_video_metadata(path.as_str())?;
-        let gap = aspargus_helper::get_capture_gap(duration.unwrap_or_default());
+        let content_hash = match cache {
+            Some(_) => file_management::get_content_key(path.as_str())?,
+            None => String::default(),
+        };
+        let metadata = aspargus_helper::get_video_metadata(path.as_str())?;
+        let cached_entry = cache.and_then(|cache| cache.get(content_hash.as_str()));
+        let (story, transcript, timeline, face_clusters, resume) = match cached_entry {
+            Some(entry) => (
+                entry.story.clone(),
+                entry.transcript.clone(),
+                entry.timeline.clone(),
+                entry.faces.clone(),
+                entry.resume.clone(),
+            ),
+            None => (String::default(), String::default(), Vec::new(), Vec::new(), Resume::default()),
+        };
         Ok(Self {
             id,
+            content_hash,
             path,
-            story: String::default(),
-            resume: Resume::default(),
+            story,
+            transcript,
+            resume,
             thumbnails: Vec::new(),
-            creation_date: creation_date.unwrap_or_default(),
-            gap,
+            frames: Vec::new(),
+            contact_sheet: Vec::new(),
+            timestamps: Vec::new(),
+            timeline,
+            face_clusters,
+            people: Vec::new(),
+            creation_date: metadata.creation_date.unwrap_or_default(),
+            duration: metadata.duration.unwrap_or_default(),
+            width: metadata.width,
+            height: metadata.height,
+            camera_make: metadata.camera_make,
+            camera_model: metadata.camera_model,
+            sampling: None,
             numeric_id,
             skip: false,
+            cached: cached_entry.is_some(),
+            stage: if cached_entry.is_some() {
+                Stage::Resume
+            } else {
+                Stage::Metadata
+            },
         })
     }
+
+    /// Gets the number of frames extracted from the video, held in memory or as thumbnails, their timestamps giving it once the frames held in memory are freed.
+    ///
+    /// ### Returns
+    /// The number of frames.
+    pub fn get_frame_count(&self) -> usize {
+        self.thumbnails.len().max(self.frames.len()).max(self.timestamps.len())
+    }
 }
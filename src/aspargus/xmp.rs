@@ -0,0 +1,497 @@
+use std::fs;
+
+use chrono::SecondsFormat;
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+
+use super::video::Video;
+
+/// The sidecar used as a starting point when the video has no sidecar yet.
+const XMP_TEMPLATE: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+/// Represents the fields already present in a sidecar.
+/// ### Fields
+/// - `title`: Flag if the sidecar has a title.
+/// - `description`: Flag if the sidecar has a description.
+/// - `create_date`: Flag if the sidecar has a creation date.
+/// - `subjects`: The keywords of the sidecar.
+#[derive(Default)]
+struct ExistingFields {
+    title: bool,
+    description: bool,
+    create_date: bool,
+    subjects: Vec<String>,
+}
+
+/// Gets the path of the XMP sidecar of a video, which is the path of the video followed by `.xmp`.
+///
+/// ### Parameters
+/// - `video_path`: The path of the video.
+///
+/// ### Returns
+/// The path of the sidecar.
+pub(crate) fn get_sidecar_path(video_path: &str) -> String {
+    format!("{}.xmp", video_path)
+}
+
+/// Writes the XMP sidecar of a video with its title, description, keywords and creation date.
+/// When a sidecar already exists, the title, description and creation date it already has are kept, the keywords are added to its own, and all the other fields are left untouched.
+///
+/// ### Parameters
+/// - `video`: The analysed video.
+///
+/// ### Returns
+/// A Result containing the path of the sidecar.
+///
+/// ### Errors
+/// Returns an error if the existing sidecar can't be parsed, or if the sidecar can't be written.
+pub(crate) fn export_xmp_sidecar(video: &Video) -> anyhow::Result<String> {
+    let path = get_sidecar_path(video.path.as_str());
+    let existing = fs::read_to_string(&path).ok();
+    let source = existing.as_deref().unwrap_or(XMP_TEMPLATE);
+    let merged = merge_xmp(source, video)?;
+    fs::write(&path, merged)?;
+    Ok(path)
+}
+
+/// Merges the resume of a video into an XMP document.
+///
+/// ### Parameters
+/// - `source`: The XMP document.
+/// - `video`: The analysed video.
+///
+/// ### Returns
+/// A Result containing the merged XMP document.
+///
+/// ### Errors
+/// Returns an error if the document can't be parsed or has no `rdf:Description` element.
+fn merge_xmp(source: &str, video: &Video) -> anyhow::Result<String> {
+    let existing = read_existing_fields(source)?;
+    let mut reader = Reader::from_str(source);
+    let mut writer = Writer::new(Vec::new());
+    let mut depth = 0;
+    let mut skipped_depth: Option<usize> = None;
+    let mut description_depth: Option<usize> = None;
+    let mut merged = false;
+    let mut subjects_written = false;
+    // Whitespace is held back so that it can be dropped together with the duplicated keywords and before the merged fields
+    let mut whitespace: Option<Event> = None;
+    loop {
+        let event = reader.read_event()?;
+        if skipped_depth.is_some() {
+            match event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) => {
+                    depth -= 1;
+                    if skipped_depth == Some(depth) {
+                        skipped_depth = None;
+                    }
+                }
+                _ => (),
+            }
+            continue;
+        }
+        match event {
+            Event::Start(element) => {
+                let name = get_name(&element);
+                if name == "dc:subject" {
+                    // The merged keywords replace the first keywords of the sidecar, and the other ones are dropped
+                    skipped_depth = Some(depth);
+                    let whitespace = whitespace.take();
+                    if !subjects_written {
+                        if let Some(whitespace) = whitespace {
+                            writer.write_event(whitespace)?;
+                        }
+                        write_subjects(&mut writer, video, &existing)?;
+                        subjects_written = true;
+                    }
+                } else {
+                    if let Some(whitespace) = whitespace.take() {
+                        writer.write_event(whitespace)?;
+                    }
+                    if name == "rdf:Description" && !merged && description_depth.is_none() {
+                        description_depth = Some(depth);
+                        writer.write_event(Event::Start(with_namespaces(element, source)))?;
+                    } else {
+                        writer.write_event(Event::Start(element))?;
+                    }
+                }
+                depth += 1;
+            }
+            Event::End(element) => {
+                depth -= 1;
+                if description_depth == Some(depth) && !merged {
+                    whitespace = None;
+                    write_fields(&mut writer, video, &existing, subjects_written)?;
+                    subjects_written = true;
+                    merged = true;
+                }
+                if let Some(whitespace) = whitespace.take() {
+                    writer.write_event(whitespace)?;
+                }
+                writer.write_event(Event::End(element))?;
+            }
+            Event::Empty(element) => {
+                let name = get_name(&element);
+                if name == "dc:subject" {
+                    let whitespace = whitespace.take();
+                    if !subjects_written {
+                        if let Some(whitespace) = whitespace {
+                            writer.write_event(whitespace)?;
+                        }
+                        write_subjects(&mut writer, video, &existing)?;
+                        subjects_written = true;
+                    }
+                    continue;
+                }
+                if let Some(whitespace) = whitespace.take() {
+                    writer.write_event(whitespace)?;
+                }
+                if name == "rdf:Description" && !merged {
+                    let element = with_namespaces(element, source);
+                    let end = BytesEnd::new(name);
+                    writer.write_event(Event::Start(element))?;
+                    write_fields(&mut writer, video, &existing, subjects_written)?;
+                    writer.write_event(Event::End(end))?;
+                    merged = true;
+                    subjects_written = true;
+                } else {
+                    writer.write_event(Event::Empty(element))?;
+                }
+            }
+            Event::Text(text) if text.iter().all(u8::is_ascii_whitespace) => {
+                if let Some(whitespace) = whitespace.take() {
+                    writer.write_event(whitespace)?;
+                }
+                whitespace = Some(Event::Text(text));
+            }
+            Event::Eof => break,
+            event => {
+                if let Some(whitespace) = whitespace.take() {
+                    writer.write_event(whitespace)?;
+                }
+                writer.write_event(event)?;
+            }
+        }
+    }
+    if let Some(whitespace) = whitespace.take() {
+        writer.write_event(whitespace)?;
+    }
+    if !merged {
+        return Err(anyhow::anyhow!("No rdf:Description element found in the sidecar"));
+    }
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+/// Reads the fields already present in an XMP document, either as elements or as attributes of `rdf:Description`.
+///
+/// ### Parameters
+/// - `source`: The XMP document.
+///
+/// ### Returns
+/// A Result containing the existing fields.
+///
+/// ### Errors
+/// Returns an error if the document can't be parsed.
+fn read_existing_fields(source: &str) -> anyhow::Result<ExistingFields> {
+    let mut existing = ExistingFields::default();
+    let mut reader = Reader::from_str(source);
+    let mut names: Vec<String> = Vec::new();
+    let mut text = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                read_description_attributes(&element, &mut existing)?;
+                names.push(get_name(&element));
+                text.clear();
+            }
+            Event::Empty(element) => read_description_attributes(&element, &mut existing)?,
+            Event::Text(content) => text.push_str(&content.decode()?),
+            Event::CData(content) => text.push_str(&content.decode()?),
+            Event::GeneralRef(reference) => {
+                let reference = format!("&{};", reference.decode()?);
+                text.push_str(&quick_xml::escape::unescape(&reference)?);
+            }
+            Event::End(_) => {
+                let name = names.pop().unwrap_or_default();
+                let value = text.trim();
+                if name == "xmp:CreateDate" && !value.is_empty() {
+                    existing.create_date = true;
+                } else if name == "rdf:li" && !value.is_empty() && names.len() >= 2 {
+                    match names[names.len() - 2].as_str() {
+                        "dc:title" => existing.title = true,
+                        "dc:description" => existing.description = true,
+                        "dc:subject" => existing.subjects.push(value.to_string()),
+                        _ => (),
+                    }
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(existing)
+}
+
+/// Reads the fields written as attributes of an `rdf:Description` element.
+///
+/// ### Parameters
+/// - `element`: The element, ignored if it is not an `rdf:Description`.
+/// - `existing`: The existing fields to update.
+///
+/// ### Errors
+/// Returns an error if an attribute can't be parsed.
+fn read_description_attributes(element: &BytesStart, existing: &mut ExistingFields) -> anyhow::Result<()> {
+    if get_name(element) != "rdf:Description" {
+        return Ok(());
+    }
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        if attribute.unescape_value()?.trim().is_empty() {
+            continue;
+        }
+        match attribute.key.as_ref() {
+            b"xmp:CreateDate" => existing.create_date = true,
+            b"dc:title" => existing.title = true,
+            b"dc:description" => existing.description = true,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Writes the fields of the video that are missing from the sidecar, and the merged keywords if they aren't written yet.
+///
+/// ### Parameters
+/// - `writer`: The XMP writer, positioned inside the `rdf:Description` element.
+/// - `video`: The analysed video.
+/// - `existing`: The fields already present in the sidecar.
+/// - `subjects_written`: Flag if the merged keywords were already written in place of the keywords of the sidecar.
+///
+/// ### Errors
+/// Returns an error if the fields can't be written.
+fn write_fields(
+    writer: &mut Writer<Vec<u8>>,
+    video: &Video,
+    existing: &ExistingFields,
+    subjects_written: bool,
+) -> anyhow::Result<()> {
+    if !existing.title && !video.resume.title.is_empty() {
+        write_language_alternative(writer, "dc:title", video.resume.title.as_str())?;
+    }
+    if !existing.description && !video.resume.description.is_empty() {
+        write_language_alternative(writer, "dc:description", video.resume.description.as_str())?;
+    }
+    if !subjects_written && (!existing.subjects.is_empty() || !video.resume.keywords.is_empty()) {
+        write_indentation(writer)?;
+        write_subjects(writer, video, existing)?;
+    }
+    if !existing.create_date && video.creation_date.timestamp() != 0 {
+        let create_date = video.creation_date.to_rfc3339_opts(SecondsFormat::Secs, true);
+        write_indentation(writer)?;
+        writer.write_event(Event::Start(BytesStart::new("xmp:CreateDate")))?;
+        writer.write_event(Event::Text(BytesText::new(create_date.as_str())))?;
+        writer.write_event(Event::End(BytesEnd::new("xmp:CreateDate")))?;
+    }
+    writer.write_event(Event::Text(BytesText::new("\n  ")))?;
+    Ok(())
+}
+
+/// Writes the keywords of the sidecar followed by the keywords of the video it doesn't have yet, ignoring the case.
+///
+/// ### Parameters
+/// - `writer`: The XMP writer.
+/// - `video`: The analysed video.
+/// - `existing`: The fields already present in the sidecar.
+///
+/// ### Errors
+/// Returns an error if the keywords can't be written.
+fn write_subjects(writer: &mut Writer<Vec<u8>>, video: &Video, existing: &ExistingFields) -> anyhow::Result<()> {
+    let mut subjects = existing.subjects.clone();
+    for keyword in &video.resume.keywords {
+        if !subjects.iter().any(|subject| subject.eq_ignore_ascii_case(keyword)) {
+            subjects.push(keyword.clone());
+        }
+    }
+    writer.write_event(Event::Start(BytesStart::new("dc:subject")))?;
+    writer.write_event(Event::Start(BytesStart::new("rdf:Bag")))?;
+    for subject in &subjects {
+        writer.write_event(Event::Start(BytesStart::new("rdf:li")))?;
+        writer.write_event(Event::Text(BytesText::new(subject)))?;
+        writer.write_event(Event::End(BytesEnd::new("rdf:li")))?;
+    }
+    writer.write_event(Event::End(BytesEnd::new("rdf:Bag")))?;
+    writer.write_event(Event::End(BytesEnd::new("dc:subject")))?;
+    Ok(())
+}
+
+/// Writes a language alternative property, with its value as default language.
+///
+/// ### Parameters
+/// - `writer`: The XMP writer.
+/// - `name`: The name of the property.
+/// - `value`: The value of the property.
+///
+/// ### Errors
+/// Returns an error if the property can't be written.
+fn write_language_alternative(writer: &mut Writer<Vec<u8>>, name: &str, value: &str) -> anyhow::Result<()> {
+    let mut item = BytesStart::new("rdf:li");
+    item.push_attribute(("xml:lang", "x-default"));
+    write_indentation(writer)?;
+    writer.write_event(Event::Start(BytesStart::new(name)))?;
+    writer.write_event(Event::Start(BytesStart::new("rdf:Alt")))?;
+    writer.write_event(Event::Start(item))?;
+    writer.write_event(Event::Text(BytesText::new(value)))?;
+    writer.write_event(Event::End(BytesEnd::new("rdf:li")))?;
+    writer.write_event(Event::End(BytesEnd::new("rdf:Alt")))?;
+    writer.write_event(Event::End(BytesEnd::new(name)))?;
+    Ok(())
+}
+
+/// Writes the indentation of a property of `rdf:Description`.
+///
+/// ### Parameters
+/// - `writer`: The XMP writer.
+///
+/// ### Errors
+/// Returns an error if the indentation can't be written.
+fn write_indentation(writer: &mut Writer<Vec<u8>>) -> anyhow::Result<()> {
+    writer.write_event(Event::Text(BytesText::new("\n   ")))?;
+    Ok(())
+}
+
+/// Adds the Dublin Core and XMP namespaces declarations to an `rdf:Description` element, if the document doesn't declare them yet.
+///
+/// ### Parameters
+/// - `element`: The `rdf:Description` element.
+/// - `source`: The XMP document.
+///
+/// ### Returns
+/// The element with the namespaces declarations.
+fn with_namespaces<'a>(mut element: BytesStart<'a>, source: &str) -> BytesStart<'a> {
+    if !source.contains("xmlns:dc=") {
+        element.push_attribute(("xmlns:dc", "http://purl.org/dc/elements/1.1/"));
+    }
+    if !source.contains("xmlns:xmp=") {
+        element.push_attribute(("xmlns:xmp", "http://ns.adobe.com/xap/1.0/"));
+    }
+    element
+}
+
+/// Gets the qualified name of an element, e.g. `dc:title`.
+///
+/// ### Parameters
+/// - `element`: The element.
+///
+/// ### Returns
+/// The qualified name of the element.
+fn get_name(element: &BytesStart) -> String {
+    String::from_utf8_lossy(element.name().as_ref()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    /// Creates an analysed video with a title, a description, keywords and a creation date.
+    fn make_video(path: &str) -> Video {
+        let mut video = Video {
+            path: path.to_string(),
+            creation_date: DateTime::from_timestamp(1_720_000_000, 0).unwrap(),
+            ..Default::default()
+        };
+        video.resume.title = "Sunset at the beach".to_string();
+        video.resume.description = "The sun sets over the sea.".to_string();
+        video.resume.keywords = vec!["sunset".to_string(), "Beach".to_string()];
+        video
+    }
+
+    #[test]
+    fn merges_into_an_existing_sidecar() {
+        let source = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:tiff="http://ns.adobe.com/tiff/1.0/">
+   <dc:title><rdf:Alt><rdf:li xml:lang="x-default">Holidays</rdf:li></rdf:Alt></dc:title>
+   <dc:subject><rdf:Bag><rdf:li>beach</rdf:li><rdf:li>family</rdf:li></rdf:Bag></dc:subject>
+   <tiff:Make>Camera</tiff:Make>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+        let merged = merge_xmp(source, &make_video("/videos/VID_0001.mp4")).unwrap();
+        // The existing title and the other fields are kept
+        assert!(merged.contains(">Holidays<"));
+        assert!(!merged.contains("Sunset at the beach"));
+        assert!(merged.contains("<tiff:Make>Camera</tiff:Make>"));
+        // The missing fields are added, with the namespace not declared yet
+        assert!(merged.contains("The sun sets over the sea."));
+        assert!(merged.contains("<xmp:CreateDate>2024-07-03T09:46:40Z</xmp:CreateDate>"));
+        assert!(merged.contains(r#"xmlns:xmp="http://ns.adobe.com/xap/1.0/""#));
+        assert_eq!(merged.matches("xmlns:dc=").count(), 1);
+        // The keywords are merged in place, without duplicates, ignoring the case
+        assert_eq!(merged.matches("<dc:subject>").count(), 1);
+        assert!(merged.find("<dc:subject>").unwrap() < merged.find("<tiff:Make>").unwrap());
+        let existing = read_existing_fields(&merged).unwrap();
+        assert!(existing.title && existing.description && existing.create_date);
+        assert_eq!(existing.subjects, vec!["beach", "family", "sunset"]);
+    }
+
+    #[test]
+    fn reads_the_fields_written_as_attributes() {
+        let source = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmp:CreateDate="2020-01-01T00:00:00Z"/>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+        let merged = merge_xmp(source, &make_video("/videos/VID_0001.mp4")).unwrap();
+        assert!(!merged.contains("<xmp:CreateDate>"));
+        assert!(merged.contains("Sunset at the beach"));
+        assert!(merged.contains("</rdf:Description>"));
+        assert!(merge_xmp("<x:xmpmeta/>", &make_video("/videos/VID_0001.mp4")).is_err());
+    }
+
+    #[test]
+    fn escapes_the_special_characters() {
+        let mut video = make_video("/videos/VID_0001.mp4");
+        video.resume.title = "Fish & chips <by the sea>".to_string();
+        video.resume.keywords = vec!["fish & chips".to_string()];
+        let merged = merge_xmp(XMP_TEMPLATE, &video).unwrap();
+        assert!(merged.contains("Fish &amp; chips &lt;by the sea&gt;"));
+        assert!(!merged.contains("<by the sea>"));
+        // The escaped keyword is read back unescaped, so it isn't duplicated by a new export
+        let existing = read_existing_fields(&merged).unwrap();
+        assert!(existing.title);
+        assert_eq!(existing.subjects, vec!["fish & chips"]);
+        assert_eq!(merge_xmp(&merged, &video).unwrap(), merged);
+    }
+
+    #[test]
+    fn keeps_the_sidecar_unchanged_when_exported_again() {
+        let temp_folder = std::env::temp_dir().join(format!("aspargus-xmp-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&temp_folder);
+        fs::create_dir_all(&temp_folder).unwrap();
+        let video = make_video(temp_folder.join("VID_0001.mp4").to_str().unwrap());
+
+        let path = export_xmp_sidecar(&video).unwrap();
+        assert_eq!(path, format!("{}.xmp", video.path));
+        let first = fs::read_to_string(&path).unwrap();
+        export_xmp_sidecar(&video).unwrap();
+        let second = fs::read_to_string(&path).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(second.matches("<dc:title>").count(), 1);
+        assert_eq!(second.matches("<rdf:li>sunset</rdf:li>").count(), 1);
+
+        fs::remove_dir_all(&temp_folder).unwrap();
+    }
+}
@@ -1,11 +1,35 @@
 use clap::parser::ValuesRef;
 use clap::ArgMatches;
 use clap::{arg, command, value_parser, ArgAction, Command};
+use glob::{MatchOptions, Pattern};
 use log::LevelFilter;
 use simple_logger::SimpleLogger;
 use std::fs;
 use std::path::{Path, PathBuf};
-use aspargus::aspargus::Aspargus;
+use aspargus::aspargus::{Aspargus, AspargusError};
+
+/// The extensions of the files picked up as videos when scanning a folder.
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "3g2", "3gp", "avi", "dv", "flv", "insv", "m2t", "m2ts", "m2v", "m4v", "mkv", "mod", "mov",
+    "mp4", "mpeg", "mpg", "mts", "mxf", "ogv", "ts", "tod", "vob", "webm", "wmv",
+];
+
+/// Represents the options of a folder scan.
+/// ### Fields
+/// - `recursive`: Flag to scan the sub-folders.
+/// - `include`: The globs a file must match one of to be selected, all files being selected if empty.
+/// - `exclude`: The globs of the files to ignore.
+/// - `sniff`: Flag to check the content of the files without extension with FFprobe.
+/// - `start`: The name of the first file to select (alphabetically), None if we start from the beginning.
+/// - `end`: The name of the last file to select (alphabetically), None if we finish at the end.
+struct ScanOptions<'a> {
+    recursive: bool,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    sniff: bool,
+    start: Option<&'a str>,
+    end: Option<&'a str>,
+}
 
 /// Builds the args parsing.
 ///
@@ -25,6 +49,36 @@ fn make_args() -> Command {
             .required(false)
             .value_parser(value_parser!(PathBuf)),
         )
+        .arg(
+            arg!(
+                 --recursive "Also analyses the videos in the sub-folders of the folder"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --include <GLOB> "Only analyses the files of the folder matching this glob, can be repeated"
+            )
+            .required(false)
+            .action(ArgAction::Append)
+            .value_parser(|glob: &str| Pattern::new(glob).map_err(|error| error.to_string())),
+        )
+        .arg(
+            arg!(
+                --exclude <GLOB> "Ignores the files of the folder matching this glob, can be repeated"
+            )
+            .required(false)
+            .action(ArgAction::Append)
+            .value_parser(|glob: &str| Pattern::new(glob).map_err(|error| error.to_string())),
+        )
+        .arg(
+            arg!(
+                 --sniff "Checks with FFprobe if the files without extension of the folder are videos"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
         .arg(
             arg!(
                 -s --start <FILE> "The name of the first file to analyse (alphabetically)"
@@ -46,6 +100,20 @@ fn make_args() -> Command {
             .required(false)
             .value_parser(value_parser!(String)),
         )
+        .arg(
+            arg!(
+                --organise <TEMPLATE> "The template of the folder to move the videos to"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                 --copy "Copies the videos to the organised folders instead of moving them"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
         .arg(
             arg!(
                 -j --json <PATH> "The path of the JSON file to export the analysis result"
@@ -95,6 +163,36 @@ fn make_args() -> Command {
             .required(false)
             .value_parser(value_parser!(u16)),
         )
+        .arg(
+            arg!(
+                --cv_servers <URLS> "The comma separated computer vision servers, as URL:PORT, between which the videos are balanced"
+            )
+            .required(false)
+            .value_delimiter(',')
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --text_servers <URLS> "The comma separated text servers, as URL:PORT, between which the videos are balanced"
+            )
+            .required(false)
+            .value_delimiter(',')
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --dispatch <STRATEGY> "How the videos are dispatched between the servers: round_robin or least_busy"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --server_timeout <SECONDS> "The time after which a request is sent to another server when there are several servers, 0 for no timeout (default)"
+            )
+            .required(false)
+            .value_parser(value_parser!(u64)),
+        )
         .arg(
             arg!(
                  --two_steps "Runs the analysis in two steps, first running the CV model and then running text model to generate a resume"
@@ -102,13 +200,196 @@ fn make_args() -> Command {
             .required(false)
             .action(ArgAction::SetTrue), 
         )
+        .arg(
+            arg!(
+                --sampling <STRATEGY> "The frame sampling strategy: count:<N>, interval:<SECONDS> or scene:<THRESHOLD>"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --min_frames <N> "The minimum number of frames to extract with the interval and scene strategies"
+            )
+            .required(false)
+            .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(
+                --max_frames <N> "The maximum number of frames to extract with the interval and scene strategies"
+            )
+            .required(false)
+            .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(
+                --frames <MODE> "Where the extracted frames are held: disk or memory"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --captions <MODE> "Captions each frame with its time: off, timeline to export the captions, or story to also tell the story from them"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --contact_sheet <MODE> "Sends the frames to the computer vision model as a single grid labelled with their time: off, on, or preview to also save it next to the video"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --sanitise <PROFILE> "The sanitisation profile of the new file names: posix, windows or slug"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --transliterate <BOOL> "Transliterates the accented and non-Latin characters of the new file names to ASCII"
+            )
+            .required(false)
+            .value_parser(value_parser!(bool)),
+        )
+        .arg(
+            arg!(
+                --max_name_length <BYTES> "The maximum length of the new file names in bytes, extension included"
+            )
+            .required(false)
+            .value_parser(value_parser!(u16).range(32..)),
+        )
+        .arg(
+            arg!(
+                --transcription <BACKEND> "The speech-to-text backend to transcribe the audio: whisper:<MODEL_PATH>, command:<COMMAND> or none"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --faces <BACKEND> "The face detection backend to recognise the recurring people: builtin, plugin:<COMMAND> or none"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --concurrency <N> "The number of videos sent to the models at the same time"
+            )
+            .required(false)
+            .value_parser(value_parser!(u16).range(1..)),
+        )
+        .arg(
+            arg!(
+                --language <CODES> "The languages of the titles, descriptions and keywords for this run, e.g. fr or fr,de"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --json_language <CODE> "Only exports the titles, descriptions and keywords in this language in the JSON file"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --prompt_set <NAME> "The set of prompt templates, in the prompts folder of the work folder"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                --on_error <POLICY> "What to do when a video fails: fail_fast, skip or retry:<N>"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                 --write_metadata "Writes the title, description and keywords into the metadata of the video files"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                 --xmp "Exports the analysis result in an XMP sidecar next to each video"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --resume <RUN_ID> "Continues an interrupted run from its journal"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+        )
+        .arg(
+            arg!(
+                 --force "Analyses the videos again even if their result is already in the cache"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                 --keep_frames "Keeps the extracted frames in the temp folder of the run, for debugging"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                 --dry_run "Only shows the new names of the videos, without renaming them"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .subcommand(
+            Command::new("undo")
+                .about("Restores the original names of the videos renamed during a run")
+                .arg(
+                    arg!(<MANIFEST> "The path or the name of the undo manifest")
+                        .value_parser(value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new("prompt")
+                .about("Prints the prompts sent to the models for a video, without running the models")
+                .arg(
+                    arg!(<VIDEO> "The path of the video")
+                        .value_parser(value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new("faces")
+                .about("Lists the recurring people found in the videos, or names one of them")
+                .arg(
+                    arg!([ID] "The ID of the face cluster to name")
+                        .value_parser(value_parser!(u32)),
+                )
+                .arg(
+                    arg!([NAME] "The name of the person, e.g. Grandma")
+                        .requires("ID")
+                        .value_parser(value_parser!(String)),
+                ),
+        )
 }
 
 /// Gets the videos list argument.
 ///
 /// ### Return
 /// An Option with a list of videos paths.
-fn get_videos(matches: &ArgMatches) -> Option<ValuesRef<String>> {
+fn get_videos(matches: &ArgMatches) -> Option<ValuesRef<'_, String>> {
     matches.get_many::<String>("videos")
 }
 
@@ -164,6 +445,29 @@ fn get_rename_template(matches: &ArgMatches) -> Option<&str> {
     }
 }
 
+/// Gets the template for the folder organisation.
+///
+/// ### Return
+/// An Option with the template for the folder organisation.
+fn get_organise_template(matches: &ArgMatches) -> Option<&str> {
+    if let Some(organise_template) = matches.get_one::<String>("organise") {
+        log::debug!("Organising template: {}", organise_template);
+        Some(organise_template.as_str())
+    } else {
+        None
+    }
+}
+
+/// Gets the flag to copy the videos instead of moving them when organising them.
+///
+/// ### Return
+/// True if the videos must be copied.
+fn get_copy(matches: &ArgMatches) -> bool {
+    let copy = matches.get_flag("copy");
+    log::debug!("Copy: {}", copy);
+    copy
+}
+
 /// Gets the path of the json file to export the results to.
 ///
 /// ### Return
@@ -177,6 +481,62 @@ fn get_json_path(matches: &ArgMatches) -> Option<&str> {
     }
 }
 
+/// Gets the language of the resumes exported in the JSON file.
+///
+/// ### Return
+/// An Option with the code of the language, None to export all the languages.
+fn get_json_language(matches: &ArgMatches) -> Option<&str> {
+    if let Some(json_language) = matches.get_one::<String>("json_language") {
+        log::debug!("JSON language: {}", json_language);
+        Some(json_language.as_str())
+    } else {
+        None
+    }
+}
+
+/// Gets the flag to write the results into the metadata of the video files.
+///
+/// ### Return
+/// True if the results must be written into the video files.
+fn get_write_metadata(matches: &ArgMatches) -> bool {
+    let write_metadata = matches.get_flag("write_metadata");
+    log::debug!("Write metadata: {}", write_metadata);
+    write_metadata
+}
+
+/// Gets the flag to export the results in XMP sidecars.
+///
+/// ### Return
+/// True if the results must be exported in XMP sidecars.
+fn get_export_xmp(matches: &ArgMatches) -> bool {
+    let export_xmp = matches.get_flag("xmp");
+    log::debug!("Export XMP sidecars: {}", export_xmp);
+    export_xmp
+}
+
+/// Gets the flag to only show the new names of the videos.
+///
+/// ### Return
+/// True if the videos must not be renamed.
+fn get_dry_run(matches: &ArgMatches) -> bool {
+    let dry_run = matches.get_flag("dry_run");
+    log::debug!("Dry run: {}", dry_run);
+    dry_run
+}
+
+/// Gets the ID of the run to continue.
+///
+/// ### Return
+/// An Option with the ID of the run to continue.
+fn get_resume_run_id(matches: &ArgMatches) -> Option<&str> {
+    if let Some(run_id) = matches.get_one::<String>("resume") {
+        log::debug!("Run to continue: {}", run_id);
+        Some(run_id.as_str())
+    } else {
+        None
+    }
+}
+
 /// Sets the URL of the computer vision server.
 ///
 /// ### Parameters
@@ -225,6 +585,36 @@ fn set_text_server_port(aspargus: &mut Aspargus, matches: &ArgMatches) {
     };
 }
 
+/// Sets the pools of servers and how the requests are dispatched between them.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+///
+/// ### Errors
+/// Returns an error if a server or the dispatch strategy can't be parsed.
+fn set_server_pools(aspargus: &mut Aspargus, matches: &ArgMatches) -> Result<(), AspargusError> {
+    if let Some(cv_servers) = matches.get_many::<String>("cv_servers") {
+        let cv_servers: Vec<String> = cv_servers.filter(|server| !server.is_empty()).cloned().collect();
+        log::debug!("Computer vision servers: {:?}", cv_servers);
+        aspargus.set_computer_vision_servers(cv_servers)?;
+    };
+    if let Some(text_servers) = matches.get_many::<String>("text_servers") {
+        let text_servers: Vec<String> = text_servers.filter(|server| !server.is_empty()).cloned().collect();
+        log::debug!("Text servers: {:?}", text_servers);
+        aspargus.set_text_servers(text_servers)?;
+    };
+    if let Some(dispatch) = matches.get_one::<String>("dispatch") {
+        log::debug!("Dispatch strategy: {}", dispatch);
+        aspargus.set_dispatch(dispatch)?;
+    };
+    if let Some(server_timeout) = matches.get_one::<u64>("server_timeout") {
+        log::debug!("Server timeout: {}", server_timeout);
+        aspargus.set_server_timeout(server_timeout.to_owned());
+    };
+    Ok(())
+}
+
 /// Sets the name of the computer vision model.
 ///
 /// ### Parameters
@@ -260,32 +650,273 @@ fn set_two_steps(aspargus: &mut Aspargus, matches: &ArgMatches) {
     aspargus.set_two_steps(two_steps);
 }
 
+/// Sets the number of videos sent to the models at the same time.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+fn set_concurrency(aspargus: &mut Aspargus, matches: &ArgMatches) {
+    if let Some(concurrency) = matches.get_one::<u16>("concurrency") {
+        log::debug!("Concurrency: {}", concurrency);
+        aspargus.set_concurrency(usize::from(concurrency.to_owned()));
+    };
+}
+
+/// Sets the frame sampling strategy and its bounds.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+///
+/// ### Errors
+/// Returns an error if the sampling strategy can't be parsed.
+fn set_sampling(aspargus: &mut Aspargus, matches: &ArgMatches) -> Result<(), AspargusError> {
+    if let Some(sampling) = matches.get_one::<String>("sampling") {
+        log::debug!("Sampling strategy: {}", sampling);
+        aspargus.set_sampling(sampling)?;
+    };
+    if let Some(min_frames) = matches.get_one::<u32>("min_frames") {
+        log::debug!("Minimum frames: {}", min_frames);
+        aspargus.set_min_frames(min_frames.to_owned());
+    };
+    if let Some(max_frames) = matches.get_one::<u32>("max_frames") {
+        log::debug!("Maximum frames: {}", max_frames);
+        aspargus.set_max_frames(max_frames.to_owned());
+    };
+    Ok(())
+}
+
+/// Sets whether the frames are captioned one by one.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+///
+/// ### Errors
+/// Returns an error if the caption mode can't be parsed.
+fn set_captions(aspargus: &mut Aspargus, matches: &ArgMatches) -> Result<(), AspargusError> {
+    if let Some(captions) = matches.get_one::<String>("captions") {
+        log::debug!("Caption mode: {}", captions);
+        aspargus.set_captions(captions)?;
+    };
+    Ok(())
+}
+
+/// Sets whether the frames are sent to the computer vision model as a single contact sheet.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+///
+/// ### Errors
+/// Returns an error if the contact sheet mode can't be parsed.
+fn set_contact_sheet(aspargus: &mut Aspargus, matches: &ArgMatches) -> Result<(), AspargusError> {
+    if let Some(contact_sheet) = matches.get_one::<String>("contact_sheet") {
+        log::debug!("Contact sheet mode: {}", contact_sheet);
+        aspargus.set_contact_sheet(contact_sheet)?;
+    };
+    Ok(())
+}
+
+/// Sets the rules applied to the new file names.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+///
+/// ### Errors
+/// Returns an error if the sanitisation profile can't be parsed.
+fn set_file_name_rules(aspargus: &mut Aspargus, matches: &ArgMatches) -> Result<(), AspargusError> {
+    if let Some(sanitisation) = matches.get_one::<String>("sanitise") {
+        log::debug!("Sanitisation profile: {}", sanitisation);
+        aspargus.set_sanitisation(sanitisation)?;
+    };
+    if let Some(transliterate) = matches.get_one::<bool>("transliterate") {
+        log::debug!("Transliteration: {}", transliterate);
+        aspargus.set_transliterate(transliterate.to_owned());
+    };
+    if let Some(max_name_length) = matches.get_one::<u16>("max_name_length") {
+        log::debug!("Maximum file name length: {}", max_name_length);
+        aspargus.set_max_name_length(usize::from(max_name_length.to_owned()));
+    };
+    Ok(())
+}
+
+/// Sets what to do when a video fails a stage of the analysis.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+///
+/// ### Errors
+/// Returns an error if the failure policy can't be parsed.
+fn set_failure_policy(aspargus: &mut Aspargus, matches: &ArgMatches) -> Result<(), AspargusError> {
+    if let Some(failure_policy) = matches.get_one::<String>("on_error") {
+        log::debug!("Failure policy: {}", failure_policy);
+        aspargus.set_failure_policy(failure_policy)?;
+    };
+    Ok(())
+}
+
+/// Sets the set of prompt templates.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+///
+/// ### Errors
+/// Returns an error if the prompt set doesn't exist or is invalid.
+fn set_prompt_set(aspargus: &mut Aspargus, matches: &ArgMatches) -> Result<(), AspargusError> {
+    if let Some(prompt_set) = matches.get_one::<String>("prompt_set") {
+        log::debug!("Prompt set: {}", prompt_set);
+        aspargus.set_prompt_set(prompt_set)?;
+    };
+    Ok(())
+}
+
+/// Sets the languages of the resumes for this run.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+///
+/// ### Errors
+/// Returns an error if a language is unknown.
+fn set_languages(aspargus: &mut Aspargus, matches: &ArgMatches) -> Result<(), AspargusError> {
+    if let Some(languages) = matches.get_one::<String>("language") {
+        log::debug!("Languages: {}", languages);
+        aspargus.set_languages(languages)?;
+    };
+    Ok(())
+}
+
+/// Sets where the extracted frames are held.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+///
+/// ### Errors
+/// Returns an error if the frame storage can't be parsed.
+fn set_frame_storage(aspargus: &mut Aspargus, matches: &ArgMatches) -> Result<(), AspargusError> {
+    if let Some(frame_storage) = matches.get_one::<String>("frames") {
+        log::debug!("Frame storage: {}", frame_storage);
+        aspargus.set_frame_storage(frame_storage)?;
+    };
+    Ok(())
+}
+
+/// Sets the speech-to-text backend.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+///
+/// ### Errors
+/// Returns an error if the backend can't be parsed.
+fn set_transcription(aspargus: &mut Aspargus, matches: &ArgMatches) -> Result<(), AspargusError> {
+    if let Some(transcription) = matches.get_one::<String>("transcription") {
+        log::debug!("Transcription backend: {}", transcription);
+        aspargus.set_transcription(transcription)?;
+    };
+    Ok(())
+}
+
+/// Sets the face detection backend.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+///
+/// ### Errors
+/// Returns an error if the backend can't be parsed.
+fn set_faces(aspargus: &mut Aspargus, matches: &ArgMatches) -> Result<(), AspargusError> {
+    if let Some(faces) = matches.get_one::<String>("faces") {
+        log::debug!("Face detection backend: {}", faces);
+        aspargus.set_faces(faces)?;
+    };
+    Ok(())
+}
+
+/// Sets the force flag.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+fn set_force(aspargus: &mut Aspargus, matches: &ArgMatches) {
+    let force = matches.get_flag("force");
+    log::debug!("Force analysis: {}", force);
+    aspargus.set_force(force);
+}
+
+/// Sets the keep frames flag.
+///
+/// ### Parameters
+/// - `aspargus`: The Aspargus instance.    
+/// - `matches`: The app's arguments.
+fn set_keep_frames(aspargus: &mut Aspargus, matches: &ArgMatches) {
+    let keep_frames = matches.get_flag("keep_frames");
+    log::debug!("Keep frames: {}", keep_frames);
+    aspargus.set_keep_frames(keep_frames);
+}
+
+/// Gets the options of the folder scan.
+///
+/// ### Parameters
+/// - `matches`: The app's arguments.
+///
+/// ### Return
+/// The options of the folder scan.
+fn get_scan_options(matches: &ArgMatches) -> ScanOptions<'_> {
+    let options = ScanOptions {
+        recursive: matches.get_flag("recursive"),
+        include: matches
+            .get_many::<Pattern>("include")
+            .map(|patterns| patterns.cloned().collect())
+            .unwrap_or_default(),
+        exclude: matches
+            .get_many::<Pattern>("exclude")
+            .map(|patterns| patterns.cloned().collect())
+            .unwrap_or_default(),
+        sniff: matches.get_flag("sniff"),
+        start: get_start_file(matches),
+        end: get_end_file(matches),
+    };
+    log::debug!(
+        "Scan options: recursive {}, include {:?}, exclude {:?}, sniff {}",
+        options.recursive,
+        options.include.iter().map(Pattern::as_str).collect::<Vec<_>>(),
+        options.exclude.iter().map(Pattern::as_str).collect::<Vec<_>>(),
+        options.sniff
+    );
+    options
+}
+
 /// Gets the list of video files based on the provided arguments.
 ///
 /// ### Parameters
 /// - `videos`: The list of video files to analyse (overrides the 'folder' parameter).    
 /// - `folder`: The path of the folder to analyse.
-/// - `start_file`: The name of the first file to analyse in the folder.
-/// - `end_file`: The name of the last file to analyse in the folder.
+/// - `options`: The options of the folder scan.
 fn get_videos_list(
     videos: Option<ValuesRef<String>>,
     folder: Option<&PathBuf>,
-    start_file: Option<&str>,
-    end_file: Option<&str>,
+    options: &ScanOptions,
 ) -> Option<Vec<String>> {
     if let Some(files) = videos {
-        if start_file.is_some() || end_file.is_some() || folder.is_some() {
+        if options.start.is_some() || options.end.is_some() || folder.is_some() {
             log::warn!("When a list of video files is given as argument, folder, start and end are not taken in account");
         }
         let the_files = files.map(|v| v.to_string()).collect::<Vec<_>>();
         log::debug!("Value for name: {:?}", the_files);
         Some(the_files)
-    } else if let Some(folder) = folder {
-        Some(filter_files_in_dir(
-            folder, start_file, end_file,
-        ))
     } else {
-        None
+        folder.map(|folder| {
+            let the_files = filter_files_in_dir(folder, options);
+            log::info!("{} video files found in {}", the_files.len(), folder.display());
+            log::debug!("Video files: {:?}", the_files);
+            the_files
+        })
     }
 }
 
@@ -305,122 +936,327 @@ async fn main() {
 
     let mut aspargus = Aspargus::new();
     let matches = make_args().get_matches();
+    if let Some(undo_matches) = matches.subcommand_matches("undo") {
+        let manifest = undo_matches.get_one::<String>("MANIFEST").unwrap();
+        if let Err(error) = aspargus.undo(manifest) {
+            log::error!("FATAL ERROR: {}", error);
+        }
+        return;
+    }
+    if let Some(faces_matches) = matches.subcommand_matches("faces") {
+        match faces_matches.get_one::<u32>("ID") {
+            Some(id) => {
+                let name = faces_matches.get_one::<String>("NAME").map(String::as_str).unwrap_or_default();
+                if let Err(error) = aspargus.name_face(*id, name) {
+                    log::error!("FATAL ERROR: {}", error);
+                }
+            }
+            None => aspargus.print_faces(),
+        }
+        return;
+    }
     let videos = get_videos(&matches);
     let folder = get_folder(&matches);
-    let start_file = get_start_file(&matches);
-    let end_file = get_end_file(&matches);
+    let scan_options = get_scan_options(&matches);
     let rename_template = get_rename_template(&matches);
+    let organise_template = get_organise_template(&matches);
+    let copy = get_copy(&matches);
     let json_path = get_json_path(&matches);
+    let json_language = get_json_language(&matches);
+    let write_metadata = get_write_metadata(&matches);
+    let export_xmp = get_export_xmp(&matches);
+    let dry_run = get_dry_run(&matches);
     set_computer_vision_server(&mut aspargus, &matches);
     set_computer_vision_server_port(&mut aspargus, &matches);
     set_computer_vision_model(&mut aspargus, &matches);
     set_text_server(&mut aspargus, &matches);
     set_text_server_port(&mut aspargus, &matches);
+    if let Err(error) = set_server_pools(&mut aspargus, &matches) {
+        log::error!("FATAL ERROR: {}", error);
+        return;
+    }
     set_text_model(&mut aspargus, &matches);
     set_two_steps(&mut aspargus, &matches);
-
-    let files = get_videos_list(videos, folder, start_file, end_file);
-    if (start_file.is_some() || end_file.is_some()) && folder.is_none() && files.is_none() {
-        log::error!(
-            "When using the start or end arguments, the folder argument must not be empty."
-        );
+    set_concurrency(&mut aspargus, &matches);
+    set_force(&mut aspargus, &matches);
+    set_keep_frames(&mut aspargus, &matches);
+    if let Err(error) = set_sampling(&mut aspargus, &matches) {
+        log::error!("FATAL ERROR: {}", error);
+        return;
+    }
+    if let Err(error) = set_frame_storage(&mut aspargus, &matches) {
+        log::error!("FATAL ERROR: {}", error);
+        return;
+    }
+    if let Err(error) = set_captions(&mut aspargus, &matches) {
+        log::error!("FATAL ERROR: {}", error);
+        return;
+    }
+    if let Err(error) = set_contact_sheet(&mut aspargus, &matches) {
+        log::error!("FATAL ERROR: {}", error);
+        return;
+    }
+    if let Err(error) = set_transcription(&mut aspargus, &matches) {
+        log::error!("FATAL ERROR: {}", error);
+        return;
+    }
+    if let Err(error) = set_faces(&mut aspargus, &matches) {
+        log::error!("FATAL ERROR: {}", error);
+        return;
+    }
+    if let Err(error) = set_file_name_rules(&mut aspargus, &matches) {
+        log::error!("FATAL ERROR: {}", error);
+        return;
+    }
+    if let Err(error) = set_failure_policy(&mut aspargus, &matches) {
+        log::error!("FATAL ERROR: {}", error);
+        return;
+    }
+    if let Err(error) = set_prompt_set(&mut aspargus, &matches) {
+        log::error!("FATAL ERROR: {}", error);
+        return;
+    }
+    if let Err(error) = set_languages(&mut aspargus, &matches) {
+        log::error!("FATAL ERROR: {}", error);
         return;
     }
-   
-    if files.is_none() {
-        log::error!("No video files to analyse, we're quitting.");
+    if let Some(prompt_matches) = matches.subcommand_matches("prompt") {
+        let video = prompt_matches.get_one::<String>("VIDEO").unwrap();
+        if let Err(error) = aspargus.print_prompts(video) {
+            log::error!("FATAL ERROR: {}", error);
+        }
         return;
     }
+    if let Some(rename_template) = rename_template {
+        if let Err(error) = Aspargus::check_rename_template(rename_template) {
+            log::error!("FATAL ERROR: {}", error);
+            return;
+        }
+    }
+    if let Some(organise_template) = organise_template {
+        if let Err(error) = Aspargus::check_organise_template(organise_template) {
+            log::error!("FATAL ERROR: {}", error);
+            return;
+        }
+    }
 
-    match aspargus.add_videos(files.unwrap_or_default()) {
-        Ok(_) => (),
-        Err(error) => { // Any error returned here means we stop the program
-            log::error!("FATAL ERROR: {}", error.to_string());
+    if let Some(run_id) = get_resume_run_id(&matches) {
+        if videos.is_some() || folder.is_some() {
+            log::warn!("When continuing a run, the videos are taken from its journal, the given videos and folder are not taken in account");
+        }
+        match aspargus.continue_run(run_id) {
+            Ok(_) => (),
+            Err(error) => {
+                log::error!("FATAL ERROR: {}", error);
+                return;
+            }
+        }
+    } else {
+        let files = get_videos_list(videos, folder, &scan_options);
+        if (scan_options.start.is_some() || scan_options.end.is_some())
+            && folder.is_none()
+            && files.is_none()
+        {
+            log::error!(
+                "When using the start or end arguments, the folder argument must not be empty."
+            );
             return;
-        },
+        }
+
+        if files.is_none() {
+            log::error!("No video files to analyse, we're quitting.");
+            return;
+        }
+
+        match aspargus.start_run() {
+            Ok(_) => (),
+            Err(error) => {
+                log::error!("FATAL ERROR: {}", error);
+                return;
+            }
+        }
+
+        match aspargus.add_videos(files.unwrap_or_default()) {
+            Ok(_) => (),
+            Err(error) => { // Any error returned here means we stop the program
+                log::error!("FATAL ERROR: {}", error);
+                return;
+            },
+        }
     }
 
     match aspargus.extract_frames() {
         Ok(_) => (),
         Err(error) => {
-            log::error!("FATAL ERROR: {}", error.to_string());
+            log::error!("FATAL ERROR: {}", error);
             return;
         },
     }
+
+    aspargus.detect_faces();
         
-    if aspargus.is_two_steps() {
-        aspargus.run_computer_vision_model().await;
-        aspargus.run_resume_model().await;
+    match aspargus.transcribe_audio() {
+        Ok(_) => (),
+        Err(error) => {
+            log::error!("FATAL ERROR: {}", error);
+            return;
+        },
+    }
+
+    if let Err(error) = aspargus.check_servers().await {
+        log::error!("FATAL ERROR: {}", error);
+        return;
+    }
+
+    let result = if aspargus.is_two_steps() {
+        aspargus.run_pipelined_models().await
     } else {
-        aspargus.run_only_computer_vision_model().await;
+        aspargus.run_only_computer_vision_model().await
+    };
+    aspargus.remove_run_temp_folder();
+    if let Err(error) = result {
+        log::error!("FATAL ERROR: {}", error);
+        return;
+    }
+
+    if write_metadata {
+        if let Err(error) = aspargus.write_metadata() {
+            log::error!("Error while writing the metadata: {}", error);
+        }
+    }
+
+    if let Some(rename_template) = rename_template {
+        if let Err(error) = aspargus.rename_videos(rename_template, dry_run) {
+            log::error!("Error while renaming the videos: {}", error);
+        }
+    }
+
+    if let Some(organise_template) = organise_template {
+        if let Err(error) = aspargus.organise_videos(organise_template, copy, dry_run) {
+            log::error!("Error while organising the videos: {}", error);
+        }
     }
 
-    if rename_template.is_some() {
-        aspargus.rename_videos(rename_template.unwrap());
+    if export_xmp {
+        aspargus.export_to_xmp();
     }
 
-    if json_path.is_some() {
-        match aspargus.export_to_json(json_path.unwrap()) {
+    aspargus.export_contact_sheets();
+
+    if let Some(json_path) = json_path {
+        match aspargus.export_to_json(json_path, json_language) {
             Ok(_) => (),
             Err(error) => log::error!("Error while exporting the JSON file: {}", error),
         };
     }
+
+    aspargus.log_summary();
 }
 
 
 
-/// Filters the content of a directory based on a start and end file namen (alphabetically).
+/// Filters the content of a directory based on the scan options, and sorts the selected files alphabetically.
 ///
 /// ### Parameters
 /// - `dir_path`: The path of the directory.
-/// - `file_name_start`: The first file to be selected, None if we start from the beginning.
-/// - `file_name_end`: TThe last file to be selected, None if we finish at the end.
+/// - `options`: The options of the scan.
 ///
 /// ### Returns
 /// A list of file paths. If the directory doesn't exist or if it is empty, an empty list is returned.
 ///
-fn filter_files_in_dir(
-    dir_path: &PathBuf,
-    file_name_start: Option<&str>,
-    file_name_end: Option<&str>,
-) -> Vec<String> {
+fn filter_files_in_dir(dir_path: &PathBuf, options: &ScanOptions) -> Vec<String> {
     let path = Path::new(dir_path);
     let mut filtered_paths = Vec::new();
 
     // Check if the path exists and is a directory
     if path.exists() && path.is_dir() {
-        if let Ok(entries) = fs::read_dir(path) {
-            for entry in entries.filter_map(Result::ok) {
-                let path = entry.path();
-                if path.is_file() {
-                    let file_name: Option<String> = match path_to_string(&path) {
-                        Ok(the_file_name) => Some(the_file_name),
-                        Err(_) => {
-                            log::error!("This {:?} will be ignored due to an error", path);
-                            None
-                        }
-                    };
-                    if file_name.is_some() {
-                        let file_name = file_name.unwrap();
-                        let file_name = file_name.as_str();
-                        // Check if the file name matches the start and end constraints
-                        let matches_start = file_name_start
-                            .map(|start| file_name >= start)
-                            .unwrap_or(true); // If no start constraint, always true
-
-                        let matches_end = file_name_end.map(|end| file_name <= end).unwrap_or(true); // If no end constraint, always true
-
-                        if matches_start && matches_end {
-                            if let Some(path_str) = path.to_str() {
-                                filtered_paths.push(path_str.to_string());
-                            }
-                        }
-                    }
-                }
+        scan_folder(path, path, options, &mut filtered_paths);
+    }
+    filtered_paths.sort();
+    filtered_paths
+}
+
+/// Selects the video files of a folder, and of its sub-folders if the scan is recursive. Hidden files and folders are ignored.
+///
+/// ### Parameters
+/// - `root`: The folder being scanned, the globs being matched against the paths relative to it.
+/// - `folder`: The current folder.
+/// - `options`: The options of the scan.
+/// - `filtered_paths`: The selected file paths, completed with the files of the current folder.
+fn scan_folder(root: &Path, folder: &Path, options: &ScanOptions, filtered_paths: &mut Vec<String>) {
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(error) => {
+            log::warn!("Could not read the folder {}: {}", folder.display(), error);
+            return;
+        }
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let file_name = match path_to_string(&path) {
+            Ok(the_file_name) => the_file_name,
+            Err(_) => {
+                log::error!("This {:?} will be ignored due to an error", path);
+                continue;
+            }
+        };
+        // Hidden files include the .DS_Store files and the ._ resource forks of macOS
+        if file_name.starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            // Symbolic links to folders are not followed, to avoid scanning a folder twice or looping
+            if options.recursive && !path.is_symlink() {
+                scan_folder(root, &path, options, filtered_paths);
+            }
+            continue;
+        }
+        if !path.is_file() {
+            continue;
+        }
+        let relative_path = path.strip_prefix(root).unwrap_or(&path);
+        let glob_options = MatchOptions {
+            case_sensitive: false,
+            ..Default::default()
+        };
+        let matches_glob = |pattern: &Pattern| pattern.matches_path_with(relative_path, glob_options);
+        if !options.include.is_empty() && !options.include.iter().any(matches_glob) {
+            continue;
+        }
+        if options.exclude.iter().any(matches_glob) {
+            continue;
+        }
+        // Check if the file name matches the start and end constraints
+        let file_name = file_name.as_str();
+        let matches_start = options.start.map(|start| file_name >= start).unwrap_or(true); // If no start constraint, always true
+        let matches_end = options.end.map(|end| file_name <= end).unwrap_or(true); // If no end constraint, always true
+        if !matches_start || !matches_end {
+            continue;
+        }
+        if let Some(path_str) = path.to_str() {
+            if is_video_file(&path, path_str, options.sniff) {
+                filtered_paths.push(path_str.to_string());
+            } else {
+                log::debug!("{} is not a video, it is ignored", path_str);
             }
         }
     }
-    filtered_paths
+}
+
+/// Checks if a file is a video from its extension, or from its content if it has no extension.
+///
+/// ### Parameters
+/// - `path`: The path of the file.
+/// - `path_str`: The path of the file as a string.
+/// - `sniff`: Flag to check the content of the files without extension.
+///
+/// ### Returns
+/// True if the file is a video.
+fn is_video_file(path: &Path, path_str: &str, sniff: bool) -> bool {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => VIDEO_EXTENSIONS.contains(&extension.to_lowercase().as_str()),
+        None => sniff && Aspargus::is_video_file(path_str),
+    }
 }
 
 /// Converts a PathBuf to a String, returning an error if the conversion fails.